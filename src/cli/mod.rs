@@ -0,0 +1,337 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use clap::{Args, Parser, Subcommand};
+use crate::services::archive::ArchiveFormat;
+use crate::services::changelog::ChangelogFormat;
+use crate::services::concurrency::Concurrency;
+use crate::services::filter::FilterMode;
+use crate::services::query::QueryFormat;
+use crate::services::scheduler::TableOrder;
+use crate::services::store::StoreMode;
+use crate::utils::{ByteRate, Compression, TimeWindow};
+
+#[derive(Debug, Parser)]
+#[command(version, about = "wago.tools DB2 csv exporter")]
+pub struct Cli {
+    /// TOML configuration file, `wago-db2.toml` in the working directory by default
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Root directory of the `<build>/<locale>/<table>.csv` tree
+    #[arg(long, global = true)]
+    pub output_dir: Option<PathBuf>,
+
+    /// Directory of WoWDBDefs `.dbd` files describing the tables
+    #[arg(long, global = true)]
+    pub definitions: Option<PathBuf>,
+
+    /// More log output, -vv for all of it
+    #[arg(short, long, global = true, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    pub verbose: u8,
+
+    /// Less log output, -qq for errors only
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub quiet: u8,
+
+    /// Also log to this file, rotated daily (see the `[log]` section)
+    #[arg(long, global = true)]
+    pub log_file: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Download tables, prompting for builds and locales that are not given
+    Download(DownloadArgs),
+    /// Summarize the changes of every table between two downloaded builds
+    Changelog(ChangelogArgs),
+    /// Infer and store table schemas of downloaded builds, flagging changes and malformed rows
+    Schema(SchemaArgs),
+    /// Show the definition of a table for a build, or list the locale-dependent tables
+    Definitions(DefinitionsArgs),
+    /// Filter the rows of downloaded tables
+    Filter(FilterArgs),
+    /// Look up rows of a downloaded table
+    Query(QueryArgs),
+    /// Build or refresh the full-text search index of downloaded builds
+    Index(IndexArgs),
+    /// Find a phrase in the string columns of every indexed table and locale
+    Search(SearchArgs),
+    /// Report missing and untranslated strings of each locale
+    #[command(name = "l10n-report")]
+    L10nReport(L10nArgs),
+    /// Remove objects of the shared store no build references anymore
+    Gc(GcArgs),
+    /// Package a downloaded build into a .zip or .tar.zst archive with a manifest
+    Archive(ArchiveArgs),
+    /// Extract an archive into the output directory and verify it
+    Extract(ExtractArgs),
+    /// Check the files of an archive against its manifest
+    Verify(VerifyArgs),
+    /// Serve the downloaded tables over HTTP with the wago.tools URL scheme
+    Serve(ServeArgs),
+    /// Serve tables like `serve`, fetching and caching the missing ones from the source
+    Proxy(ProxyArgs),
+    /// Poll the source and download new builds as they are released
+    Watch(WatchArgs),
+}
+
+#[derive(Debug, Default, Args)]
+pub struct DownloadArgs {
+    /// Builds to download, e.g. 11.0.5.57212
+    #[arg(long = "build")]
+    pub builds: Vec<String>,
+    /// Locales to download, e.g. enUS
+    #[arg(long = "locale")]
+    pub locales: Vec<String>,
+    /// Tables to download, all tables by default
+    #[arg(long = "table")]
+    pub tables: Vec<String>,
+    /// How many levels of referenced tables are added to the requested tables
+    #[arg(long)]
+    pub depth: Option<usize>,
+    /// Keep only the rows matching this expression, e.g. "ID in 1000..2000"
+    #[arg(long)]
+    pub filter: Option<String>,
+    #[arg(long, value_enum, default_value = "alongside")]
+    pub filter_mode: FilterMode,
+    /// Store identical files once across builds, see `gc`
+    #[arg(long, value_enum, default_value = "plain")]
+    pub store: StoreMode,
+    /// Compress tables on disk as `.csv.zst` or `.csv.gz` while they download
+    #[arg(long, value_enum, default_value = "none")]
+    pub compress: Compression,
+    /// Downloads in flight, or `auto` to adapt them to the server
+    #[arg(long)]
+    pub concurrency: Option<Concurrency>,
+    /// Bytes per second of all the downloads together, e.g. 500K or 2M
+    #[arg(long)]
+    pub limit_rate: Option<ByteRate>,
+    /// Only send requests during these local times of day, e.g. 22:00-06:00
+    #[arg(long = "window", value_delimiter = ',')]
+    pub windows: Vec<TimeWindow>,
+    /// Order in which the tables are downloaded
+    #[arg(long, value_enum, default_value = "name")]
+    pub order: TableOrder,
+    /// Locale downloaded before the others, e.g. enUS
+    #[arg(long)]
+    pub priority_locale: Option<String>,
+    /// Package each build into `<build>.zip` or `<build>.tar.zst` once downloaded
+    #[arg(long, value_enum)]
+    pub archive: Option<ArchiveFormat>,
+    /// Do not ask for confirmation
+    #[arg(long, short)]
+    pub yes: bool,
+    /// Continue the interrupted or failed download of the output directory
+    #[arg(long, conflicts_with_all = ["builds", "locales", "tables"])]
+    pub resume: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ChangelogArgs {
+    /// Older build, e.g. 11.0.5.57171
+    pub old_build: String,
+    /// Newer build, e.g. 11.0.5.57212
+    pub new_build: String,
+    #[arg(long, default_value = "enUS")]
+    pub locale: String,
+    #[arg(long, value_enum, default_value = "markdown")]
+    pub format: ChangelogFormat,
+    /// Number of modified IDs listed per table
+    #[arg(long, default_value_t = 10)]
+    pub top: usize,
+    /// Write the changelog to a file instead of stdout
+    #[arg(long)]
+    pub out: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct SchemaArgs {
+    /// Builds to check, e.g. 11.0.5.57212
+    #[arg(required = true)]
+    pub builds: Vec<String>,
+    /// Locales to check, all downloaded locales by default
+    #[arg(long = "locale")]
+    pub locales: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct DefinitionsArgs {
+    /// Build to match the definitions against, e.g. 11.0.5.57212
+    pub build: String,
+    /// Table to describe
+    pub table: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct FilterArgs {
+    /// Filter expression, e.g. "ID in 1000..2000 and ExpansionID = 10"
+    pub expression: String,
+    #[arg(long = "build", required = true)]
+    pub builds: Vec<String>,
+    /// Locales to filter, all downloaded locales by default
+    #[arg(long = "locale")]
+    pub locales: Vec<String>,
+    /// Tables to filter, all downloaded tables having the filtered columns by default
+    #[arg(long = "table")]
+    pub tables: Vec<String>,
+    #[arg(long, value_enum, default_value = "alongside")]
+    pub mode: FilterMode,
+}
+
+#[derive(Debug, Args)]
+pub struct QueryArgs {
+    pub table: String,
+    /// Build to query, the newest downloaded build by default
+    #[arg(long)]
+    pub build: Option<String>,
+    #[arg(long, default_value = "enUS")]
+    pub locale: String,
+    /// Rows with this ID
+    #[arg(long = "id")]
+    pub ids: Vec<String>,
+    /// Rows where a column equals a value, e.g. SpellID=133
+    #[arg(long = "where")]
+    pub equals: Vec<String>,
+    /// Rows with a string column containing this text, case-insensitive
+    #[arg(long)]
+    pub contains: Option<String>,
+    /// Join another table, as Table[:LocalColumn[=OtherColumn]]
+    #[arg(long)]
+    pub join: Option<String>,
+    /// Columns to print, joined columns being named Table.Column
+    #[arg(long, value_delimiter = ',')]
+    pub columns: Vec<String>,
+    /// Maximum number of rows printed, 0 for all
+    #[arg(long, default_value_t = 50)]
+    pub limit: usize,
+    #[arg(long, value_enum, default_value = "table")]
+    pub format: QueryFormat,
+}
+
+#[derive(Debug, Args)]
+pub struct IndexArgs {
+    /// Builds to index, every downloaded build by default
+    pub builds: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct SearchArgs {
+    pub phrase: String,
+    /// Build to search, the newest indexed build by default
+    #[arg(long)]
+    pub build: Option<String>,
+    /// Only search these locales
+    #[arg(long = "locale")]
+    pub locales: Vec<String>,
+    /// Only search these tables
+    #[arg(long = "table")]
+    pub tables: Vec<String>,
+    /// Maximum number of hits, 0 for all
+    #[arg(long, default_value_t = 100)]
+    pub limit: usize,
+    #[arg(long, value_enum, default_value = "table")]
+    pub format: QueryFormat,
+}
+
+#[derive(Debug, Args)]
+pub struct L10nArgs {
+    pub build: String,
+    /// Locale the others are compared with
+    #[arg(long, default_value = "enUS")]
+    pub reference: String,
+    /// Locales to check, all downloaded locales by default
+    #[arg(long = "locale")]
+    pub locales: Vec<String>,
+    /// Tables to check, every table with localized strings by default
+    #[arg(long = "table")]
+    pub tables: Vec<String>,
+    /// Write the per-table and per-locale completion to a CSV file
+    #[arg(long)]
+    pub out: Option<PathBuf>,
+    /// Write every missing, empty or untranslated string to a CSV file
+    #[arg(long)]
+    pub details: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct GcArgs {
+    /// Only report what would be removed
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ArchiveArgs {
+    pub build: String,
+    /// Defaults to the extension of `--out`, zip otherwise
+    #[arg(long, value_enum)]
+    pub format: Option<ArchiveFormat>,
+    /// Defaults to `<output-dir>/<build>.<format>`
+    #[arg(long)]
+    pub out: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct ExtractArgs {
+    pub archive: PathBuf,
+    /// Defaults to the output directory
+    #[arg(long)]
+    pub to: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+    pub archive: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    pub listen: SocketAddr,
+}
+
+#[derive(Debug, Args)]
+pub struct ProxyArgs {
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    pub listen: SocketAddr,
+    /// Storage compression of the cached tables
+    #[arg(long, value_enum, default_value = "none")]
+    pub compress: Compression,
+}
+
+/// Overrides the `[watch]` section of the configuration.
+#[derive(Debug, Args)]
+pub struct WatchArgs {
+    /// Seconds between two polls of the build list
+    #[arg(long)]
+    pub interval: Option<u64>,
+    /// Products to watch, e.g. wow or wowt
+    #[arg(long = "product")]
+    pub products: Vec<String>,
+    /// Newest builds of each product to keep downloaded
+    #[arg(long)]
+    pub latest: Option<usize>,
+    #[arg(long = "locale")]
+    pub locales: Vec<String>,
+    #[arg(long = "table")]
+    pub tables: Vec<String>,
+    /// Shell command run after each downloaded build, with WAGO_DB2_BUILD,
+    /// WAGO_DB2_BUILD_DIR and WAGO_DB2_OUTPUT_DIR set
+    #[arg(long)]
+    pub hook: Option<String>,
+    #[arg(long, value_enum, default_value = "none")]
+    pub compress: Compression,
+    /// Bytes per second of all the downloads together. Changing `limit_rate`
+    /// in the configuration file applies while watching
+    #[arg(long)]
+    pub limit_rate: Option<ByteRate>,
+    /// Serve Prometheus metrics of the downloads at http://<address>/metrics
+    #[arg(long)]
+    pub metrics_listen: Option<SocketAddr>,
+    /// Poll once and exit
+    #[arg(long)]
+    pub once: bool,
+}
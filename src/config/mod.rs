@@ -1,18 +1,190 @@
-#[derive(Debug)]
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use crate::services::hooks::HooksConfig;
+use crate::services::source::SourceConfig;
+use crate::services::watch::WatchConfig;
+use crate::utils::{ByteRate, LogConfig, TimeWindow};
+
+/// Read from the working directory when `--config` is not given.
+pub const DEFAULT_CONFIG_FILE: &str = "wago-db2.toml";
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct AppConfig {
     pub base_url: String,
+    pub output_dir: PathBuf,
+    pub definitions_dir: Option<PathBuf>,
+    pub dependency_depth: usize,
     pub requests_per_minute: u32,
     pub max_retries: u32,
     pub retry_delay_secs: u64,
+    /// Downloads in flight, where adaptive concurrency starts from
+    pub concurrent_downloads: usize,
+    /// Adapt the number of downloads to the latency, throughput and errors of the server
+    pub adaptive_concurrency: bool,
+    pub min_concurrent_downloads: usize,
+    pub max_concurrent_downloads: usize,
+    /// Bytes per second of all the downloads together, e.g. "2M"
+    pub limit_rate: Option<ByteRate>,
+    /// Local times of day downloads may send requests in, e.g. ["22:00-06:00"]
+    pub download_windows: Vec<TimeWindow>,
+    /// Time given to the downloads in progress to complete after Ctrl-C
+    pub shutdown_timeout_secs: u64,
+    pub http: HttpConfig,
+    pub source: SourceConfig,
+    pub watch: WatchConfig,
+    pub hooks: HooksConfig,
+    pub log: LogConfig,
+}
+
+/// `[http]` section, used to build the client of every request.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    pub user_agent: String,
+    /// `http://`, `https://`, `socks5://` or `socks5h://` URL
+    pub proxy: Option<String>,
+    /// Sent with every request, e.g. an `Authorization` token for a private mirror
+    pub headers: BTreeMap<String, String>,
+    pub connect_timeout_secs: u64,
+    /// Maximum time between two reads of a response
+    pub read_timeout_secs: u64,
+    /// Maximum time for a whole request, none by default as large tables take a while
+    pub request_timeout_secs: Option<u64>,
+    /// PEM files of additional root certificates
+    pub ca_certificates: Vec<PathBuf>,
 }
 
 impl AppConfig {
     pub fn new() -> Self {
         Self {
             base_url: "https://wago.tools/db2".to_string(),
+            output_dir: PathBuf::from("."),
+            definitions_dir: None,
+            dependency_depth: 1,
             requests_per_minute: 100,
             max_retries: 3,
             retry_delay_secs: 5,
+            concurrent_downloads: 4,
+            adaptive_concurrency: false,
+            min_concurrent_downloads: 1,
+            max_concurrent_downloads: 16,
+            limit_rate: None,
+            download_windows: Vec::new(),
+            shutdown_timeout_secs: 10,
+            http: HttpConfig::default(),
+            source: SourceConfig::default(),
+            watch: WatchConfig::default(),
+            hooks: HooksConfig::default(),
+            log: LogConfig::default(),
+        }
+    }
+
+    /// `path`, or `wago-db2.toml` when it exists.
+    pub fn resolve_path(path: Option<&Path>) -> Option<PathBuf> {
+        match path {
+            Some(path) => Some(path.to_path_buf()),
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => Some(PathBuf::from(DEFAULT_CONFIG_FILE)),
+            None => None,
+        }
+    }
+
+    /// Loads `path`, or `wago-db2.toml` when it exists, on top of the defaults.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let Some(path) = Self::resolve_path(path) else {
+            return Ok(Self::new());
+        };
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Unable to read {}", path.display()))?;
+        toml::from_str(&content)
+            .with_context(|| format!("Invalid configuration {}", path.display()))
+    }
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            user_agent: concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")).to_string(),
+            proxy: None,
+            headers: BTreeMap::new(),
+            connect_timeout_secs: 15,
+            read_timeout_secs: 60,
+            request_timeout_secs: None,
+            ca_certificates: Vec::new(),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_load_config_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        std::fs::write(&path, r#"
+base_url = "http://mirror.lan:8080/db2"
+requests_per_minute = 600
+limit_rate = "2M"
+download_windows = ["22:00-06:00"]
+adaptive_concurrency = true
+max_concurrent_downloads = 8
+
+[http]
+user_agent = "db2-team/1.0"
+proxy = "socks5h://127.0.0.1:9050"
+request_timeout_secs = 300
+
+[http.headers]
+Authorization = "Bearer secret"
+
+[watch]
+products = ["wowt"]
+hook = "./import.sh"
+
+[hooks]
+webhook = "http://ci.lan/db2"
+
+[log]
+file = "logs/wago-db2.log"
+"#).unwrap();
+
+        let config = AppConfig::load(Some(&path)).unwrap();
+        assert_eq!(config.base_url, "http://mirror.lan:8080/db2");
+        assert_eq!(config.requests_per_minute, 600);
+        assert_eq!(config.max_retries, 3);
+        assert!(config.adaptive_concurrency);
+        assert_eq!(config.limit_rate, Some(ByteRate(2 * 1024 * 1024)));
+        assert_eq!(config.download_windows, vec!["22:00-06:00".parse().unwrap()]);
+        assert_eq!((config.concurrent_downloads, config.max_concurrent_downloads), (4, 8));
+        assert_eq!(config.http.user_agent, "db2-team/1.0");
+        assert_eq!(config.http.headers["Authorization"], "Bearer secret");
+        assert_eq!(config.http.request_timeout_secs, Some(300));
+        assert_eq!(config.http.connect_timeout_secs, 15);
+        assert_eq!(config.watch.products, vec!["wowt"]);
+        assert_eq!(config.log.file, Some(PathBuf::from("logs/wago-db2.log")));
+        assert_eq!(config.watch.locales, vec!["enUS"]);
+        assert_eq!(config.watch.hook.as_deref(), Some("./import.sh"));
+        assert_eq!(config.hooks.webhook.as_deref(), Some("http://ci.lan/db2"));
+        assert!(config.hooks.after_build.is_none());
+    }
+
+    #[test]
+    fn test_invalid_config_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        std::fs::write(&path, "[http]\nuser_agnet = \"typo\"\n").unwrap();
+        assert!(AppConfig::load(Some(&path)).is_err());
+        assert!(AppConfig::load(Some(&temp_dir.path().join("missing.toml"))).is_err());
+    }
+}
@@ -1,4 +1,7 @@
-#[derive(Debug, Clone)]
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Build {
     version: String,
     build_number: u32,
@@ -12,11 +15,53 @@ impl Build {
         }
     }
 
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn build_number(&self) -> u32 {
+        self.build_number
+    }
+
+    /// Numeric parts of the version, e.g. `[11, 0, 5]` for 11.0.5.
+    pub fn version_parts(&self) -> Vec<u32> {
+        self.version.split('.').map(|p| p.parse().unwrap_or(0)).collect()
+    }
+
     pub fn format_full_version(&self) -> String {
         format!("{}.{}", self.version, self.build_number)
     }
 }
 
+impl FromStr for Build {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.trim().split('.').collect();
+        if parts.len() != 4 {
+            return Err(anyhow::anyhow!("Invalid build '{}', expected e.g. 11.0.5.57212", s));
+        }
+        let build_number = parts[3]
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid build number in '{}'", s))?;
+        Ok(Self::new(&parts[..3].join("."), build_number))
+    }
+}
+
+impl Ord for Build {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.version_parts()
+            .cmp(&other.version_parts())
+            .then(self.build_number.cmp(&other.build_number))
+    }
+}
+
+impl PartialOrd for Build {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl std::fmt::Display for Build {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.format_full_version())
@@ -0,0 +1,65 @@
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use crate::cli::{ArchiveArgs, ExtractArgs, VerifyArgs};
+use crate::entities::Build;
+use crate::services::archive::{create_archive, extract_archive, verify_archive, ArchiveFormat, ArchiveManifest, VerifyReport};
+
+pub fn archive_path(output_dir: &Path, build: &Build, format: ArchiveFormat) -> PathBuf {
+    output_dir.join(format!("{}.{}", build.format_full_version(), format.extension()))
+}
+
+pub fn archive_build(output_dir: &Path, build: &Build, format: ArchiveFormat, path: &Path) -> Result<()> {
+    let manifest = create_archive(output_dir, &build.format_full_version(), path, format)?;
+    println!(
+        "📦 {}: {} files ({} tables, {} locales) archived to {}",
+        build, manifest.files.len(), manifest.tables.len(), manifest.locales.len(), path.display()
+    );
+    Ok(())
+}
+
+pub fn handle_archive(output_dir: &Path, args: &ArchiveArgs) -> Result<()> {
+    let build: Build = args.build.parse()?;
+    let format = match (args.format, &args.out) {
+        (Some(format), _) => format,
+        (None, Some(out)) => ArchiveFormat::from_path(out)?,
+        (None, None) => ArchiveFormat::Zip,
+    };
+    let path = args.out.clone().unwrap_or_else(|| archive_path(output_dir, &build, format));
+    archive_build(output_dir, &build, format, &path)
+}
+
+fn print_report(manifest: &ArchiveManifest, report: &VerifyReport) -> Result<()> {
+    println!(
+        "Build {} downloaded at {}, archived at {}",
+        manifest.build, manifest.downloaded_at, manifest.archived_at
+    );
+    for (label, files) in [
+        ("Hash mismatch", &report.mismatched),
+        ("Missing", &report.missing),
+        ("Not in manifest", &report.unexpected),
+    ] {
+        for file in files {
+            println!("  ❌ {}: {}", label, file);
+        }
+    }
+    if !report.is_ok() {
+        return Err(anyhow::anyhow!(
+            "{} files failed verification",
+            report.mismatched.len() + report.missing.len() + report.unexpected.len()
+        ));
+    }
+    println!("✅ {} files verified", report.verified);
+    Ok(())
+}
+
+pub fn handle_extract(output_dir: &Path, args: &ExtractArgs) -> Result<()> {
+    let dest = args.to.as_deref().unwrap_or(output_dir);
+    let (manifest, report) = extract_archive(&args.archive, dest)?;
+    println!("Extracted {} to {}", args.archive.display(), dest.join(&manifest.build).display());
+    print_report(&manifest, &report)
+}
+
+pub fn handle_verify(args: &VerifyArgs) -> Result<()> {
+    let (manifest, report) = verify_archive(&args.archive)?;
+    print_report(&manifest, &report)
+}
@@ -1,8 +1,27 @@
 use anyhow::Result;
 use dialoguer::MultiSelect;
+use crate::data::builds::AVAILABLE_BUILDS;
 use crate::entities::Build;
+use crate::services::source::DataSource;
 
-pub fn handle_build_selection(available_builds: &[&str]) -> Result<Vec<Build>> {
+const MAX_LISTED_BUILDS: usize = 25;
+
+/// Newest builds of the source, the bundled list when it cannot tell.
+pub async fn available_builds(source: &dyn DataSource) -> Vec<String> {
+    match source.list_builds().await {
+        Ok(builds) if !builds.is_empty() => builds.iter()
+            .take(MAX_LISTED_BUILDS)
+            .map(|b| b.to_string())
+            .collect(),
+        Ok(_) => AVAILABLE_BUILDS.iter().map(|b| b.to_string()).collect(),
+        Err(e) => {
+            tracing::warn!("Unable to list the builds of {}: {}", source.describe(), e);
+            AVAILABLE_BUILDS.iter().map(|b| b.to_string()).collect()
+        }
+    }
+}
+
+pub fn handle_build_selection(available_builds: &[String]) -> Result<Vec<Build>> {
     println!("\n📦 Select builds (space to select/cancel, Enter to confirm):");
     let chosen = MultiSelect::new()
         .items(available_builds)
@@ -0,0 +1,38 @@
+use std::path::Path;
+use anyhow::Result;
+use crate::cli::ChangelogArgs;
+use crate::data::tables::get_available_tables;
+use crate::entities::Build;
+use crate::services::changelog::Changelog;
+
+pub fn handle_changelog(output_dir: &Path, args: &ChangelogArgs) -> Result<()> {
+    let old_build: Build = args.old_build.parse()?;
+    let new_build: Build = args.new_build.parse()?;
+
+    for build in [&old_build, &new_build] {
+        if !output_dir.join(build.format_full_version()).is_dir() {
+            return Err(anyhow::anyhow!(
+                "Build {} has not been downloaded to {}", build, output_dir.display()
+            ));
+        }
+    }
+
+    let changelog = Changelog::build(
+        output_dir,
+        &old_build.format_full_version(),
+        &new_build.format_full_version(),
+        &args.locale,
+        &get_available_tables(),
+        args.top,
+    )?;
+    let rendered = changelog.render(args.format);
+
+    match &args.out {
+        Some(path) => {
+            std::fs::write(path, rendered)?;
+            println!("Changelog written to {}", path.display());
+        }
+        None => print!("{}", rendered),
+    }
+    Ok(())
+}
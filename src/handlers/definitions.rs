@@ -0,0 +1,66 @@
+use std::path::Path;
+use anyhow::Result;
+use crate::cli::DefinitionsArgs;
+use crate::entities::Build;
+use crate::services::definitions::{Definitions, FieldType};
+
+pub fn load_definitions(definitions_dir: Option<&Path>) -> Result<Option<Definitions>> {
+    match definitions_dir {
+        Some(dir) => {
+            let definitions = Definitions::load_dir(dir)?;
+            println!("Loaded {} table definitions from {}", definitions.len(), dir.display());
+            Ok(Some(definitions))
+        }
+        None => Ok(None),
+    }
+}
+
+pub fn handle_definitions(definitions: Option<&Definitions>, args: &DefinitionsArgs) -> Result<()> {
+    let definitions = definitions
+        .ok_or_else(|| anyhow::anyhow!("No definitions directory given, use --definitions <dir>"))?;
+    let build: Build = args.build.parse()?;
+
+    let Some(table) = &args.table else {
+        let tables = definitions.locale_dependent_tables(&build);
+        println!("{} locale-dependent tables in {}:", tables.len(), build);
+        for table in tables {
+            if let Some(layout) = definitions.layout(&table, &build) {
+                println!("  {}: {}", table, layout.localized_columns().join(", "));
+            }
+        }
+        return Ok(());
+    };
+
+    let layout = definitions.layout(table, &build)
+        .ok_or_else(|| anyhow::anyhow!("No definition of {} matches build {}", table, build))?;
+
+    println!("{} ({}):", layout.table, build);
+    for field in &layout.fields {
+        let kind = match field.kind {
+            FieldType::Int => match field.size {
+                Some(size) if field.signed => format!("int{}", size),
+                Some(size) => format!("uint{}", size),
+                None => "int".to_string(),
+            },
+            FieldType::Float => "float".to_string(),
+            FieldType::String => "string".to_string(),
+            FieldType::LocString => "locstring".to_string(),
+        };
+        let array = field.array_size.map(|n| format!("[{}]", n)).unwrap_or_default();
+        let mut notes = Vec::new();
+        if field.is_id {
+            notes.push("id".to_string());
+        }
+        if let Some(fk) = &field.foreign_key {
+            notes.push(format!("→ {}::{}", fk.table, fk.column));
+        }
+        if field.is_localized() {
+            notes.push("localized".to_string());
+        }
+        if !field.verified {
+            notes.push("unverified name".to_string());
+        }
+        println!("  {:<32} {:<12} {}", format!("{}{}", field.name, array), kind, notes.join(", "));
+    }
+    Ok(())
+}
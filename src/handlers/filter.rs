@@ -0,0 +1,67 @@
+use std::collections::HashSet;
+use std::path::Path;
+use anyhow::Result;
+use crate::cli::FilterArgs;
+use crate::entities::Build;
+use crate::services::filter::{apply_filter, FilterMode, RowFilter};
+use crate::utils::{list_dirs, list_tables, table_path};
+
+pub fn handle_filter(output_dir: &Path, args: &FilterArgs) -> Result<()> {
+    let filter: RowFilter = args.expression.parse()?;
+    let builds = args.builds.iter()
+        .map(|b| b.parse())
+        .collect::<Result<Vec<Build>>>()?;
+
+    for build in &builds {
+        let build_name = build.format_full_version();
+        let locales = if args.locales.is_empty() {
+            list_dirs(&output_dir.join(&build_name))?
+        } else {
+            args.locales.clone()
+        };
+        for locale in &locales {
+            let tables: HashSet<String> = if args.tables.is_empty() {
+                list_tables(&output_dir.join(&build_name).join(locale))?.into_iter().collect()
+            } else {
+                args.tables.iter().cloned().collect()
+            };
+            filter_tables(output_dir, &filter, args.mode, build, locale, &tables)?;
+        }
+    }
+    Ok(())
+}
+
+/// Applies `filter` to the downloaded tables of one build and locale.
+pub fn filter_tables(
+    output_dir: &Path,
+    filter: &RowFilter,
+    mode: FilterMode,
+    build: &Build,
+    locale: &str,
+    tables: &HashSet<String>,
+) -> Result<()> {
+    let mut names: Vec<&String> = tables.iter().collect();
+    names.sort();
+
+    let mut skipped = 0;
+    for table in names {
+        let path = table_path(output_dir, &build.format_full_version(), locale, table);
+        if !path.exists() {
+            continue;
+        }
+        match apply_filter(filter, &path, mode)? {
+            Some(result) => println!(
+                "🔍 {} {} {}: kept {} of {} rows",
+                build, locale, table, result.kept, result.total
+            ),
+            None => skipped += 1,
+        }
+    }
+    if skipped > 0 {
+        println!(
+            "{} tables of {} {} have no column used by \"{}\" and were left untouched",
+            skipped, build, locale, filter.expression()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,42 @@
+use std::path::Path;
+use anyhow::Result;
+use crate::cli::L10nArgs;
+use crate::entities::Build;
+use crate::services::definitions::Definitions;
+use crate::services::l10n::build_report;
+use crate::utils::list_dirs;
+
+pub fn handle_l10n_report(output_dir: &Path, definitions: Option<&Definitions>, args: &L10nArgs) -> Result<()> {
+    let build: Build = args.build.parse()?;
+    let locales = if args.locales.is_empty() {
+        list_dirs(&output_dir.join(build.format_full_version()))?
+    } else {
+        args.locales.clone()
+    };
+
+    let report = build_report(output_dir, &build, &args.reference, &locales, &args.tables, definitions)?;
+    if report.coverage.is_empty() {
+        println!("No localized table to compare with {} in build {}", args.reference, build);
+        return Ok(());
+    }
+
+    println!("🌍 Localization coverage of {} against {}:", report.build, report.reference);
+    for total in report.by_locale() {
+        println!(
+            "  {}: {:.1}% ({} of {} strings, {} in missing rows, {} empty, {} identical to {})",
+            total.locale, total.completion(), total.translated, total.strings,
+            total.strings - total.translated - total.empty - total.untranslated,
+            total.empty, total.untranslated, args.reference
+        );
+    }
+
+    if let Some(path) = &args.out {
+        report.write_summary_csv(path)?;
+        println!("Summary written to {}", path.display());
+    }
+    if let Some(path) = &args.details {
+        report.write_details_csv(path)?;
+        println!("{} issues written to {}", report.issues.len(), path.display());
+    }
+    Ok(())
+}
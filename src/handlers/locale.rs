@@ -1,16 +1,17 @@
 use anyhow::Result;
 use dialoguer::MultiSelect;
+use crate::data::locales::DEFAULT_LOCALES;
 
-pub fn handle_locale_selection(available_locales: &[&str]) -> anyhow::Result<Vec<String>> {
+pub fn handle_locale_selection(available_locales: &[&str]) -> Result<Vec<String>> {
     println!("\n🌍 Select the locales (space to select/cancel, Enter to confirm):");
     
     // Par exemple, si available_locales est dans l'ordre et contient frFR, esES et enUS à des positions connues
     // Vous pouvez créer un vecteur de booléens avec ces valeurs à true
     let defaults: Vec<bool> = available_locales.iter().map(|&loc| {
-        loc == "frFR" || loc == "esES" || loc == "enUS"
+        DEFAULT_LOCALES.contains(&loc)
     }).collect();
     
-    let chosen = dialoguer::MultiSelect::new()
+    let chosen = MultiSelect::new()
         .items(available_locales)
         .defaults(&defaults)
         .interact()?;
@@ -1,2 +1,14 @@
+pub mod archive;
 pub mod build;
-pub mod locale;
\ No newline at end of file
+pub mod changelog;
+pub mod definitions;
+pub mod filter;
+pub mod l10n;
+pub mod locale;
+pub mod proxy;
+pub mod query;
+pub mod schema;
+pub mod search;
+pub mod serve;
+pub mod store;
+pub mod table;
\ No newline at end of file
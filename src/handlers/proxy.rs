@@ -0,0 +1,25 @@
+use std::sync::Arc;
+use anyhow::Result;
+use crate::cli::ProxyArgs;
+use crate::config::AppConfig;
+use tokio_util::sync::CancellationToken;
+use crate::services::proxy::{router, ProxyState};
+use crate::services::server::serve;
+use crate::services::source;
+use crate::utils::RateLimiter;
+
+pub async fn handle_proxy(config: &AppConfig, args: &ProxyArgs, shutdown: CancellationToken) -> Result<()> {
+    let source = source::from_config(config)?;
+    println!("🌐 Caching {} in {}", source.describe(), config.output_dir.display());
+    println!(
+        "   Listening on http://{}, statistics at http://{}/status and http://{}/metrics",
+        args.listen, args.listen, args.listen
+    );
+    let state = ProxyState::new(
+        config.output_dir.clone(),
+        source,
+        RateLimiter::new(config.requests_per_minute),
+        args.compress,
+    );
+    serve(router(Arc::new(state)), args.listen, shutdown).await
+}
@@ -0,0 +1,36 @@
+use std::path::Path;
+use anyhow::Result;
+use crate::cli::QueryArgs;
+use crate::entities::Build;
+use crate::services::query::{run_query, Query};
+use crate::utils::list_builds;
+
+pub fn handle_query(output_dir: &Path, args: &QueryArgs) -> Result<()> {
+    let build = match &args.build {
+        Some(build) => build.parse::<Build>()?,
+        None => list_builds(output_dir)?
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("No build downloaded in {}", output_dir.display()))?,
+    };
+
+    let equals = args.equals.iter()
+        .map(|e| {
+            e.split_once('=')
+                .map(|(c, v)| (c.trim().to_string(), v.trim().to_string()))
+                .ok_or_else(|| anyhow::anyhow!("Invalid condition '{}', expected Column=Value", e))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let query = Query {
+        ids: args.ids.clone(),
+        equals,
+        contains: args.contains.clone(),
+        join: args.join.as_deref().map(str::parse).transpose()?,
+        columns: args.columns.clone(),
+        limit: args.limit,
+    };
+
+    let result = run_query(output_dir, &build.format_full_version(), &args.locale, &args.table, &query)?;
+    println!("{}", result.render(args.format).trim_end());
+    Ok(())
+}
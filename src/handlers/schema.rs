@@ -0,0 +1,56 @@
+use std::path::Path;
+use anyhow::Result;
+use crate::cli::SchemaArgs;
+use crate::entities::Build;
+use crate::services::definitions::Definitions;
+use crate::services::schema::{check_build, SchemaReport};
+use crate::utils::list_dirs;
+
+pub fn handle_schema(output_dir: &Path, definitions: Option<&Definitions>, args: &SchemaArgs) -> Result<()> {
+    let builds = args.builds.iter()
+        .map(|b| b.parse())
+        .collect::<Result<Vec<Build>>>()?;
+
+    for build in &builds {
+        let locales = if args.locales.is_empty() {
+            list_dirs(&output_dir.join(build.format_full_version()))?
+        } else {
+            args.locales.clone()
+        };
+        let report = check_build(output_dir, build, &locales, definitions)?;
+        print_report(&report);
+    }
+    Ok(())
+}
+
+pub fn handle_schema_check(
+    output_dir: &Path,
+    definitions: Option<&Definitions>,
+    builds: &[Build],
+    locales: &[String],
+) -> Result<()> {
+    println!("\n🔎 Checking table schemas:");
+    for build in builds {
+        let report = check_build(output_dir, build, locales, definitions)?;
+        print_report(&report);
+    }
+    Ok(())
+}
+
+fn print_report(report: &SchemaReport) {
+    match &report.previous_build {
+        Some(previous) => println!("Build {}: {} tables, compared with {}", report.build, report.tables, previous),
+        None => println!("Build {}: {} tables", report.build, report.tables),
+    }
+
+    if report.is_clean() {
+        println!("✓ No schema changes or malformed rows");
+        return;
+    }
+    for change in &report.changes {
+        tracing::warn!("{}", change);
+    }
+    for row in &report.malformed {
+        tracing::warn!("{} ({}) line {}: {}", row.table, row.locale, row.line, row.issue);
+    }
+}
@@ -0,0 +1,69 @@
+use std::path::Path;
+use anyhow::Result;
+use crate::cli::{IndexArgs, SearchArgs};
+use crate::entities::Build;
+use crate::services::query::QueryResult;
+use crate::services::search::{has_index, search, update_index, SearchOptions};
+use crate::utils::list_builds;
+
+pub fn handle_index(output_dir: &Path, args: &IndexArgs) -> Result<()> {
+    let builds = if args.builds.is_empty() {
+        list_builds(output_dir)?
+    } else {
+        args.builds.iter().map(|b| b.parse()).collect::<Result<Vec<Build>>>()?
+    };
+    for build in &builds {
+        index_build(output_dir, build)?;
+    }
+    Ok(())
+}
+
+/// Refreshes the search index of the builds that already have one.
+pub fn handle_index_update(output_dir: &Path, builds: &[Build]) -> Result<()> {
+    for build in builds {
+        if has_index(output_dir, &build.format_full_version()) {
+            index_build(output_dir, build)?;
+        }
+    }
+    Ok(())
+}
+
+fn index_build(output_dir: &Path, build: &Build) -> Result<()> {
+    let stats = update_index(output_dir, &build.format_full_version())?;
+    println!(
+        "🗂 Search index of {}: {} files indexed, {} unchanged, {} removed",
+        build, stats.indexed, stats.unchanged, stats.removed
+    );
+    Ok(())
+}
+
+pub fn handle_search(output_dir: &Path, args: &SearchArgs) -> Result<()> {
+    let build = match &args.build {
+        Some(build) => build.parse::<Build>()?,
+        None => list_builds(output_dir)?
+            .into_iter()
+            .filter(|b| has_index(output_dir, &b.format_full_version()))
+            .max()
+            .ok_or_else(|| anyhow::anyhow!("No search index in {}, run the index command first", output_dir.display()))?,
+    };
+    if !has_index(output_dir, &build.format_full_version()) {
+        return Err(anyhow::anyhow!("Build {} has no search index, run the index command first", build));
+    }
+
+    let options = SearchOptions {
+        locales: args.locales.clone(),
+        tables: args.tables.clone(),
+        limit: args.limit,
+    };
+    let hits = search(output_dir, &build.format_full_version(), &args.phrase, &options)?;
+
+    let result = QueryResult {
+        headers: ["Locale", "Table", "ID", "Column", "Text"].iter().map(|h| h.to_string()).collect(),
+        total: hits.len(),
+        rows: hits.into_iter()
+            .map(|h| vec![h.locale, h.table, h.id, h.column, h.text])
+            .collect(),
+    };
+    println!("{}", result.render(args.format).trim_end());
+    Ok(())
+}
@@ -0,0 +1,13 @@
+use std::path::Path;
+use std::sync::Arc;
+use anyhow::Result;
+use tokio_util::sync::CancellationToken;
+use crate::cli::ServeArgs;
+use crate::services::server::{router, serve, ServerState};
+
+pub async fn handle_serve(output_dir: &Path, args: &ServeArgs, shutdown: CancellationToken) -> Result<()> {
+    let state = Arc::new(ServerState::new(output_dir.to_path_buf()));
+    println!("🌐 Serving {} on http://{}, metrics at http://{}/metrics", output_dir.display(), args.listen, args.listen);
+    println!("   Point other instances at it with base_url = \"http://{}/db2\"", args.listen);
+    serve(router(state), args.listen, shutdown).await
+}
@@ -0,0 +1,50 @@
+use std::collections::HashSet;
+use std::path::Path;
+use anyhow::Result;
+use crate::cli::GcArgs;
+use crate::entities::Build;
+use crate::services::store::ObjectStore;
+use crate::utils::table_path;
+
+fn megabytes(bytes: u64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+/// Moves the downloaded tables of the given builds into the object store.
+pub fn store_tables(output_dir: &Path, builds: &[Build], locales: &[String], tables: &HashSet<String>) -> Result<()> {
+    let store = ObjectStore::new(output_dir);
+    let mut names: Vec<&String> = tables.iter().collect();
+    names.sort();
+
+    for build in builds {
+        let build_name = build.format_full_version();
+        let mut files = Vec::new();
+        for locale in locales {
+            for table in &names {
+                // Stored under whichever compression it was downloaded with
+                let path = table_path(output_dir, &build_name, locale, table);
+                if let Some(name) = path.file_name() {
+                    files.push(format!("{}/{}", locale, name.to_string_lossy()));
+                }
+            }
+        }
+        let stats = store.store_build(&build_name, &files)?;
+        if stats.stored + stats.deduplicated > 0 {
+            println!(
+                "🗄️ {}: {} new objects, {} files shared with other builds ({:.1} MB saved)",
+                build, stats.stored, stats.deduplicated, megabytes(stats.bytes_saved)
+            );
+        }
+    }
+    Ok(())
+}
+
+pub fn handle_gc(output_dir: &Path, args: &GcArgs) -> Result<()> {
+    let stats = ObjectStore::new(output_dir).gc(args.dry_run)?;
+    let verb = if args.dry_run { "Would remove" } else { "Removed" };
+    println!(
+        "{} {} unreferenced objects ({:.1} MB), {} objects still in use",
+        verb, stats.removed, megabytes(stats.bytes_freed), stats.kept
+    );
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use std::collections::HashSet;
+use std::path::Path;
+use anyhow::Result;
+use crate::data::tables::get_available_tables;
+use crate::entities::Build;
+use crate::services::definitions::Definitions;
+use crate::services::dependencies::DependencyGraph;
+use crate::services::schema::BuildSchema;
+use crate::services::source::DataSource;
+use crate::utils::list_builds;
+
+/// Tables the source has for the given builds, the bundled list when it cannot tell.
+pub async fn available_tables(source: &dyn DataSource, builds: &[Build]) -> HashSet<String> {
+    let mut tables = HashSet::new();
+    for build in builds {
+        match source.list_tables(build).await {
+            Ok(listed) => tables.extend(listed),
+            Err(e) => {
+                tracing::warn!("Unable to list the tables of {} {}: {}", source.describe(), build, e);
+                return get_available_tables();
+            }
+        }
+    }
+    if tables.is_empty() {
+        return get_available_tables();
+    }
+    tables
+}
+
+/// Every known table when nothing is requested, otherwise the requested tables
+/// expanded to the tables they reference up to `depth` levels.
+pub fn handle_table_selection(
+    output_dir: &Path,
+    definitions: Option<&Definitions>,
+    builds: &[Build],
+    mut known_tables: HashSet<String>,
+    requested: &[String],
+    depth: usize,
+) -> Result<HashSet<String>> {
+    if requested.is_empty() {
+        return Ok(known_tables);
+    }
+    for table in requested {
+        if known_tables.insert(table.clone()) {
+            tracing::warn!("{} is not in the table list, downloading it anyway", table);
+        }
+    }
+    if depth == 0 {
+        return Ok(requested.iter().cloned().collect());
+    }
+
+    let mut graph = DependencyGraph::default();
+    if let Some(definitions) = definitions {
+        for build in builds {
+            graph.add_definitions(definitions, build, &known_tables);
+        }
+    }
+    for schema in load_schemas(output_dir, builds)? {
+        graph.add_schema(&schema, &known_tables);
+    }
+
+    for table in requested {
+        if !graph.is_known(table) {
+            tracing::warn!(
+                "No column information for {}: download it once or pass --definitions to follow its references",
+                table
+            );
+        }
+    }
+
+    let selected = graph.expand(requested, depth);
+    let added: Vec<&String> = selected.iter().filter(|t| !requested.contains(t)).collect();
+    if !added.is_empty() {
+        let tables = added.iter().map(|t| t.as_str()).collect::<Vec<_>>().join(", ");
+        tracing::info!(tables = %tables, "Added {} referenced tables", added.len());
+    }
+    Ok(selected.into_iter().collect())
+}
+
+/// Stored schemas of the selected builds, falling back to the newest downloaded build.
+fn load_schemas(output_dir: &Path, builds: &[Build]) -> Result<Vec<BuildSchema>> {
+    let mut schemas = Vec::new();
+    for build in builds {
+        if let Some(schema) = BuildSchema::load(output_dir, &build.format_full_version())? {
+            schemas.push(schema);
+        }
+    }
+    if schemas.is_empty() {
+        let newest = list_builds(output_dir)?
+            .into_iter()
+            .filter(|b| BuildSchema::path(output_dir, &b.format_full_version()).exists())
+            .max();
+        if let Some(build) = newest {
+            schemas.extend(BuildSchema::load(output_dir, &build.format_full_version())?);
+        }
+    }
+    Ok(schemas)
+}
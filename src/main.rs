@@ -1,3 +1,4 @@
+mod cli;
 mod config;
 mod data;
 mod entities;
@@ -5,56 +6,387 @@ mod handlers;
 mod services;
 mod utils;
 
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 use anyhow::Result;
+use clap::Parser;
 use dialoguer::Confirm;
+use tokio_util::sync::CancellationToken;
+use cli::{Cli, Command, DownloadArgs, WatchArgs};
+use entities::Build;
+use services::concurrency::Concurrency;
 use services::downloader::DownloadService;
+use services::job::{DownloadJob, JobOptions, JOB_FILE};
+use services::metrics::DownloadMetrics;
+use services::store::StoreMode;
+use utils::BandwidthLimiter;
 
 async fn run() -> Result<()> {
-    println!("wago.tools DB2 csv exporter by notwonderful");
+    let cli = Cli::parse();
 
-    let config = config::AppConfig::new();
-    
-    let available_builds = data::builds::AVAILABLE_BUILDS;
+    let mut config = config::AppConfig::load(cli.config.as_deref())?;
+    if let Some(output_dir) = cli.output_dir {
+        config.output_dir = output_dir;
+    }
+    if let Some(definitions_dir) = cli.definitions {
+        config.definitions_dir = Some(definitions_dir);
+    }
+    if let Some(log_file) = cli.log_file {
+        config.log.file = Some(log_file);
+    }
+    let verbosity = (cli.verbose.min(10) as i8) - (cli.quiet.min(10) as i8);
+    let _log_guard = utils::init_logging(verbosity, &config.log)?;
+
+    // Logged here so that the log file gets it before its guard is dropped
+    let result = dispatch(cli.command, config, cli.config).await;
+    if let Err(e) = &result {
+        tracing::error!("{:#}", e);
+    }
+    result
+}
+
+async fn dispatch(command: Option<Command>, config: config::AppConfig, config_path: Option<PathBuf>) -> Result<()> {
+    match command {
+        Some(Command::Changelog(args)) => handlers::changelog::handle_changelog(&config.output_dir, &args),
+        Some(Command::Schema(args)) => {
+            let definitions = handlers::definitions::load_definitions(config.definitions_dir.as_deref())?;
+            handlers::schema::handle_schema(&config.output_dir, definitions.as_ref(), &args)
+        }
+        Some(Command::Definitions(args)) => {
+            let definitions = handlers::definitions::load_definitions(config.definitions_dir.as_deref())?;
+            handlers::definitions::handle_definitions(definitions.as_ref(), &args)
+        }
+        Some(Command::Query(args)) => handlers::query::handle_query(&config.output_dir, &args),
+        Some(Command::Index(args)) => handlers::search::handle_index(&config.output_dir, &args),
+        Some(Command::Search(args)) => handlers::search::handle_search(&config.output_dir, &args),
+        Some(Command::L10nReport(args)) => {
+            let definitions = handlers::definitions::load_definitions(config.definitions_dir.as_deref())?;
+            handlers::l10n::handle_l10n_report(&config.output_dir, definitions.as_ref(), &args)
+        }
+        Some(Command::Archive(args)) => handlers::archive::handle_archive(&config.output_dir, &args),
+        Some(Command::Extract(args)) => handlers::archive::handle_extract(&config.output_dir, &args),
+        Some(Command::Verify(args)) => handlers::archive::handle_verify(&args),
+        Some(Command::Serve(args)) => {
+            handlers::serve::handle_serve(&config.output_dir, &args, utils::shutdown_on_signal()).await
+        }
+        Some(Command::Proxy(args)) => {
+            handlers::proxy::handle_proxy(&config, &args, utils::shutdown_on_signal()).await
+        }
+        Some(Command::Gc(args)) => handlers::store::handle_gc(&config.output_dir, &args),
+        Some(Command::Filter(args)) => handlers::filter::handle_filter(&config.output_dir, &args),
+        Some(Command::Watch(args)) => {
+            let config_path = config::AppConfig::resolve_path(config_path.as_deref());
+            run_watch(config, config_path, args, &utils::shutdown_on_signal()).await
+        }
+        Some(Command::Download(args)) => {
+            let bandwidth = BandwidthLimiter::new(args.limit_rate.or(config.limit_rate));
+            run_download(&config, args, &utils::shutdown_on_signal(), &config.output_dir, &bandwidth, &Arc::default()).await
+        }
+        None => {
+            let bandwidth = BandwidthLimiter::new(config.limit_rate);
+            let cancel = utils::shutdown_on_signal();
+            run_download(&config, DownloadArgs::default(), &cancel, &config.output_dir, &bandwidth, &Arc::default()).await
+        }
+    }
+}
+
+async fn run_download(
+    config: &config::AppConfig,
+    mut args: DownloadArgs,
+    cancel: &CancellationToken,
+    job_dir: &Path,
+    bandwidth: &BandwidthLimiter,
+    metrics: &Arc<DownloadMetrics>,
+) -> Result<()> {
+    tracing::info!("wago.tools DB2 csv exporter by notwonderful");
+
+    if args.resume {
+        let job = DownloadJob::load(job_dir)?.ok_or_else(|| {
+            anyhow::anyhow!("No download to resume in {}", job_dir.display())
+        })?;
+        tracing::info!("Resuming the download started at {}", job.started_at);
+        args.builds = job.builds;
+        args.locales = job.locales;
+        args.tables = job.tables.into_iter().collect();
+        args.depth = Some(0);
+        let options = job.options;
+        args.compress = options.compress;
+        args.store = options.store;
+        args.filter_mode = options.filter_mode;
+        args.order = options.order;
+        // Given again on the command line, they replace the saved ones
+        if args.windows.is_empty() {
+            args.windows = options.windows;
+        }
+        args.filter = args.filter.or(options.filter);
+        args.archive = args.archive.or(options.archive);
+        args.priority_locale = args.priority_locale.or(options.priority_locale);
+        args.concurrency = args.concurrency.or(options.concurrency);
+        args.yes = true;
+    }
+
+    let source = services::source::from_config(config)?;
     let available_locales = data::locales::AVAILABLE_LOCALES;
-    let tables = data::tables::get_available_tables();
 
-    let selected_builds = handlers::build::handle_build_selection(available_builds)?;
+    let selected_builds = if args.builds.is_empty() {
+        let available_builds = handlers::build::available_builds(source.as_ref()).await;
+        handlers::build::handle_build_selection(&available_builds)?
+    } else {
+        args.builds.iter().map(|b| b.parse()).collect::<Result<Vec<Build>>>()?
+    };
     if selected_builds.is_empty() {
         return Ok(());
     }
 
-    let selected_locales = handlers::locale::handle_locale_selection(available_locales)?;
+    let selected_locales = if args.locales.is_empty() {
+        handlers::locale::handle_locale_selection(available_locales)?
+    } else {
+        args.locales.clone()
+    };
     if selected_locales.is_empty() {
         return Ok(());
     }
 
-    println!("\n📥 Let's start downloading:");
-    println!("Builds: {}", selected_builds.iter()
+    // Fail on an invalid expression before downloading anything
+    let filter = args.filter.as_deref()
+        .map(str::parse::<services::filter::RowFilter>)
+        .transpose()?;
+
+    let definitions = handlers::definitions::load_definitions(config.definitions_dir.as_deref())?;
+    let tables = handlers::table::handle_table_selection(
+        &config.output_dir,
+        definitions.as_ref(),
+        &selected_builds,
+        handlers::table::available_tables(source.as_ref(), &selected_builds).await,
+        &args.tables,
+        args.depth.unwrap_or(config.dependency_depth),
+    )?;
+
+    tracing::info!("📥 Let's start downloading:");
+    tracing::info!("Builds: {}", selected_builds.iter()
         .map(|b| b.to_string())
         .collect::<Vec<_>>()
         .join(", "));
-    println!("Locales: {}", selected_locales.join(", "));
-    println!("Total Tables: {}", tables.len());
+    tracing::info!("Locales: {}", selected_locales.join(", "));
+    tracing::info!("Total Tables: {}", tables.len());
+    let windows = if args.windows.is_empty() { config.download_windows.clone() } else { args.windows.clone() };
+    if !windows.is_empty() {
+        tracing::info!("Download windows: {}", windows.iter()
+            .map(|w| w.to_string())
+            .collect::<Vec<_>>()
+            .join(", "));
+    }
 
-    if Confirm::new()
+    if args.yes || Confirm::new()
         .with_prompt("Start downloading?")
         .interact()? 
     {
-        let mut downloader = DownloadService::new(config.base_url)?;
+        let mut downloader = DownloadService::with_source(source);
+        downloader.set_output_dir(&config.output_dir);
         downloader.set_rate_limit(config.requests_per_minute);
         downloader.set_retry_params(config.max_retries, config.retry_delay_secs);
-        
-        downloader.download_all(&tables, &selected_builds, &selected_locales).await?;
-        println!("Download completed!");
+        downloader.set_compression(args.compress);
+        downloader.set_order(args.order, args.priority_locale.clone());
+        downloader.set_bandwidth_limiter(bandwidth.clone());
+        downloader.set_windows(windows.clone());
+        downloader.set_metrics(Arc::clone(metrics));
+        match args.concurrency {
+            Some(Concurrency::Fixed(count)) => downloader.set_concurrent_downloads(count),
+            Some(Concurrency::Auto) => downloader.set_adaptive_concurrency(
+                config.concurrent_downloads, config.min_concurrent_downloads, config.max_concurrent_downloads,
+            ),
+            None if config.adaptive_concurrency => downloader.set_adaptive_concurrency(
+                config.concurrent_downloads, config.min_concurrent_downloads, config.max_concurrent_downloads,
+            ),
+            None => downloader.set_concurrent_downloads(config.concurrent_downloads),
+        }
+        downloader.set_cancellation(cancel.clone(), Duration::from_secs(config.shutdown_timeout_secs));
+
+        DownloadJob::new(
+            selected_builds.iter().map(|b| b.to_string()).collect(),
+            selected_locales.clone(),
+            tables.iter().cloned().collect(),
+            JobOptions {
+                compress: args.compress,
+                windows,
+                store: args.store,
+                filter: args.filter.clone(),
+                filter_mode: args.filter_mode,
+                archive: args.archive,
+                order: args.order,
+                priority_locale: args.priority_locale.clone(),
+                concurrency: args.concurrency,
+            },
+        ).save(job_dir)?;
+        downloader.set_hooks(config.hooks.clone());
+        let report = downloader.download_all(&tables, &selected_builds, &selected_locales).await?;
+        let processed = async {
+            if cancel.is_cancelled() {
+                tracing::info!("{}", report.total.summary());
+                tracing::warn!("Download interrupted, run `download --resume` to continue it");
+                return Err(anyhow::anyhow!("Download cancelled"));
+            }
+            // The tables are downloaded all the same
+            if let Err(e) = handlers::schema::handle_schema_check(
+                &config.output_dir,
+                definitions.as_ref(),
+                &selected_builds,
+                &selected_locales,
+            ) {
+                tracing::warn!("Schema check failed: {:#}", e);
+            }
+            if let Some(filter) = &filter {
+                for build in &selected_builds {
+                    for locale in &selected_locales {
+                        handlers::filter::filter_tables(
+                            &config.output_dir, filter, args.filter_mode, build, locale, &tables,
+                        )?;
+                    }
+                }
+            }
+            if args.store == StoreMode::Cas {
+                handlers::store::store_tables(&config.output_dir, &selected_builds, &selected_locales, &tables)?;
+            }
+            handlers::search::handle_index_update(&config.output_dir, &selected_builds)?;
+            tracing::info!("{}", report.total.summary());
+            if report.total.failed == 0 {
+                if let Some(format) = args.archive {
+                    for build in &selected_builds {
+                        let path = handlers::archive::archive_path(&config.output_dir, build, format);
+                        handlers::archive::archive_build(&config.output_dir, build, format, &path)?;
+                    }
+                }
+            }
+            Ok(())
+        }.await;
+        // Run whatever happened to the downloads or their post-processing
+        let hooks = services::hooks::after_run(&config.hooks, &config.http, &config.output_dir, &report).await;
+        processed?;
+        hooks?;
+        if report.total.failed > 0 {
+            return Err(anyhow::anyhow!("Some downloads failed, run `download --resume` to retry them"));
+        }
+        DownloadJob::remove(job_dir)?;
+        tracing::info!("Download completed!");
     }
 
     Ok(())
 }
 
+/// Downloads the new builds of the watched products at every poll.
+async fn run_watch(
+    mut config: config::AppConfig,
+    config_path: Option<PathBuf>,
+    args: WatchArgs,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    let watch = &mut config.watch;
+    if let Some(interval) = args.interval {
+        watch.interval_secs = interval;
+    }
+    if !args.products.is_empty() {
+        watch.products = args.products;
+    }
+    if let Some(latest) = args.latest {
+        watch.latest = latest;
+    }
+    if !args.locales.is_empty() {
+        watch.locales = args.locales;
+    }
+    if !args.tables.is_empty() {
+        watch.tables = args.tables;
+    }
+    if args.hook.is_some() {
+        watch.hook = args.hook;
+    }
+    if args.metrics_listen.is_some() {
+        watch.metrics_listen = args.metrics_listen;
+    }
+    let watch = config.watch.clone();
+
+    let source = services::source::from_config(&config)?;
+    let products = if watch.products.is_empty() { "all products".to_string() } else { watch.products.join(", ") };
+    tracing::info!("Watching {} ({}) every {} seconds", source.describe(), products, watch.interval_secs);
+
+    let bandwidth = BandwidthLimiter::new(args.limit_rate.or(config.limit_rate));
+    if let Some(rate) = bandwidth.rate() {
+        tracing::info!("Bandwidth limited to {}", rate);
+    }
+    if let Some(path) = config_path {
+        tokio::spawn(services::watch::follow_config(path, config.limit_rate, bandwidth.clone()));
+    }
+    let metrics = Arc::new(DownloadMetrics::default());
+    if let Some(listen) = watch.metrics_listen {
+        tracing::info!("Metrics at http://{}/metrics", listen);
+        let server = services::server::serve(services::metrics::router(Arc::clone(&metrics)), listen, cancel.clone());
+        tokio::spawn(async move {
+            if let Err(e) = server.await {
+                tracing::error!("Metrics endpoint on {}: {:#}", listen, e);
+            }
+        });
+    }
+
+    // Builds of an interrupted download are completed first
+    let mut failed: HashSet<Build> = utils::list_builds(&config.output_dir)?
+        .into_iter()
+        .filter(|build| config.output_dir.join(build.format_full_version()).join(JOB_FILE).exists())
+        .collect();
+    loop {
+        match services::watch::pending_builds(
+            source.as_ref(), &watch.products, watch.latest, &config.output_dir, &failed,
+        ).await {
+            Ok(builds) => {
+                for build in builds {
+                    tracing::info!("New build {}", build);
+                    let download = DownloadArgs {
+                        builds: vec![build.to_string()],
+                        locales: watch.locales.clone(),
+                        tables: watch.tables.clone(),
+                        compress: args.compress,
+                        yes: true,
+                        ..Default::default()
+                    };
+                    // Each build keeps its own job so that they do not replace each other
+                    let job_dir = config.output_dir.join(build.format_full_version());
+                    let downloaded = run_download(&config, download, cancel, &job_dir, &bandwidth, &metrics).await;
+                    if cancel.is_cancelled() {
+                        return Ok(());
+                    }
+                    if let Err(e) = downloaded {
+                        tracing::error!("Build {}: {:#}", build, e);
+                        failed.insert(build);
+                        continue;
+                    }
+                    failed.remove(&build);
+                    if let Some(hook) = &watch.hook {
+                        let env = services::hooks::build_env(&config.output_dir, &build.to_string());
+                        if let Err(e) = services::hooks::run_command(hook, &env).await {
+                            tracing::error!("{:#}", e);
+                        }
+                    }
+                }
+            }
+            Err(e) => tracing::error!("Unable to list the builds of {}: {:#}", source.describe(), e),
+        }
+        if args.once {
+            return Ok(());
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(watch.interval_secs)) => {}
+            _ = cancel.cancelled() => return Ok(()),
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     if let Err(e) = run().await {
-        eprintln!("Error: {}", e);
+        // Errors are logged once logging is set up
+        if !tracing::dispatcher::has_been_set() {
+            eprintln!("Error: {:#}", e);
+        }
         std::process::exit(1);
     }
     Ok(())
@@ -0,0 +1,395 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zip::write::SimpleFileOptions;
+use crate::services::store::sha256_file;
+use crate::utils::{ensure_dir_exists, list_dirs, split_table_file};
+
+const MANIFEST_FILE: &str = "manifest.json";
+const ZSTD_LEVEL: i32 = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveFormat {
+    Zip,
+    #[value(name = "tar.zst")]
+    #[serde(rename = "tar.zst")]
+    TarZst,
+}
+
+impl ArchiveFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::TarZst => "tar.zst",
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let name = path.to_string_lossy().to_lowercase();
+        if name.ends_with(".zip") {
+            Ok(ArchiveFormat::Zip)
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Ok(ArchiveFormat::TarZst)
+        } else {
+            Err(anyhow::anyhow!("Unknown archive format for {}, expected .zip or .tar.zst", path.display()))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchivedFile {
+    pub sha256: String,
+    pub size: u64,
+}
+
+/// `<build>/manifest.json` at the root of every archive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchiveManifest {
+    pub build: String,
+    /// Modification time of the most recently downloaded file
+    pub downloaded_at: String,
+    pub archived_at: String,
+    pub locales: Vec<String>,
+    pub tables: Vec<String>,
+    /// Archived files by path inside the archive, e.g. `11.0.5.57212/enUS/Spell.csv`
+    pub files: BTreeMap<String, ArchivedFile>,
+}
+
+impl ArchiveManifest {
+    /// Describes the downloaded table files of a build, compressed or not.
+    pub fn from_tree(root: &Path, build: &str) -> Result<Self> {
+        let build_dir = root.join(build);
+        if !build_dir.is_dir() {
+            return Err(anyhow::anyhow!("Build {} has not been downloaded to {}", build, root.display()));
+        }
+
+        let mut locales = Vec::new();
+        let mut tables = BTreeSet::new();
+        let mut files = BTreeMap::new();
+        let mut downloaded_at: Option<DateTime<Utc>> = None;
+        for locale in list_dirs(&build_dir)? {
+            let mut entries: Vec<PathBuf> = fs::read_dir(build_dir.join(&locale))?
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.is_file() && split_table_file(&p.file_name().unwrap_or_default().to_string_lossy()).is_some())
+                .collect();
+            if entries.is_empty() {
+                continue;
+            }
+            entries.sort();
+            locales.push(locale.clone());
+
+            for path in entries {
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+                if let Some((table, _)) = split_table_file(&file_name).filter(|(t, _)| !t.contains('.')) {
+                    tables.insert(table.to_string());
+                }
+                let metadata = path.metadata()?;
+                let modified = DateTime::<Utc>::from(metadata.modified()?);
+                downloaded_at = downloaded_at.max(Some(modified));
+                files.insert(
+                    format!("{}/{}/{}", build, locale, file_name),
+                    ArchivedFile { sha256: sha256_file(&path)?, size: metadata.len() },
+                );
+            }
+        }
+
+        Ok(Self {
+            build: build.to_string(),
+            downloaded_at: downloaded_at.unwrap_or_else(Utc::now).to_rfc3339_opts(SecondsFormat::Secs, true),
+            archived_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            locales,
+            tables: tables.into_iter().collect(),
+            files,
+        })
+    }
+
+    fn entry_name(&self) -> String {
+        format!("{}/{}", self.build, MANIFEST_FILE)
+    }
+}
+
+/// Result of checking the files of an archive against its manifest.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    pub verified: usize,
+    pub mismatched: Vec<String>,
+    pub missing: Vec<String>,
+    pub unexpected: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.mismatched.is_empty() && self.missing.is_empty() && self.unexpected.is_empty()
+    }
+}
+
+/// Packages the downloaded tree of `build` into `output`, manifest first.
+pub fn create_archive(root: &Path, build: &str, output: &Path, format: ArchiveFormat) -> Result<ArchiveManifest> {
+    let manifest = ArchiveManifest::from_tree(root, build)?;
+    let files: Vec<&String> = manifest.files.keys().collect();
+    write_archive(root, &manifest, &files, output, format)?;
+    Ok(manifest)
+}
+
+fn write_archive(
+    root: &Path,
+    manifest: &ArchiveManifest,
+    files: &[&String],
+    output: &Path,
+    format: ArchiveFormat,
+) -> Result<()> {
+    if let Some(parent) = output.parent().filter(|p| !p.as_os_str().is_empty()) {
+        ensure_dir_exists(parent)?;
+    }
+    let file = File::create(output)
+        .with_context(|| format!("Unable to write {}", output.display()))?;
+    let manifest_json = serde_json::to_vec_pretty(manifest)?;
+
+    match format {
+        ArchiveFormat::Zip => {
+            let mut zip = zip::ZipWriter::new(file);
+            let options = SimpleFileOptions::default()
+                .compression_method(zip::CompressionMethod::Deflated)
+                .large_file(true);
+            zip.start_file(manifest.entry_name(), options)?;
+            zip.write_all(&manifest_json)?;
+            for name in files {
+                zip.start_file(name.as_str(), options)?;
+                io::copy(&mut File::open(root.join(name.as_str()))?, &mut zip)?;
+            }
+            zip.finish()?;
+        }
+        ArchiveFormat::TarZst => {
+            let encoder = zstd::Encoder::new(file, ZSTD_LEVEL)?;
+            let mut tar = tar::Builder::new(encoder);
+            let mut header = tar::Header::new_gnu();
+            header.set_size(manifest_json.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(Utc::now().timestamp().max(0) as u64);
+            tar.append_data(&mut header, manifest.entry_name(), manifest_json.as_slice())?;
+            for name in files {
+                tar.append_path_with_name(root.join(name), name.as_str())?;
+            }
+            tar.into_inner()?.finish()?;
+        }
+    }
+    Ok(())
+}
+
+/// Calls `f` with the name and content of every regular file of an archive.
+fn for_each_entry(archive: &Path, mut f: impl FnMut(&str, &mut dyn Read) -> Result<()>) -> Result<()> {
+    let file = File::open(archive)
+        .with_context(|| format!("Unable to read {}", archive.display()))?;
+    match ArchiveFormat::from_path(archive)? {
+        ArchiveFormat::Zip => {
+            let mut zip = zip::ZipArchive::new(file)?;
+            for i in 0..zip.len() {
+                let mut entry = zip.by_index(i)?;
+                if entry.is_dir() {
+                    continue;
+                }
+                let name = entry.name().to_string();
+                f(&name, &mut entry)?;
+            }
+        }
+        ArchiveFormat::TarZst => {
+            let mut tar = tar::Archive::new(zstd::Decoder::new(file)?);
+            for entry in tar.entries()? {
+                let mut entry = entry?;
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+                let name = entry.path()?.to_string_lossy().to_string();
+                f(&name, &mut entry)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Archive entries must stay inside the extraction directory.
+fn safe_path(name: &str) -> Result<PathBuf> {
+    let path = PathBuf::from(name);
+    if path.components().all(|c| matches!(c, Component::Normal(_))) {
+        Ok(path)
+    } else {
+        Err(anyhow::anyhow!("Refusing to extract unsafe path {}", name))
+    }
+}
+
+fn is_manifest(name: &str) -> bool {
+    let path = Path::new(name);
+    path.file_name().is_some_and(|f| f == MANIFEST_FILE) && path.components().count() == 2
+}
+
+/// Hashes whatever is read through it.
+struct HashingReader<'a> {
+    inner: &'a mut dyn Read,
+    hasher: Sha256,
+}
+
+impl Read for HashingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Reads an archive once, hashing every file while `sink` consumes the non-manifest entries.
+fn scan_archive(
+    archive: &Path,
+    mut sink: impl FnMut(&str, &mut dyn Read) -> Result<()>,
+) -> Result<(ArchiveManifest, VerifyReport)> {
+    let mut manifest: Option<ArchiveManifest> = None;
+    let mut hashes: BTreeMap<String, String> = BTreeMap::new();
+
+    for_each_entry(archive, |name, reader| {
+        if is_manifest(name) && manifest.is_none() {
+            manifest = Some(serde_json::from_reader(reader)
+                .with_context(|| format!("Invalid manifest in {}", archive.display()))?);
+            return Ok(());
+        }
+        let mut hashing = HashingReader { inner: reader, hasher: Sha256::new() };
+        sink(name, &mut hashing)?;
+        // Whatever the sink left unread still counts
+        io::copy(&mut hashing, &mut io::sink())?;
+        hashes.insert(name.to_string(), format!("{:x}", hashing.hasher.finalize()));
+        Ok(())
+    })?;
+
+    let manifest = manifest
+        .ok_or_else(|| anyhow::anyhow!("{} has no {}", archive.display(), MANIFEST_FILE))?;
+    let mut report = VerifyReport::default();
+    for (name, expected) in &manifest.files {
+        match hashes.remove(name) {
+            Some(hash) if hash == expected.sha256 => report.verified += 1,
+            Some(_) => report.mismatched.push(name.clone()),
+            None => report.missing.push(name.clone()),
+        }
+    }
+    report.unexpected = hashes.into_keys().collect();
+    Ok((manifest, report))
+}
+
+pub fn verify_archive(archive: &Path) -> Result<(ArchiveManifest, VerifyReport)> {
+    scan_archive(archive, |_, _| Ok(()))
+}
+
+/// Extracts an archive under `dest`, recreating `<build>/<locale>/<table>.csv`,
+/// and verifies the extracted files against the manifest, which is kept next to them.
+pub fn extract_archive(archive: &Path, dest: &Path) -> Result<(ArchiveManifest, VerifyReport)> {
+    let (manifest, report) = scan_archive(archive, |name, reader| {
+        let path = dest.join(safe_path(name)?);
+        if let Some(parent) = path.parent() {
+            ensure_dir_exists(parent)?;
+        }
+        // Replace rather than truncate, the file may be linked to a store object
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        let mut file = File::create(&path)
+            .with_context(|| format!("Unable to write {}", path.display()))?;
+        io::copy(reader, &mut file)?;
+        Ok(())
+    })?;
+
+    let path = dest.join(safe_path(&manifest.entry_name())?);
+    if let Some(parent) = path.parent() {
+        ensure_dir_exists(parent)?;
+    }
+    fs::write(&path, serde_json::to_vec_pretty(&manifest)?)?;
+    Ok((manifest, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const BUILD: &str = "11.0.5.57212";
+
+    fn create_test_tree() -> TempDir {
+        let temp_dir = TempDir::new().unwrap();
+        for (locale, name) in [("enUS", "Fireball"), ("frFR", "Boule de feu")] {
+            let dir = temp_dir.path().join(BUILD).join(locale);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("SpellName.csv"), format!("ID,Name_lang\n133,{}\n", name)).unwrap();
+            fs::write(dir.join("SpellName.filtered.csv"), "ID,Name_lang\n").unwrap();
+            fs::write(dir.join("SpellName.csv.tmp"), "ID,Name_lang\n").unwrap();
+        }
+        fs::create_dir_all(temp_dir.path().join(BUILD).join(".search")).unwrap();
+        temp_dir
+    }
+
+    fn round_trip(format: ArchiveFormat) {
+        let tree = create_test_tree();
+        let output = tree.path().join(format!("{}.{}", BUILD, format.extension()));
+        let manifest = create_archive(tree.path(), BUILD, &output, format).unwrap();
+        assert_eq!(manifest.locales, vec!["enUS", "frFR"]);
+        assert_eq!(manifest.tables, vec!["SpellName"]);
+        assert_eq!(manifest.files.len(), 4);
+
+        let (read, report) = verify_archive(&output).unwrap();
+        assert_eq!(read, manifest);
+        assert!(report.is_ok());
+        assert_eq!(report.verified, 4);
+
+        let dest = TempDir::new().unwrap();
+        let (_, report) = extract_archive(&output, dest.path()).unwrap();
+        assert!(report.is_ok());
+        let extracted = dest.path().join(BUILD).join("frFR").join("SpellName.csv");
+        assert_eq!(fs::read_to_string(extracted).unwrap(), "ID,Name_lang\n133,Boule de feu\n");
+        assert!(dest.path().join(BUILD).join(MANIFEST_FILE).exists());
+    }
+
+    #[test]
+    fn test_zip_round_trip() {
+        round_trip(ArchiveFormat::Zip);
+    }
+
+    #[test]
+    fn test_tar_zst_round_trip() {
+        round_trip(ArchiveFormat::TarZst);
+    }
+
+    #[test]
+    fn test_verify_detects_changes() {
+        let tree = create_test_tree();
+        let manifest = ArchiveManifest::from_tree(tree.path(), BUILD).unwrap();
+
+        let build_dir = tree.path().join(BUILD);
+        fs::write(build_dir.join("enUS").join("SpellName.csv"), "ID,Name_lang\n133,Pyroblast\n").unwrap();
+        fs::remove_file(build_dir.join("frFR").join("SpellName.filtered.csv")).unwrap();
+        fs::create_dir_all(build_dir.join("deDE")).unwrap();
+        fs::write(build_dir.join("deDE").join("SpellName.csv"), "ID,Name_lang\n").unwrap();
+
+        // Current files with the manifest of the original tree
+        let current = ArchiveManifest::from_tree(tree.path(), BUILD).unwrap();
+        let files: Vec<&String> = current.files.keys().collect();
+        let output = tree.path().join("changed.tar.zst");
+        write_archive(tree.path(), &manifest, &files, &output, ArchiveFormat::TarZst).unwrap();
+
+        let (_, report) = verify_archive(&output).unwrap();
+        assert_eq!(report.verified, 2);
+        assert_eq!(report.mismatched, vec![format!("{}/enUS/SpellName.csv", BUILD)]);
+        assert_eq!(report.missing, vec![format!("{}/frFR/SpellName.filtered.csv", BUILD)]);
+        assert_eq!(report.unexpected, vec![format!("{}/deDE/SpellName.csv", BUILD)]);
+        assert!(!report.is_ok());
+    }
+
+    #[test]
+    fn test_format_and_paths() {
+        assert_eq!(ArchiveFormat::from_path(Path::new("a/11.0.5.57212.TAR.ZST")).unwrap(), ArchiveFormat::TarZst);
+        assert!(ArchiveFormat::from_path(Path::new("dump.rar")).is_err());
+        assert!(safe_path("11.0.5.57212/enUS/Spell.csv").is_ok());
+        assert!(safe_path("../etc/passwd").is_err());
+        assert!(safe_path("/etc/passwd").is_err());
+    }
+}
@@ -0,0 +1,399 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::path::Path;
+use anyhow::Result;
+use crate::utils::{table_path, TableData};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ChangelogFormat {
+    Markdown,
+    Html,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableStatus {
+    Added,
+    Removed,
+    Modified,
+    Unchanged,
+}
+
+#[derive(Debug, Clone)]
+pub struct ModifiedRow {
+    pub id: String,
+    pub changed_fields: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct TableChange {
+    pub table: String,
+    pub status: TableStatus,
+    pub old_rows: usize,
+    pub new_rows: usize,
+    pub added_columns: Vec<String>,
+    pub removed_columns: Vec<String>,
+    pub added_rows: usize,
+    pub removed_rows: usize,
+    pub modified_rows: usize,
+    pub top_modified: Vec<ModifiedRow>,
+}
+
+impl TableChange {
+    pub fn row_delta(&self) -> i64 {
+        self.new_rows as i64 - self.old_rows as i64
+    }
+
+    pub fn schema_changed(&self) -> bool {
+        !self.added_columns.is_empty() || !self.removed_columns.is_empty()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Changelog {
+    pub old_build: String,
+    pub new_build: String,
+    pub locale: String,
+    pub tables: Vec<TableChange>,
+}
+
+impl Changelog {
+    pub fn build(
+        root: &Path,
+        old_build: &str,
+        new_build: &str,
+        locale: &str,
+        tables: &HashSet<String>,
+        top: usize,
+    ) -> Result<Self> {
+        let mut names: Vec<&String> = tables.iter().collect();
+        names.sort();
+
+        let mut changes = Vec::new();
+        for table in names {
+            let old_path = table_path(root, old_build, locale, table);
+            let new_path = table_path(root, new_build, locale, table);
+
+            let old = if old_path.exists() { Some(TableData::load(&old_path)?) } else { None };
+            let new = if new_path.exists() { Some(TableData::load(&new_path)?) } else { None };
+
+            if let Some(change) = compare_tables(table, old.as_ref(), new.as_ref(), top) {
+                changes.push(change);
+            }
+        }
+
+        Ok(Self {
+            old_build: old_build.to_string(),
+            new_build: new_build.to_string(),
+            locale: locale.to_string(),
+            tables: changes,
+        })
+    }
+
+    fn with_status(&self, status: TableStatus) -> impl Iterator<Item = &TableChange> {
+        self.tables.iter().filter(move |t| t.status == status)
+    }
+
+    pub fn render(&self, format: ChangelogFormat) -> String {
+        match format {
+            ChangelogFormat::Markdown => self.render_markdown(),
+            ChangelogFormat::Html => self.render_html(),
+        }
+    }
+
+    pub fn render_markdown(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# Changelog {} → {} ({})\n", self.old_build, self.new_build, self.locale);
+
+        let _ = writeln!(out, "## Summary\n");
+        for (label, status) in STATUS_LABELS {
+            let _ = writeln!(out, "- Tables {}: {}", label, self.with_status(status).count());
+        }
+
+        for (title, status) in [("Added tables", TableStatus::Added), ("Removed tables", TableStatus::Removed)] {
+            let tables: Vec<_> = self.with_status(status).collect();
+            if tables.is_empty() {
+                continue;
+            }
+            let _ = writeln!(out, "\n## {}\n", title);
+            for t in tables {
+                let rows = if status == TableStatus::Added { t.new_rows } else { t.old_rows };
+                let _ = writeln!(out, "- `{}` ({} rows)", t.table, rows);
+            }
+        }
+
+        let modified: Vec<_> = self.with_status(TableStatus::Modified).collect();
+        if !modified.is_empty() {
+            let _ = writeln!(out, "\n## Modified tables\n");
+            let _ = writeln!(out, "| Table | Rows | Delta | Added | Removed | Modified | Schema |");
+            let _ = writeln!(out, "|---|---:|---:|---:|---:|---:|---|");
+            for t in &modified {
+                let _ = writeln!(
+                    out,
+                    "| `{}` | {} | {:+} | {} | {} | {} | {} |",
+                    t.table, t.new_rows, t.row_delta(), t.added_rows, t.removed_rows,
+                    t.modified_rows, if t.schema_changed() { "changed" } else { "" }
+                );
+            }
+
+            for t in modified {
+                if !t.schema_changed() && t.top_modified.is_empty() {
+                    continue;
+                }
+                let _ = writeln!(out, "\n### {}\n", t.table);
+                if !t.added_columns.is_empty() {
+                    let _ = writeln!(out, "- Columns added: {}", code_list(&t.added_columns));
+                }
+                if !t.removed_columns.is_empty() {
+                    let _ = writeln!(out, "- Columns removed: {}", code_list(&t.removed_columns));
+                }
+                if !t.top_modified.is_empty() {
+                    let ids: Vec<String> = t.top_modified.iter()
+                        .map(|m| format!("{} ({} fields)", m.id, m.changed_fields))
+                        .collect();
+                    let _ = writeln!(out, "- Top modified IDs: {}", ids.join(", "));
+                }
+            }
+        }
+
+        out
+    }
+
+    pub fn render_html(&self) -> String {
+        let mut out = String::new();
+        let title = format!("Changelog {} → {} ({})", self.old_build, self.new_build, self.locale);
+        let _ = writeln!(out, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">");
+        let _ = writeln!(out, "<title>{}</title>\n</head>\n<body>", escape_html(&title));
+        let _ = writeln!(out, "<h1>{}</h1>", escape_html(&title));
+
+        let _ = writeln!(out, "<h2>Summary</h2>\n<ul>");
+        for (label, status) in STATUS_LABELS {
+            let _ = writeln!(out, "<li>Tables {}: {}</li>", label, self.with_status(status).count());
+        }
+        let _ = writeln!(out, "</ul>");
+
+        for (title, status) in [("Added tables", TableStatus::Added), ("Removed tables", TableStatus::Removed)] {
+            let tables: Vec<_> = self.with_status(status).collect();
+            if tables.is_empty() {
+                continue;
+            }
+            let _ = writeln!(out, "<h2>{}</h2>\n<ul>", title);
+            for t in tables {
+                let rows = if status == TableStatus::Added { t.new_rows } else { t.old_rows };
+                let _ = writeln!(out, "<li><code>{}</code> ({} rows)</li>", escape_html(&t.table), rows);
+            }
+            let _ = writeln!(out, "</ul>");
+        }
+
+        let modified: Vec<_> = self.with_status(TableStatus::Modified).collect();
+        if !modified.is_empty() {
+            let _ = writeln!(out, "<h2>Modified tables</h2>\n<table>");
+            let _ = writeln!(
+                out,
+                "<tr><th>Table</th><th>Rows</th><th>Delta</th><th>Added</th><th>Removed</th>\
+                 <th>Modified</th><th>Columns added</th><th>Columns removed</th><th>Top modified IDs</th></tr>"
+            );
+            for t in modified {
+                let ids: Vec<String> = t.top_modified.iter()
+                    .map(|m| format!("{} ({})", m.id, m.changed_fields))
+                    .collect();
+                let _ = writeln!(
+                    out,
+                    "<tr><td><code>{}</code></td><td>{}</td><td>{:+}</td><td>{}</td><td>{}</td>\
+                     <td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    escape_html(&t.table), t.new_rows, t.row_delta(), t.added_rows, t.removed_rows,
+                    t.modified_rows, escape_html(&t.added_columns.join(", ")),
+                    escape_html(&t.removed_columns.join(", ")), escape_html(&ids.join(", "))
+                );
+            }
+            let _ = writeln!(out, "</table>");
+        }
+
+        let _ = writeln!(out, "</body>\n</html>");
+        out
+    }
+}
+
+const STATUS_LABELS: [(&str, TableStatus); 4] = [
+    ("added", TableStatus::Added),
+    ("removed", TableStatus::Removed),
+    ("modified", TableStatus::Modified),
+    ("unchanged", TableStatus::Unchanged),
+];
+
+/// Compares two versions of a table. Returns `None` when the table exists in neither build.
+pub fn compare_tables(
+    table: &str,
+    old: Option<&TableData>,
+    new: Option<&TableData>,
+    top: usize,
+) -> Option<TableChange> {
+    let mut change = TableChange {
+        table: table.to_string(),
+        status: TableStatus::Unchanged,
+        old_rows: old.map_or(0, |t| t.rows.len()),
+        new_rows: new.map_or(0, |t| t.rows.len()),
+        added_columns: vec![],
+        removed_columns: vec![],
+        added_rows: 0,
+        removed_rows: 0,
+        modified_rows: 0,
+        top_modified: vec![],
+    };
+
+    let (old, new) = match (old, new) {
+        (None, None) => return None,
+        (None, Some(_)) => {
+            change.status = TableStatus::Added;
+            return Some(change);
+        }
+        (Some(_), None) => {
+            change.status = TableStatus::Removed;
+            return Some(change);
+        }
+        (Some(old), Some(new)) => (old, new),
+    };
+
+    change.added_columns = new.headers.iter()
+        .filter(|h| !old.headers.contains(h))
+        .cloned()
+        .collect();
+    change.removed_columns = old.headers.iter()
+        .filter(|h| !new.headers.contains(h))
+        .cloned()
+        .collect();
+
+    // Only the columns present in both builds take part in the row comparison
+    let shared: Vec<(usize, usize)> = old.headers.iter()
+        .enumerate()
+        .filter_map(|(i, h)| new.column(h).map(|j| (i, j)))
+        .collect();
+
+    let mut modified = Vec::new();
+    for (id, &new_index) in &new.ids {
+        match old.row(id) {
+            None => change.added_rows += 1,
+            Some(old_row) => {
+                let new_row = &new.rows[new_index];
+                let changed_fields = shared.iter()
+                    .filter(|&&(i, j)| old_row.get(i) != new_row.get(j))
+                    .count();
+                if changed_fields > 0 {
+                    modified.push(ModifiedRow { id: id.clone(), changed_fields });
+                }
+            }
+        }
+    }
+    change.removed_rows = old.ids.keys().filter(|id| !new.ids.contains_key(*id)).count();
+    change.modified_rows = modified.len();
+
+    modified.sort_by(|a, b| {
+        b.changed_fields.cmp(&a.changed_fields).then_with(|| compare_ids(&a.id, &b.id))
+    });
+    modified.truncate(top);
+    change.top_modified = modified;
+
+    if change.schema_changed() || change.added_rows + change.removed_rows + change.modified_rows > 0 {
+        change.status = TableStatus::Modified;
+    }
+    Some(change)
+}
+
+/// Numeric IDs sort numerically, anything else falls back to string order.
+pub fn compare_ids(a: &str, b: &str) -> Ordering {
+    match (a.parse::<i64>(), b.parse::<i64>()) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        _ => a.cmp(b),
+    }
+}
+
+fn code_list(items: &[String]) -> String {
+    items.iter().map(|i| format!("`{}`", i)).collect::<Vec<_>>().join(", ")
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn write_table(root: &Path, build: &str, table: &str, content: &str) {
+        let path = table_path(root, build, "enUS", table);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn create_test_changelog() -> Changelog {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        write_table(root, "11.0.5.57171", "Spell", "ID,Name,Flags\n1,Fireball,0\n2,Frostbolt,0\n3,Blink,1\n");
+        write_table(root, "11.0.5.57212", "Spell", "ID,Name,Flags,School\n1,Fireball,0,4\n2,Frost Bolt,2,16\n4,Polymorph,0,64\n");
+        write_table(root, "11.0.5.57171", "Item", "ID,Name\n1,Sword\n");
+        write_table(root, "11.0.5.57212", "Item", "ID,Name\n1,Sword\n");
+        write_table(root, "11.0.5.57171", "Achievement", "ID,Title\n1,First\n");
+        write_table(root, "11.0.5.57212", "Map", "ID,Directory\n1,Azeroth\n");
+
+        let tables = HashSet::from([
+            "Spell".to_string(),
+            "Item".to_string(),
+            "Achievement".to_string(),
+            "Map".to_string(),
+            "Missing".to_string(),
+        ]);
+        Changelog::build(root, "11.0.5.57171", "11.0.5.57212", "enUS", &tables, 10).unwrap()
+    }
+
+    #[test]
+    fn test_table_statuses() {
+        let changelog = create_test_changelog();
+        let status = |name: &str| changelog.tables.iter().find(|t| t.table == name).map(|t| t.status);
+
+        assert_eq!(status("Achievement"), Some(TableStatus::Removed));
+        assert_eq!(status("Map"), Some(TableStatus::Added));
+        assert_eq!(status("Item"), Some(TableStatus::Unchanged));
+        assert_eq!(status("Spell"), Some(TableStatus::Modified));
+        assert_eq!(status("Missing"), None);
+    }
+
+    #[test]
+    fn test_row_and_schema_changes() {
+        let changelog = create_test_changelog();
+        let spell = changelog.tables.iter().find(|t| t.table == "Spell").unwrap();
+
+        assert_eq!(spell.added_columns, vec!["School".to_string()]);
+        assert!(spell.removed_columns.is_empty());
+        assert_eq!(spell.added_rows, 1);
+        assert_eq!(spell.removed_rows, 1);
+        assert_eq!(spell.modified_rows, 1);
+        assert_eq!(spell.top_modified[0].id, "2");
+        assert_eq!(spell.top_modified[0].changed_fields, 2);
+        assert_eq!(spell.row_delta(), 0);
+    }
+
+    #[test]
+    fn test_render_formats() {
+        let changelog = create_test_changelog();
+
+        let markdown = changelog.render(ChangelogFormat::Markdown);
+        assert!(markdown.contains("- Tables modified: 1"));
+        assert!(markdown.contains("| `Spell` | 3 | +0 | 1 | 1 | 1 | changed |"));
+        assert!(markdown.contains("- Top modified IDs: 2 (2 fields)"));
+
+        let html = changelog.render(ChangelogFormat::Html);
+        assert!(html.contains("<li>Tables added: 1</li>"));
+        assert!(html.contains("<code>Map</code> (1 rows)"));
+    }
+
+    #[test]
+    fn test_compare_ids() {
+        assert_eq!(compare_ids("9", "10"), Ordering::Less);
+        assert_eq!(compare_ids("b", "a"), Ordering::Greater);
+    }
+}
@@ -0,0 +1,267 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use crate::services::source::is_transient;
+
+/// `--concurrency`: a fixed number of downloads, or `auto`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Concurrency {
+    Fixed(usize),
+    Auto,
+}
+
+impl std::str::FromStr for Concurrency {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Concurrency::Auto),
+            _ => match s.parse() {
+                Ok(count) if count > 0 => Ok(Concurrency::Fixed(count)),
+                _ => Err(anyhow::anyhow!("Invalid concurrency '{}', expected a positive number or auto", s)),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for Concurrency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Concurrency::Fixed(count) => write!(f, "{}", count),
+            Concurrency::Auto => write!(f, "auto"),
+        }
+    }
+}
+
+impl TryFrom<String> for Concurrency {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Concurrency> for String {
+    fn from(concurrency: Concurrency) -> Self {
+        concurrency.to_string()
+    }
+}
+
+/// Downloads completed since the last adjustment.
+struct Round {
+    started: Instant,
+    completed: usize,
+    bytes: u64,
+    latency: Duration,
+}
+
+impl Round {
+    fn new() -> Self {
+        Self { started: Instant::now(), completed: 0, bytes: 0, latency: Duration::ZERO }
+    }
+}
+
+struct State {
+    limit: usize,
+    /// Permits to drop when they are released, after a decrease
+    debt: usize,
+    round: Round,
+    previous_throughput: Option<f64>,
+    best_latency: Option<Duration>,
+}
+
+/// Bounds the downloads in flight. In adaptive mode the limit grows by one
+/// after each round of downloads that kept the throughput up, shrinks by one
+/// when responses get much slower, and is halved when the server answers
+/// 429 or 5xx or stops answering.
+pub struct ConcurrencyLimiter {
+    semaphore: Arc<Semaphore>,
+    /// (min, max) in adaptive mode
+    bounds: Option<(usize, usize)>,
+    state: Mutex<State>,
+}
+
+pub struct ConcurrencyPermit<'a> {
+    limiter: &'a ConcurrencyLimiter,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for ConcurrencyPermit<'_> {
+    fn drop(&mut self) {
+        let mut state = self.limiter.state.lock().unwrap();
+        if state.debt > 0 {
+            state.debt -= 1;
+            if let Some(permit) = self.permit.take() {
+                permit.forget();
+            }
+        }
+    }
+}
+
+impl ConcurrencyLimiter {
+    pub fn fixed(count: usize) -> Self {
+        Self::new(count.max(1), None)
+    }
+
+    /// Starts at `initial` and stays within `min..=max`.
+    pub fn adaptive(initial: usize, min: usize, max: usize) -> Self {
+        let min = min.max(1);
+        let max = max.max(min);
+        Self::new(initial.clamp(min, max), Some((min, max)))
+    }
+
+    fn new(limit: usize, bounds: Option<(usize, usize)>) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(limit)),
+            bounds,
+            state: Mutex::new(State {
+                limit,
+                debt: 0,
+                round: Round::new(),
+                previous_throughput: None,
+                best_latency: None,
+            }),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit
+    }
+
+    /// Highest limit the downloads can reach.
+    pub fn max(&self) -> usize {
+        self.bounds.map(|(_, max)| max).unwrap_or_else(|| self.limit())
+    }
+
+    pub async fn acquire(&self) -> ConcurrencyPermit<'_> {
+        let permit = Arc::clone(&self.semaphore).acquire_owned().await.unwrap();
+        ConcurrencyPermit { limiter: self, permit: Some(permit) }
+    }
+
+    /// A download completed, `latency` being the time to its response headers.
+    pub fn record_success(&self, latency: Duration, bytes: u64) {
+        let Some((min, max)) = self.bounds else { return };
+        let mut state = self.state.lock().unwrap();
+        state.round.completed += 1;
+        state.round.bytes += bytes;
+        state.round.latency += latency;
+        if state.round.completed < state.limit {
+            return;
+        }
+
+        let elapsed = state.round.started.elapsed().as_secs_f64().max(0.001);
+        let throughput = state.round.bytes as f64 / elapsed;
+        let latency = state.round.latency / state.round.completed as u32;
+        let best_latency = state.best_latency.map_or(latency, |best| best.min(latency));
+        let limit = if latency > best_latency * 3 {
+            state.limit.saturating_sub(1)
+        } else {
+            match state.previous_throughput {
+                Some(previous) if throughput < previous * 0.8 => state.limit.saturating_sub(1),
+                Some(previous) if throughput < previous * 0.95 => state.limit,
+                _ => state.limit + 1,
+            }
+        };
+        state.best_latency = Some(best_latency);
+        state.previous_throughput = Some(throughput);
+        state.round = Round::new();
+        self.set_limit(&mut state, limit.clamp(min, max));
+    }
+
+    /// A download failed, once its retries are exhausted. Only transient
+    /// errors, which tell that the server is overloaded or unreachable,
+    /// change the limit.
+    pub fn record_failure(&self, error: &anyhow::Error) {
+        let Some((min, max)) = self.bounds else { return };
+        if !is_transient(error) {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        let limit = (state.limit / 2).clamp(min, max);
+        state.previous_throughput = None;
+        state.round = Round::new();
+        self.set_limit(&mut state, limit);
+    }
+
+    fn set_limit(&self, state: &mut State, limit: usize) {
+        if limit > state.limit {
+            let added = limit - state.limit;
+            let repaid = added.min(state.debt);
+            state.debt -= repaid;
+            self.semaphore.add_permits(added - repaid);
+        } else if limit < state.limit {
+            let removed = state.limit - limit;
+            let forgotten = self.semaphore.forget_permits(removed);
+            state.debt += removed - forgotten;
+        }
+        state.limit = limit;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::source::StatusError;
+
+    fn status_error(status: u16) -> anyhow::Error {
+        StatusError { table: "Spell".to_string(), status: reqwest::StatusCode::from_u16(status).unwrap() }.into()
+    }
+
+    #[test]
+    fn test_parse_concurrency() {
+        assert_eq!("auto".parse::<Concurrency>().unwrap(), Concurrency::Auto);
+        assert_eq!("8".parse::<Concurrency>().unwrap(), Concurrency::Fixed(8));
+        assert!("0".parse::<Concurrency>().is_err());
+        assert!("many".parse::<Concurrency>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fixed_limit() {
+        let limiter = ConcurrencyLimiter::fixed(2);
+        limiter.record_failure(&status_error(429));
+        limiter.record_success(Duration::from_millis(10), 100);
+        assert_eq!(limiter.limit(), 2);
+        assert_eq!(limiter.max(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_adaptive_limit() {
+        let limiter = ConcurrencyLimiter::adaptive(4, 1, 6);
+        assert_eq!(limiter.max(), 6);
+
+        // A round of successes grows the limit
+        for _ in 0..4 {
+            limiter.record_success(Duration::from_millis(10), 1000);
+        }
+        assert_eq!(limiter.limit(), 5);
+
+        // Overloaded server
+        limiter.record_failure(&status_error(503));
+        assert_eq!(limiter.limit(), 2);
+        limiter.record_failure(&status_error(429));
+        limiter.record_failure(&status_error(429));
+        assert_eq!(limiter.limit(), 1);
+        // Not a load issue
+        limiter.record_failure(&status_error(404));
+        assert_eq!(limiter.limit(), 1);
+
+        limiter.record_success(Duration::from_millis(10), 1000);
+        assert_eq!(limiter.limit(), 2);
+
+        // Much slower responses
+        limiter.record_success(Duration::from_millis(100), 1000);
+        limiter.record_success(Duration::from_millis(100), 1000);
+        assert_eq!(limiter.limit(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_decrease_with_permits_in_use() {
+        let limiter = ConcurrencyLimiter::adaptive(4, 1, 4);
+        let permits: Vec<_> = futures::future::join_all((0..4).map(|_| limiter.acquire())).await;
+        limiter.record_failure(&status_error(500));
+        assert_eq!(limiter.limit(), 2);
+        drop(permits);
+        assert_eq!(limiter.semaphore.available_permits(), 2);
+    }
+}
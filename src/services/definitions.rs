@@ -0,0 +1,455 @@
+use std::collections::HashMap;
+use std::path::Path;
+use anyhow::{Context, Result};
+use crate::entities::Build;
+use crate::services::schema::ColumnType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    Int,
+    Float,
+    String,
+    LocString,
+}
+
+impl FieldType {
+    fn parse(name: &str) -> Result<Self> {
+        match name {
+            "int" => Ok(FieldType::Int),
+            "float" => Ok(FieldType::Float),
+            "string" => Ok(FieldType::String),
+            "locstring" => Ok(FieldType::LocString),
+            other => Err(anyhow::anyhow!("Unknown column type '{}'", other)),
+        }
+    }
+
+    pub fn column_type(self) -> ColumnType {
+        match self {
+            FieldType::Int => ColumnType::Int,
+            FieldType::Float => ColumnType::Float,
+            FieldType::String | FieldType::LocString => ColumnType::String,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForeignKey {
+    pub table: String,
+    pub column: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ColumnDefinition {
+    pub name: String,
+    pub kind: FieldType,
+    pub foreign_key: Option<ForeignKey>,
+    pub verified: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildRange {
+    Exact(Build),
+    Range(Build, Build),
+}
+
+impl BuildRange {
+    pub fn contains(&self, build: &Build) -> bool {
+        match self {
+            BuildRange::Exact(b) => b == build,
+            BuildRange::Range(min, max) => min <= build && build <= max,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DefinitionField {
+    pub name: String,
+    pub size: Option<u8>,
+    pub signed: bool,
+    pub array_size: Option<usize>,
+    pub is_id: bool,
+    pub is_relation: bool,
+    pub is_noninline: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct VersionDefinition {
+    pub builds: Vec<BuildRange>,
+    pub layouts: Vec<String>,
+    pub fields: Vec<DefinitionField>,
+}
+
+/// Contents of a WoWDBDefs `.dbd` file.
+#[derive(Debug, Clone)]
+pub struct TableDefinition {
+    pub name: String,
+    pub columns: HashMap<String, ColumnDefinition>,
+    pub versions: Vec<VersionDefinition>,
+}
+
+impl TableDefinition {
+    pub fn load(path: &Path) -> Result<Self> {
+        let name = path.file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let content = std::fs::read_to_string(path)?;
+        Self::parse(&name, &content).with_context(|| format!("Invalid definition {}", path.display()))
+    }
+
+    pub fn parse(name: &str, content: &str) -> Result<Self> {
+        let mut columns = HashMap::new();
+        let mut versions = Vec::new();
+        let mut current: Option<VersionDefinition> = None;
+        let mut in_columns = false;
+
+        for raw in content.lines() {
+            let line = strip_comment(raw);
+
+            if line.is_empty() {
+                in_columns = false;
+                if let Some(version) = current.take() {
+                    versions.push(version);
+                }
+                continue;
+            }
+            if line == "COLUMNS" {
+                in_columns = true;
+                continue;
+            }
+            if in_columns {
+                let column = parse_column(line)?;
+                columns.insert(column.name.clone(), column);
+                continue;
+            }
+
+            let version = current.get_or_insert_with(VersionDefinition::default);
+            if let Some(layouts) = line.strip_prefix("LAYOUT ") {
+                version.layouts.extend(layouts.split(',').map(|l| l.trim().to_string()));
+            } else if let Some(builds) = line.strip_prefix("BUILD ") {
+                for entry in builds.split(',') {
+                    version.builds.push(parse_build_range(entry.trim())?);
+                }
+            } else if line.starts_with("COMMENT") {
+                continue;
+            } else {
+                let field = parse_field(line)?;
+                if !columns.contains_key(&field.name) {
+                    return Err(anyhow::anyhow!("Field '{}' is missing from COLUMNS", field.name));
+                }
+                version.fields.push(field);
+            }
+        }
+        if let Some(version) = current {
+            versions.push(version);
+        }
+
+        Ok(Self { name: name.to_string(), columns, versions })
+    }
+
+    pub fn version_for(&self, build: &Build) -> Option<&VersionDefinition> {
+        self.versions.iter().find(|v| v.builds.iter().any(|r| r.contains(build)))
+    }
+
+    pub fn layout_for(&self, build: &Build) -> Option<TableLayout> {
+        let version = self.version_for(build)?;
+        let fields = version.fields.iter()
+            .map(|field| {
+                let column = &self.columns[&field.name];
+                LayoutField {
+                    name: field.name.clone(),
+                    kind: column.kind,
+                    size: field.size,
+                    signed: field.signed,
+                    array_size: field.array_size,
+                    is_id: field.is_id,
+                    foreign_key: column.foreign_key.clone(),
+                    verified: column.verified,
+                }
+            })
+            .collect();
+        Some(TableLayout { table: self.name.clone(), fields })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LayoutField {
+    pub name: String,
+    pub kind: FieldType,
+    pub size: Option<u8>,
+    pub signed: bool,
+    pub array_size: Option<usize>,
+    pub is_id: bool,
+    pub foreign_key: Option<ForeignKey>,
+    pub verified: bool,
+}
+
+impl LayoutField {
+    pub fn is_localized(&self) -> bool {
+        self.kind == FieldType::LocString
+    }
+
+    /// Header names of this field in the wago.tools CSV export, arrays being split as `Name_0`, `Name_1`, ...
+    pub fn csv_columns(&self) -> Vec<String> {
+        match self.array_size {
+            Some(size) => (0..size).map(|i| format!("{}_{}", self.name, i)).collect(),
+            None => vec![self.name.clone()],
+        }
+    }
+}
+
+/// Structure of a table for one specific build.
+#[derive(Debug, Clone)]
+pub struct TableLayout {
+    pub table: String,
+    pub fields: Vec<LayoutField>,
+}
+
+impl TableLayout {
+    pub fn is_locale_dependent(&self) -> bool {
+        self.fields.iter().any(LayoutField::is_localized)
+    }
+
+    pub fn localized_columns(&self) -> Vec<String> {
+        self.fields.iter()
+            .filter(|f| f.is_localized())
+            .flat_map(LayoutField::csv_columns)
+            .collect()
+    }
+}
+
+/// Set of `.dbd` files read from a local WoWDBDefs checkout.
+#[derive(Debug, Clone, Default)]
+pub struct Definitions {
+    tables: HashMap<String, TableDefinition>,
+}
+
+impl Definitions {
+    /// Reads the `.dbd` files of `dir`, skipping those that cannot be parsed.
+    pub fn load_dir(dir: &Path) -> Result<Self> {
+        let mut tables = HashMap::new();
+        let entries = std::fs::read_dir(dir)
+            .with_context(|| format!("Unable to read definitions from {}", dir.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "dbd") {
+                match TableDefinition::load(&path) {
+                    Ok(definition) => {
+                        tables.insert(definition.name.clone(), definition);
+                    }
+                    Err(e) => tracing::warn!("Skipping {:#}", e),
+                }
+            }
+        }
+        Ok(Self { tables })
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    pub fn get(&self, table: &str) -> Option<&TableDefinition> {
+        self.tables.get(table)
+    }
+
+    pub fn layout(&self, table: &str, build: &Build) -> Option<TableLayout> {
+        self.get(table)?.layout_for(build)
+    }
+
+    /// Tables with at least one localized string column in `build`, sorted.
+    pub fn locale_dependent_tables(&self, build: &Build) -> Vec<String> {
+        let mut tables: Vec<String> = self.tables.values()
+            .filter_map(|d| d.layout_for(build))
+            .filter(TableLayout::is_locale_dependent)
+            .map(|l| l.table)
+            .collect();
+        tables.sort();
+        tables
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    match line.find("//") {
+        Some(i) => line[..i].trim(),
+        None => line.trim(),
+    }
+}
+
+fn parse_column(line: &str) -> Result<ColumnDefinition> {
+    let (kind, name) = line.split_once(char::is_whitespace)
+        .ok_or_else(|| anyhow::anyhow!("Invalid column line '{}'", line))?;
+
+    let (kind, foreign_key) = match kind.split_once('<') {
+        Some((kind, fk)) => {
+            let fk = fk.trim_end_matches('>');
+            let (table, column) = fk.split_once("::")
+                .ok_or_else(|| anyhow::anyhow!("Invalid foreign key '{}'", fk))?;
+            (kind, Some(ForeignKey { table: table.to_string(), column: column.to_string() }))
+        }
+        None => (kind, None),
+    };
+
+    let name = name.trim();
+    let verified = !name.ends_with('?');
+    Ok(ColumnDefinition {
+        name: name.trim_end_matches('?').to_string(),
+        kind: FieldType::parse(kind)?,
+        foreign_key,
+        verified,
+    })
+}
+
+fn parse_build_range(entry: &str) -> Result<BuildRange> {
+    match entry.split_once('-') {
+        Some((min, max)) => Ok(BuildRange::Range(min.parse()?, max.parse()?)),
+        None => Ok(BuildRange::Exact(entry.parse()?)),
+    }
+}
+
+fn parse_field(line: &str) -> Result<DefinitionField> {
+    let mut rest = line;
+    let mut annotations: Vec<&str> = vec![];
+    if let Some(stripped) = rest.strip_prefix('$') {
+        let (list, tail) = stripped.split_once('$')
+            .ok_or_else(|| anyhow::anyhow!("Invalid annotations in '{}'", line))?;
+        annotations = list.split(',').map(str::trim).collect();
+        rest = tail;
+    }
+
+    let name_end = rest.find(['<', '[']).unwrap_or(rest.len());
+    let name = rest[..name_end].to_string();
+    rest = &rest[name_end..];
+
+    let mut size = None;
+    let mut signed = true;
+    if let Some(stripped) = rest.strip_prefix('<') {
+        let (spec, tail) = stripped.split_once('>')
+            .ok_or_else(|| anyhow::anyhow!("Invalid size in '{}'", line))?;
+        let spec = match spec.strip_prefix('u') {
+            Some(unsigned) => {
+                signed = false;
+                unsigned
+            }
+            None => spec,
+        };
+        size = Some(spec.parse().with_context(|| format!("Invalid size in '{}'", line))?);
+        rest = tail;
+    }
+
+    let mut array_size = None;
+    if let Some(stripped) = rest.strip_prefix('[') {
+        let count = stripped.trim_end_matches(']');
+        array_size = Some(count.parse().with_context(|| format!("Invalid array size in '{}'", line))?);
+    }
+
+    Ok(DefinitionField {
+        name,
+        size,
+        signed,
+        array_size,
+        is_id: annotations.contains(&"id"),
+        is_relation: annotations.contains(&"relation"),
+        is_noninline: annotations.contains(&"noninline"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPELL_MISC: &str = "COLUMNS
+int ID
+int<Spell::ID> SpellID
+int<SpellIcon::ID> SpellIconFileDataID
+locstring Name_lang // only in old builds
+float Speed
+int Attributes
+int Unknown?
+
+LAYOUT 0DFB4941
+BUILD 7.3.5.25600-7.3.5.26972
+$id$ID<32>
+Name_lang
+Speed
+$relation$SpellID<32>
+
+LAYOUT 10EAF6D1
+BUILD 11.0.2.55665-11.1.0.59888
+BUILD 11.1.5.59735
+COMMENT Attributes were widened in 11.0
+$noninline,id$ID<32>
+Attributes<32>[16]
+SpellIconFileDataID<u32>
+Speed
+Unknown<u8>
+$relation$SpellID<32>
+";
+
+    fn build(s: &str) -> Build {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_columns() {
+        let definition = TableDefinition::parse("SpellMisc", SPELL_MISC).unwrap();
+        assert_eq!(definition.columns.len(), 7);
+        assert_eq!(definition.versions.len(), 2);
+
+        let spell_id = &definition.columns["SpellID"];
+        assert_eq!(spell_id.kind, FieldType::Int);
+        assert_eq!(spell_id.foreign_key, Some(ForeignKey { table: "Spell".into(), column: "ID".into() }));
+        assert!(!definition.columns["Unknown"].verified);
+        assert_eq!(definition.columns["Name_lang"].kind, FieldType::LocString);
+    }
+
+    #[test]
+    fn test_parse_fields() {
+        let definition = TableDefinition::parse("SpellMisc", SPELL_MISC).unwrap();
+        let version = &definition.versions[1];
+        assert_eq!(version.layouts, vec!["10EAF6D1".to_string()]);
+        assert_eq!(version.builds.len(), 2);
+
+        assert!(version.fields[0].is_id && version.fields[0].is_noninline);
+        assert_eq!(version.fields[1].array_size, Some(16));
+        assert_eq!(version.fields[2].size, Some(32));
+        assert!(!version.fields[2].signed);
+        assert!(version.fields[5].is_relation);
+    }
+
+    #[test]
+    fn test_layout_matching() {
+        let definition = TableDefinition::parse("SpellMisc", SPELL_MISC).unwrap();
+
+        let old = definition.layout_for(&build("7.3.5.26124")).unwrap();
+        assert!(old.is_locale_dependent());
+        assert_eq!(old.localized_columns(), vec!["Name_lang".to_string()]);
+
+        let current = definition.layout_for(&build("11.0.5.57212")).unwrap();
+        assert!(!current.is_locale_dependent());
+        assert_eq!(current.fields[1].csv_columns().len(), 16);
+        assert_eq!(current.fields[1].csv_columns()[15], "Attributes_15");
+        assert_eq!(current.fields[3].kind.column_type(), ColumnType::Float);
+        assert!(!current.fields[4].verified);
+
+        assert!(definition.layout_for(&build("11.1.5.59735")).is_some());
+        assert!(definition.layout_for(&build("11.1.5.60000")).is_none());
+        assert!(definition.layout_for(&build("10.2.7.55000")).is_none());
+    }
+
+    #[test]
+    fn test_unknown_field_is_rejected() {
+        let content = "COLUMNS\nint ID\n\nBUILD 11.0.5.57212\n$id$ID<32>\nMissing<32>\n";
+        assert!(TableDefinition::parse("Broken", content).is_err());
+    }
+
+    #[test]
+    fn test_malformed_file_is_skipped() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("SpellMisc.dbd"), SPELL_MISC).unwrap();
+        std::fs::write(temp_dir.path().join("Broken.dbd"), "COLUMNS\nint ID\n\nBUILD 11.0.5.57212\nMissing<32>\n").unwrap();
+
+        let definitions = Definitions::load_dir(temp_dir.path()).unwrap();
+        assert_eq!(definitions.len(), 1);
+        assert!(definitions.get("SpellMisc").is_some());
+        assert!(definitions.get("Broken").is_none());
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use crate::entities::Build;
+use crate::services::definitions::Definitions;
+use crate::services::schema::BuildSchema;
+
+/// Table a column points to by naming convention: `SpellID` → `Spell`, `ParentSpellID_1` → `Spell`.
+/// Leading words are dropped until a known table name is found.
+pub fn referenced_table(column: &str, tables: &HashSet<String>) -> Option<String> {
+    let column = match column.rsplit_once('_') {
+        Some((name, index)) if index.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => column,
+    };
+    let base = column.strip_suffix("ID")?;
+
+    let mut candidate = base;
+    while !candidate.is_empty() {
+        if tables.contains(candidate) {
+            return Some(candidate.to_string());
+        }
+        // Skip to the next uppercase letter, i.e. drop the leading word
+        candidate = match candidate.char_indices().skip(1).find(|(_, c)| c.is_ascii_uppercase()) {
+            Some((i, _)) => &candidate[i..],
+            None => "",
+        };
+    }
+    None
+}
+
+/// References between tables, from foreign keys and column-name conventions.
+#[derive(Debug, Clone, Default)]
+pub struct DependencyGraph {
+    references: HashMap<String, BTreeSet<String>>,
+}
+
+impl DependencyGraph {
+    /// Adds the references of `table` found in its columns, an explicit foreign key
+    /// winning over the naming convention.
+    pub fn add_columns<'a>(
+        &mut self,
+        table: &str,
+        columns: impl IntoIterator<Item = (&'a str, Option<&'a str>)>,
+        known_tables: &HashSet<String>,
+    ) {
+        let references = self.references.entry(table.to_string()).or_default();
+        for (column, foreign_key) in columns {
+            let referenced = match foreign_key {
+                Some(fk) => Some(fk.to_string()),
+                None => referenced_table(column, known_tables),
+            };
+            if let Some(referenced) = referenced.filter(|r| r != table) {
+                references.insert(referenced);
+            }
+        }
+    }
+
+    pub fn add_definitions(&mut self, definitions: &Definitions, build: &Build, known_tables: &HashSet<String>) {
+        for table in known_tables {
+            let Some(layout) = definitions.layout(table, build) else {
+                continue;
+            };
+            let columns: Vec<(String, Option<String>)> = layout.fields.iter()
+                .map(|f| (f.name.clone(), f.foreign_key.as_ref().map(|fk| fk.table.clone())))
+                .collect();
+            self.add_columns(
+                table,
+                columns.iter().map(|(c, fk)| (c.as_str(), fk.as_deref())),
+                known_tables,
+            );
+        }
+    }
+
+    pub fn add_schema(&mut self, schema: &BuildSchema, known_tables: &HashSet<String>) {
+        for (table, table_schema) in &schema.tables {
+            let columns = table_schema.columns.iter().map(|c| {
+                let fk = c.foreign_key.as_deref().and_then(|fk| fk.split("::").next());
+                (c.name.as_str(), fk)
+            });
+            self.add_columns(table, columns, known_tables);
+        }
+    }
+
+    pub fn is_known(&self, table: &str) -> bool {
+        self.references.contains_key(table)
+    }
+
+    pub fn references(&self, table: &str) -> impl Iterator<Item = &String> {
+        self.references.get(table).into_iter().flatten()
+    }
+
+    /// Tables of the `<table>*` family pointing back to `table`, e.g. `SpellMisc` for `Spell`.
+    pub fn dependents<'a>(&'a self, table: &'a str) -> impl Iterator<Item = &'a String> {
+        self.references.iter()
+            .filter(move |(name, refs)| name.as_str() != table && name.starts_with(table) && refs.contains(table))
+            .map(|(name, _)| name)
+    }
+
+    /// `requested` tables plus everything reachable from them in at most `depth` steps.
+    pub fn expand(&self, requested: &[String], depth: usize) -> BTreeSet<String> {
+        let mut selected: BTreeSet<String> = requested.iter().cloned().collect();
+        let mut frontier: Vec<String> = requested.to_vec();
+
+        for _ in 0..depth {
+            let mut next = Vec::new();
+            for table in &frontier {
+                for related in self.references(table).chain(self.dependents(table)) {
+                    if selected.insert(related.clone()) {
+                        next.push(related.clone());
+                    }
+                }
+            }
+            if next.is_empty() {
+                break;
+            }
+            frontier = next;
+        }
+
+        selected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn known_tables() -> HashSet<String> {
+        ["Spell", "SpellMisc", "SpellEffect", "SpellIcon", "SpellVisualKit", "Item", "Map", "FileData"]
+            .iter()
+            .map(|t| t.to_string())
+            .collect()
+    }
+
+    fn create_test_graph() -> DependencyGraph {
+        let known = known_tables();
+        let mut graph = DependencyGraph::default();
+        graph.add_columns("Spell", [("ID", None), ("NameSubtext_lang", None)], &known);
+        graph.add_columns("SpellMisc", [("ID", None), ("SpellID", None), ("SpellIconFileDataID", Some("FileData"))], &known);
+        graph.add_columns("SpellEffect", [("ID", None), ("SpellID", None), ("EffectItemType", None), ("TriggerSpellID_0", None)], &known);
+        graph.add_columns("Item", [("ID", None), ("IconFileDataID", None)], &known);
+        graph.add_columns("FileData", [("ID", None), ("MapID", None)], &known);
+        graph
+    }
+
+    #[test]
+    fn test_referenced_table_convention() {
+        let known = known_tables();
+        assert_eq!(referenced_table("SpellID", &known), Some("Spell".to_string()));
+        assert_eq!(referenced_table("TriggerSpellID_2", &known), Some("Spell".to_string()));
+        assert_eq!(referenced_table("SpellVisualKitID", &known), Some("SpellVisualKit".to_string()));
+        assert_eq!(referenced_table("IconFileDataID", &known), Some("FileData".to_string()));
+        assert_eq!(referenced_table("ID", &known), None);
+        assert_eq!(referenced_table("Flags", &known), None);
+        assert_eq!(referenced_table("CreatureID", &known), None);
+    }
+
+    #[test]
+    fn test_expand_by_depth() {
+        let graph = create_test_graph();
+        let requested = vec!["Spell".to_string()];
+
+        let none: Vec<String> = graph.expand(&requested, 0).into_iter().collect();
+        assert_eq!(none, vec!["Spell"]);
+
+        let direct: Vec<String> = graph.expand(&requested, 1).into_iter().collect();
+        assert_eq!(direct, vec!["Spell", "SpellEffect", "SpellMisc"]);
+
+        let deep: Vec<String> = graph.expand(&requested, 3).into_iter().collect();
+        assert_eq!(deep, vec!["FileData", "Map", "Spell", "SpellEffect", "SpellMisc"]);
+    }
+
+    #[test]
+    fn test_item_is_not_a_spell_dependent() {
+        let graph = create_test_graph();
+        let items: Vec<String> = graph.expand(&["Item".to_string()], 1).into_iter().collect();
+        assert_eq!(items, vec!["FileData", "Item"]);
+        assert!(!graph.is_known("Map"));
+    }
+}
@@ -1,36 +1,342 @@
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use anyhow::Result;
-use reqwest::Client;
-use tokio::time::Duration;
-use crate::utils::RateLimiter;
+use tokio::time::{Duration, Instant};
+use crate::utils::{next_opening, set_progress_bar, BandwidthLimiter, RateLimiter, TimeWindow};
 use crate::entities::Build;
-use std::collections::HashSet;
-use crate::utils::{file_exists_with_size, ensure_dir_exists};
-use tokio::sync::Semaphore;
+use std::collections::{BTreeMap, HashSet};
+use chrono::{Local, SecondsFormat, Utc};
+use serde::Serialize;
+use crate::utils::{file_exists_with_size, ensure_dir_exists, part_path, table_path, Compression, TableWriter};
+use crate::utils::{ContentDecoder, CountingWriter};
+use crate::services::concurrency::ConcurrencyLimiter;
+use crate::services::metrics::DownloadMetrics;
+use crate::services::hooks::{spawn_group_hooks, GroupEvent, HooksConfig};
+use crate::services::download_task::DownloadTask;
+use crate::services::scheduler::{schedule_tasks, TableOrder};
+use crate::services::source::{is_transient, DataSource, StatusError, TableStream};
+use tokio_util::sync::CancellationToken;
 use std::sync::Arc;
 use indicatif::{ProgressBar, ProgressStyle};
-use futures::future::join_all; 
+use futures::StreamExt;
+use tracing::Instrument;
+use std::io::Write;
 
 pub struct DownloadService {
-    client: Client,
-    base_url: String,
+    source: Arc<dyn DataSource>,
+    output_dir: PathBuf,
     rate_limiter: RateLimiter,
     max_retries: u32,
     retry_delay_secs: u64,
     max_concurrent_downloads: usize,
+    /// (min, max) when the number of downloads adapts to the server
+    concurrency_bounds: Option<(usize, usize)>,
+    compression: Compression,
+    bandwidth: BandwidthLimiter,
+    windows: Vec<TimeWindow>,
+    cancel: CancellationToken,
+    shutdown_timeout: Duration,
+    order: TableOrder,
+    priority_locale: Option<String>,
+    metrics: Arc<DownloadMetrics>,
+    hooks: HooksConfig,
+}
+
+/// Outcome of a download run, summed over every file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DownloadReport {
+    pub downloaded: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    /// Not downloaded because the run was cancelled
+    pub cancelled: usize,
+    /// Response bodies as received, before content decoding
+    pub bytes_on_wire: u64,
+    /// CSV data once decoded
+    pub bytes_decoded: u64,
+    /// Files written to disk, after storage compression
+    pub bytes_written: u64,
+}
+
+impl DownloadReport {
+    pub fn add(&mut self, other: &DownloadReport) {
+        self.downloaded += other.downloaded;
+        self.skipped += other.skipped;
+        self.failed += other.failed;
+        self.cancelled += other.cancelled;
+        self.bytes_on_wire += other.bytes_on_wire;
+        self.bytes_decoded += other.bytes_decoded;
+        self.bytes_written += other.bytes_written;
+    }
+
+    pub fn summary(&self) -> String {
+        let mb = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+        let saved = if self.bytes_decoded > 0 {
+            100.0 - self.bytes_on_wire as f64 * 100.0 / self.bytes_decoded as f64
+        } else {
+            0.0
+        };
+        let cancelled = if self.cancelled > 0 {
+            format!(", {} cancelled", self.cancelled)
+        } else {
+            String::new()
+        };
+        format!(
+            "{} downloaded, {} skipped, {} failed{}. {:.1} MB on the wire for {:.1} MB of CSV ({:.0}% saved by transfer compression), {:.1} MB written",
+            self.downloaded, self.skipped, self.failed, cancelled,
+            mb(self.bytes_on_wire), mb(self.bytes_decoded), saved, mb(self.bytes_written)
+        )
+    }
+}
+
+/// Files of one build and locale.
+#[derive(Debug, Clone, Serialize)]
+pub struct LocaleReport {
+    pub build: String,
+    pub locale: String,
+    #[serde(flatten)]
+    pub report: DownloadReport,
+}
+
+/// `download_all` outcome, in total and per build and locale.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunReport {
+    pub started_at: String,
+    pub finished_at: String,
+    pub total: DownloadReport,
+    pub locales: Vec<LocaleReport>,
+    /// `after_locale` and `after_build` hooks that failed during the run
+    #[serde(skip)]
+    pub hooks_failed: usize,
+}
+
+/// Streams a fetched table to `path`, decoding its transfer compression and
+/// applying the storage one on the way.
+/// The table is written to a `.part` file renamed once complete, and removed
+/// on error so the next run downloads it again.
+pub async fn save_table(fetched: TableStream, path: &Path, compression: Compression) -> Result<DownloadReport> {
+    let TableStream { content_encoding, mut body, .. } = fetched;
+    let part = part_path(path);
+    let result = async {
+        let table = CountingWriter::new(TableWriter::create(&part, compression)?);
+        let mut writer = ContentDecoder::new(content_encoding.as_deref(), table)?;
+        let mut bytes_on_wire = 0;
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk?;
+            bytes_on_wire += chunk.len() as u64;
+            writer.write_all(&chunk)?;
+        }
+        let table = writer.finish()?;
+        let bytes_decoded = table.count();
+        table.into_inner().finish()?;
+        fs::rename(&part, path)?;
+        Ok(DownloadReport {
+            downloaded: 1,
+            bytes_on_wire,
+            bytes_decoded,
+            bytes_written: fs::metadata(path)?.len(),
+            ..Default::default()
+        })
+    }.await;
+    if result.is_err() {
+        let _ = fs::remove_file(&part);
+    }
+    result
+}
+
+/// What the download tasks of a run share.
+struct TaskContext {
+    source: Arc<dyn DataSource>,
+    output_dir: PathBuf,
+    rate_limiter: RateLimiter,
+    limiter: Arc<ConcurrencyLimiter>,
+    bandwidth: BandwidthLimiter,
+    windows: Vec<TimeWindow>,
+    compression: Compression,
+    progress: ProgressBar,
+    cancel: CancellationToken,
+    shutdown_timeout: Duration,
+    max_retries: u32,
+    retry_delay: Duration,
+    metrics: Arc<DownloadMetrics>,
+}
+
+/// Downloads one table within a `download` span that records its last
+/// attempt, response status and latency.
+async fn run_task(context: Arc<TaskContext>, task: DownloadTask) -> Result<DownloadReport> {
+    let span = tracing::info_span!(
+        "download",
+        table = %task.table,
+        build = %task.build,
+        locale = %task.locale,
+        attempt = tracing::field::Empty,
+        status = tracing::field::Empty,
+        latency_ms = tracing::field::Empty,
+    );
+    let metrics = Arc::clone(&context.metrics);
+    let result = download_task(context, task).instrument(span).await;
+    match &result {
+        Ok(report) if report.downloaded > 0 => {
+            metrics.record_file("downloaded");
+            metrics.record_bytes(report.bytes_on_wire, report.bytes_written);
+        }
+        Ok(report) if report.skipped > 0 => metrics.record_file("skipped"),
+        Ok(_) => metrics.record_file("cancelled"),
+        Err(_) => metrics.record_file("failed"),
+    }
+    result
+}
+
+async fn download_task(context: Arc<TaskContext>, task: DownloadTask) -> Result<DownloadReport> {
+    let DownloadTask { table, build, locale } = task;
+    let cancelled = DownloadReport { cancelled: 1, ..Default::default() };
+    if context.cancel.is_cancelled() {
+        return Ok(cancelled);
+    }
+
+    let output_dir = &context.output_dir;
+    let folder_path = output_dir.join(build.format_full_version()).join(&locale);
+    let existing = table_path(output_dir, &build.format_full_version(), &locale, &table);
+    let file_path = folder_path.join(format!("{}.{}", table, context.compression.extension()));
+
+    if file_exists_with_size(&existing) {
+        tracing::debug!(path = %existing.display(), "Skipped, already downloaded");
+        context.progress.inc(1);
+        context.progress.set_message(format!("Skipped: {}", existing.display()));
+        return Ok(DownloadReport { skipped: 1, ..Default::default() });
+    }
+
+    ensure_dir_exists(&folder_path)?;
+
+    let _permit = tokio::select! {
+        biased;
+        _ = context.cancel.cancelled() => return Ok(cancelled),
+        permit = context.limiter.acquire() => permit,
+    };
+    let span = tracing::Span::current();
+    let task_started = Instant::now();
+    let mut attempt = 0;
+    let downloaded = loop {
+        attempt += 1;
+        span.record("attempt", attempt);
+        // Woken up every minute in case the clock changes while waiting
+        while let Some((opening, wait)) = next_opening(&context.windows, Local::now().time()) {
+            context.progress.set_message(format!("Paused until {}", opening.format("%H:%M")));
+            tokio::select! {
+                biased;
+                _ = context.cancel.cancelled() => return Ok(cancelled),
+                _ = tokio::time::sleep(wait.min(Duration::from_secs(60))) => {}
+            }
+        }
+        tokio::select! {
+            biased;
+            _ = context.cancel.cancelled() => return Ok(cancelled),
+            waited = context.rate_limiter.wait() => context.metrics.rate_limit_wait.observe(waited),
+        }
+
+        let download = async {
+            let started = Instant::now();
+            let fetched = context.source.fetch_table(&table, &build, &locale).await;
+            let latency = started.elapsed();
+            span.record("latency_ms", latency.as_millis() as u64);
+            context.metrics.record_request(&fetched, |f| f.status);
+            if fetched.as_ref().is_ok_and(|f| f.status.is_some()) {
+                context.metrics.request_latency.observe(latency);
+            }
+            let mut fetched = fetched?;
+            if let Some(status) = fetched.status {
+                span.record("status", status.as_u16());
+            }
+            fetched.body = context.bandwidth.throttle(fetched.body);
+            let report = save_table(fetched, &file_path, context.compression).await?;
+            anyhow::Ok((report, latency))
+        };
+        let deadline = async {
+            context.cancel.cancelled().await;
+            tokio::time::sleep(context.shutdown_timeout).await;
+        };
+        let downloaded = tokio::select! {
+            downloaded = download => downloaded,
+            _ = deadline => {
+                tracing::warn!("Cancelled while downloading");
+                let _ = fs::remove_file(part_path(&file_path));
+                return Ok(cancelled);
+            }
+        };
+        match downloaded {
+            Err(e) if attempt <= context.max_retries && is_transient(&e) && !context.cancel.is_cancelled() => {
+                if let Some(e) = e.downcast_ref::<StatusError>() {
+                    span.record("status", e.status.as_u16());
+                }
+                // The limiter hears of the outcome of the task, not of each attempt
+                context.metrics.record_retry();
+                tracing::warn!(error = %e, "Retrying in {} seconds", context.retry_delay.as_secs());
+                tokio::select! {
+                    biased;
+                    _ = context.cancel.cancelled() => return Ok(cancelled),
+                    _ = tokio::time::sleep(context.retry_delay) => {}
+                }
+            }
+            downloaded => break downloaded,
+        }
+    };
+    context.metrics.task_duration.observe(task_started.elapsed());
+    let report = match downloaded {
+        Ok((report, latency)) => {
+            context.limiter.record_success(latency, report.bytes_on_wire);
+            tracing::debug!(bytes = report.bytes_on_wire, path = %file_path.display(), "Downloaded");
+            report
+        }
+        Err(e) => {
+            if let Some(e) = e.downcast_ref::<StatusError>() {
+                span.record("status", e.status.as_u16());
+            }
+            tracing::error!(error = %e, "Download failed");
+            context.limiter.record_failure(&e);
+            context.progress.set_prefix(format!("×{}", context.limiter.limit()));
+            context.progress.inc(1);
+            context.progress.set_message(format!("Failed: {}", file_path.display()));
+            return Err(e);
+        }
+    };
+    context.progress.set_prefix(format!("×{}", context.limiter.limit()));
+    context.progress.inc(1);
+    context.progress.set_message(format!("Downloaded: {}", file_path.display()));
+    Ok(report)
 }
 
 impl DownloadService {
+    /// Downloads from wago.tools, or a server with the same URL scheme, at `base_url`.
+    #[cfg(test)]
     pub fn new(base_url: String) -> Result<Self> {
-        Ok(Self {
-            client: Client::new(),
-            base_url,
+        use crate::services::source::{WagoSource, WAGO_BUILDS_URL};
+        let client = crate::services::http::build_client(&crate::config::HttpConfig::default())?;
+        Ok(Self::with_source(Arc::new(WagoSource::new(client, &base_url, WAGO_BUILDS_URL))))
+    }
+
+    pub fn with_source(source: Arc<dyn DataSource>) -> Self {
+        Self {
+            source,
+            output_dir: PathBuf::from("."),
             rate_limiter: RateLimiter::new(100),
             max_retries: 3,
             retry_delay_secs: 5,
             max_concurrent_downloads: 4,
-        })
+            concurrency_bounds: None,
+            compression: Compression::None,
+            bandwidth: BandwidthLimiter::default(),
+            windows: Vec::new(),
+            cancel: CancellationToken::new(),
+            shutdown_timeout: Duration::from_secs(10),
+            order: TableOrder::Name,
+            priority_locale: None,
+            metrics: Arc::default(),
+            hooks: HooksConfig::default(),
+        }
+    }
+
+    pub fn set_output_dir(&mut self, output_dir: &Path) {
+        self.output_dir = output_dir.to_path_buf();
     }
 
     pub fn set_rate_limit(&mut self, requests_per_minute: u32) {
@@ -42,73 +348,55 @@ impl DownloadService {
         self.retry_delay_secs = retry_delay_secs;
     }
 
-    pub fn set_concurrent_downloads(&mut self, count: usize) {
-        self.max_concurrent_downloads = count;
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
     }
 
-    async fn download_csv(
-        &mut self,
-        table: &str,
-        build: &Build,
-        locale: &str,
-    ) -> Result<()> {
-        self.rate_limiter.wait().await;
-    
-        let url = format!(
-            "{}/{}/csv?build={}&locale={}",
-            self.base_url, table, build.format_full_version(), locale
-        );
-    
-        let folder_path = Path::new(&build.format_full_version()).join(locale);
-        let file_path = folder_path.join(format!("{}.csv", table));
-    
-        if file_exists_with_size(&file_path) {
-            println!("Skipping an existing file: {}", file_path.display());
-            return Ok(());
-        }
-    
-        println!("Downloading: {}", url);
-    
-        ensure_dir_exists(&folder_path)?;
-        
-        let response = self.client.get(&url)
-            .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36")
-            .send()
-            .await?;
-        
-        if response.status().is_success() {
-            let content = response.bytes().await?;
-            fs::write(&file_path, content)?;
-            println!("✓ Downloaded {}", file_path.display());
-            Ok(())
-        } else {
-            Err(anyhow::anyhow!("Download error: {}: {}", table, response.status()))
-        }
+    /// Caps the bytes per second of all the downloads, `limiter` can be
+    /// adjusted while they run.
+    pub fn set_bandwidth_limiter(&mut self, limiter: BandwidthLimiter) {
+        self.bandwidth = limiter;
     }
 
-    async fn download_with_retry(
-        &mut self,
-        table: &str,
-        build: &Build,
-        locale: &str,
-    ) -> Result<()> {
-        const MAX_RETRIES: u32 = 3;
-        let mut retries = 0;
-
-        while retries < MAX_RETRIES {
-            match self.download_csv(table, build, locale).await {
-                Ok(_) => return Ok(()),
-                Err(e) if e.to_string().contains("timeout") => {
-                    retries += 1;
-                    println!("Attempt {} of {} for table {}", retries, MAX_RETRIES, table);
-                    println!("Wait 5 seconds before trying again....");
-                    tokio::time::sleep(Duration::from_secs(5)).await;
-                },
-                Err(e) => return Err(e),
-            }
-        }
-        
-        Err(anyhow::anyhow!("The number of attempts for the table has been exceeded {}", table))
+    /// Local times of day in which requests are sent. Downloads in progress
+    /// when a window closes complete, the next ones wait for it to open again.
+    pub fn set_windows(&mut self, windows: Vec<TimeWindow>) {
+        self.windows = windows;
+    }
+
+    /// Where the requests, files and timings of the downloads are counted.
+    pub fn set_metrics(&mut self, metrics: Arc<DownloadMetrics>) {
+        self.metrics = metrics;
+    }
+
+    /// `after_locale` and `after_build` hooks, run as soon as the tables of a
+    /// locale or a build are downloaded.
+    pub fn set_hooks(&mut self, hooks: HooksConfig) {
+        self.hooks = hooks;
+    }
+
+    /// Order of the tables, and a locale whose tables come before the others.
+    pub fn set_order(&mut self, order: TableOrder, priority_locale: Option<String>) {
+        self.order = order;
+        self.priority_locale = priority_locale;
+    }
+
+    /// Once `cancel` is cancelled no new download starts, and the ones in
+    /// progress are given `timeout` to complete.
+    pub fn set_cancellation(&mut self, cancel: CancellationToken, timeout: Duration) {
+        self.cancel = cancel;
+        self.shutdown_timeout = timeout;
+    }
+
+    pub fn set_concurrent_downloads(&mut self, count: usize) {
+        self.max_concurrent_downloads = count;
+        self.concurrency_bounds = None;
+    }
+
+    /// Starts `initial` downloads at once, then adapts to the server within `min..=max`.
+    pub fn set_adaptive_concurrency(&mut self, initial: usize, min: usize, max: usize) {
+        self.max_concurrent_downloads = initial;
+        self.concurrency_bounds = Some((min, max));
     }
 
     pub async fn download_all(
@@ -116,113 +404,145 @@ impl DownloadService {
         tables: &HashSet<String>,
         builds: &[Build],
         locales: &[String]
-    ) -> Result<()> {
-        let semaphore = Arc::new(Semaphore::new(self.max_concurrent_downloads));
-        let client = self.client.clone();
-        let rate_limiter = self.rate_limiter.clone();
+    ) -> Result<RunReport> {
+        let started_at = Utc::now();
+        let tasks = schedule_tasks(tables, builds, locales, self.order, self.priority_locale.as_deref(), &self.output_dir);
     
         // Создаем прогресс-бар
-        let total_files = tables.len() * builds.len() * locales.len();
-        let progress = ProgressBar::new(total_files as u64);
+        let progress = ProgressBar::new(tasks.len() as u64);
         progress.set_style(ProgressStyle::default_bar()
-            .template("[{elapsed_precise}] {bar:40.cyan/blue} {pos}/{len} ({percent}%) {msg}")?);
-    
-        let mut handles = Vec::new();
-        
-        for build in builds {
-            for locale in locales {
-                for table in tables {
-                    let table = table.clone();
-                    let build = build.clone();
-                    let locale = locale.clone();
-                    let semaphore = Arc::clone(&semaphore);
-                    let client = client.clone();
-                    let rate_limiter = rate_limiter.clone();
-                    let progress = progress.clone();
-                    let base_url = self.base_url.clone();
-    
-                    let handle = tokio::spawn(async move {
-                        let _permit = semaphore.acquire().await.unwrap();
-                        
-                        let folder_path = Path::new(&build.format_full_version()).join(&locale);
-                        let file_path = folder_path.join(format!("{}.csv", table));
-    
-                        if file_exists_with_size(&file_path) {
-                            progress.inc(1);
-                            progress.set_message(format!("Skipped: {}", file_path.display()));
-                            return Ok(());
-                        }
-    
-                        ensure_dir_exists(&folder_path)?;
+            .template("[{elapsed_precise}] {bar:40.cyan/blue} {pos}/{len} ({percent}%) {prefix} {msg}")?);
 
-                        let mut rate_limiter = rate_limiter.clone(); 
+        let limiter = Arc::new(match self.concurrency_bounds {
+            Some((min, max)) => ConcurrencyLimiter::adaptive(self.max_concurrent_downloads, min, max),
+            None => ConcurrencyLimiter::fixed(self.max_concurrent_downloads),
+        });
+        progress.set_prefix(format!("×{}", limiter.limit()));
+        set_progress_bar(Some(progress.clone()));
 
-                        rate_limiter.wait().await;
-    
-                        let url = format!(
-                            "{}/{}/csv?build={}&locale={}",
-                            base_url, table, build.format_full_version(), locale
-                        );
-    
-                        let response = client.get(&url)
-                            .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36")
-                            .send()
-                            .await?;
-    
-                        if response.status().is_success() {
-                            let content = response.bytes().await?;
-                            fs::write(&file_path, content)?;
-                            progress.inc(1);
-                            progress.set_message(format!("Downloaded: {}", file_path.display()));
-                            Ok(())
-                        } else {
-                            Err(anyhow::anyhow!("Download error: {}: {}", table, response.status()))
-                        }
-                    });
-    
-                    handles.push(handle);
+        let context = Arc::new(TaskContext {
+            source: Arc::clone(&self.source),
+            output_dir: self.output_dir.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+            limiter: Arc::clone(&limiter),
+            bandwidth: self.bandwidth.clone(),
+            windows: self.windows.clone(),
+            compression: self.compression,
+            progress: progress.clone(),
+            cancel: self.cancel.clone(),
+            shutdown_timeout: self.shutdown_timeout,
+            max_retries: self.max_retries,
+            retry_delay: Duration::from_secs(self.retry_delay_secs),
+            metrics: Arc::clone(&self.metrics),
+        });
+
+        let key = |task: &DownloadTask| (builds.iter().position(|b| *b == task.build).unwrap_or_default(), task.locale.clone());
+        let mut locale_tasks: BTreeMap<(usize, String), usize> = BTreeMap::new();
+        let mut build_tasks: BTreeMap<usize, usize> = BTreeMap::new();
+        for task in &tasks {
+            let key = key(task);
+            *build_tasks.entry(key.0).or_default() += 1;
+            *locale_tasks.entry(key).or_default() += 1;
+        }
+        let (hook_events, hook_runner) = match self.hooks.has_group_hooks() {
+            true => {
+                let (events, runner) = spawn_group_hooks(self.hooks.clone(), self.output_dir.clone());
+                (Some(events), Some(runner))
+            }
+            false => (None, None),
+        };
+
+        // Tasks are only spawned once they can get a download slot
+        let mut results = futures::stream::iter(tasks)
+            .map(|task| {
+                let key = key(&task);
+                let handle = tokio::spawn(run_task(Arc::clone(&context), task));
+                async move { (key, handle.await) }
+            })
+            .buffer_unordered(limiter.max());
+
+        let mut by_locale: BTreeMap<(usize, String), DownloadReport> = BTreeMap::new();
+        while let Some((key, result)) = results.next().await {
+            let report = by_locale.entry(key.clone()).or_default();
+            match result {
+                Ok(Ok(task)) => report.add(&task),
+                // Logged by the task
+                Ok(Err(_)) => report.failed += 1,
+                Err(e) => {
+                    tracing::error!("Task error: {}", e);
+                    report.failed += 1;
                 }
             }
-        }
-    
-        let results = join_all(handles).await;
-        
-        let mut had_errors = false;
-        for result in results {
-            if let Err(e) = result {
-                eprintln!("Task error: {}", e);
-                had_errors = true;
+
+            let Some(events) = &hook_events else { continue };
+            if self.cancel.is_cancelled() {
+                continue;
+            }
+            let build = builds[key.0].to_string();
+            let locale_left = locale_tasks.get_mut(&key).unwrap();
+            *locale_left -= 1;
+            if *locale_left == 0 {
+                let report = LocaleReport { build: build.clone(), locale: key.1.clone(), report: *report };
+                let _ = events.send(GroupEvent::Locale(report));
+            }
+            let build_left = build_tasks.get_mut(&key.0).unwrap();
+            *build_left -= 1;
+            if *build_left == 0 {
+                let mut total = DownloadReport::default();
+                for (_, report) in by_locale.range((key.0, String::new())..(key.0 + 1, String::new())) {
+                    total.add(report);
+                }
+                let _ = events.send(GroupEvent::Build(build, total));
             }
         }
+        drop(hook_events);
+        let hooks_failed = match hook_runner {
+            Some(runner) => runner.await.unwrap_or(1),
+            None => 0,
+        };
     
-        progress.finish_with_message("Download complete");
-    
-        if had_errors {
-            Err(anyhow::anyhow!("Some downloads failed"))
+        if self.cancel.is_cancelled() {
+            progress.abandon_with_message("Cancelled");
         } else {
-            Ok(())
+            progress.finish_with_message("Download complete");
         }
+        set_progress_bar(None);
+        let mut total = DownloadReport::default();
+        let locales = by_locale.into_iter()
+            .map(|((build_index, locale), report)| {
+                total.add(&report);
+                LocaleReport { build: builds[build_index].to_string(), locale, report }
+            })
+            .collect();
+        Ok(RunReport {
+            started_at: started_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+            finished_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            total,
+            locales,
+            hooks_failed,
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use mockito::{mock, Mock};
+    use mockito::{Mock, ServerGuard};
     use std::fs;
     use tempfile::TempDir;
 
     fn create_test_build() -> Build {
-        Build::new("11.0.5".to_string(), 57212)
+        Build::new("11.0.5", 57212)
     }
 
-    fn create_mock_response(status: usize, body: &str) -> Mock {
-        mock("GET", "/Achievement/csv")
+    async fn create_mock_response(server: &mut ServerGuard, status: usize, body: &str) -> Mock {
+        server.mock("GET", "/Achievement/csv")
             .match_query(mockito::Matcher::Any)
             .with_status(status)
             .with_header("content-type", "text/csv")
             .with_body(body)
-            .create()
+            .create_async()
+            .await
     }
 
     #[tokio::test]
@@ -233,20 +553,18 @@ mod tests {
 
     #[tokio::test]
     async fn test_successful_download() {
-        let mock_server = mockito::Server::new();
-        let _m = create_mock_response(200, "id,name\n1,Test");
+        let mut mock_server = mockito::Server::new_async().await;
+        let _m = create_mock_response(&mut mock_server, 200, "id,name\n1,Test").await;
         
         let temp_dir = TempDir::new().unwrap();
-        let original_dir = std::env::current_dir().unwrap();
-        std::env::set_current_dir(&temp_dir).unwrap();
 
         let mut service = DownloadService::new(mock_server.url()).unwrap();
-        let build = create_test_build();
+        service.set_output_dir(temp_dir.path());
+        let tables = HashSet::from(["Achievement".to_string()]);
         
-        let result = service.download_csv("Achievement", &build, "ruRU").await;
+        let report = service.download_all(&tables, &[create_test_build()], &["ruRU".to_string()]).await.unwrap();
         
-        std::env::set_current_dir(original_dir).unwrap();
-        assert!(result.is_ok());
+        assert_eq!(report.total.downloaded, 1);
         
         let file_path = temp_dir
             .path()
@@ -261,88 +579,95 @@ mod tests {
 
     #[tokio::test]
     async fn test_failed_download() {
-        let mock_server = mockito::Server::new();
-        let _m = create_mock_response(404, "Not Found");
+        let mut mock_server = mockito::Server::new_async().await;
+        let _m = create_mock_response(&mut mock_server, 404, "Not Found").await;
         
+        let temp_dir = TempDir::new().unwrap();
         let mut service = DownloadService::new(mock_server.url()).unwrap();
-        let build = create_test_build();
+        service.set_output_dir(temp_dir.path());
+        let tables = HashSet::from(["Achievement".to_string()]);
         
-        let result = service.download_csv("Achievement", &build, "ruRU").await;
-        assert!(result.is_err());
-        assert!(result.unwrap_err().to_string().contains("404"));
+        let report = service.download_all(&tables, &[create_test_build()], &["ruRU".to_string()]).await.unwrap();
+        assert_eq!(report.total.failed, 1);
+        assert!(!temp_dir.path().join("11.0.5.57212").join("ruRU").join("Achievement.csv").exists());
     }
 
     #[tokio::test]
     async fn test_retry_on_timeout() {
-        let mock_server = mockito::Server::new();
+        let mut mock_server = mockito::Server::new_async().await;
         
-        let _m1 = mock("GET", "/Achievement/csv")
+        let m1 = mock_server.mock("GET", "/Achievement/csv")
+            .match_query(mockito::Matcher::Any)
             .with_status(408)
-            .create();
+            .expect(1)
+            .create_async()
+            .await;
             
-        let _m2 = create_mock_response(200, "id,name\n1,Test");
+        let m2 = create_mock_response(&mut mock_server, 200, "id,name\n1,Test").await;
         
         let temp_dir = TempDir::new().unwrap();
-        let original_dir = std::env::current_dir().unwrap();
-        std::env::set_current_dir(&temp_dir).unwrap();
 
         let mut service = DownloadService::new(mock_server.url()).unwrap();
-        let build = create_test_build();
+        service.set_output_dir(temp_dir.path());
+        service.set_retry_params(1, 0);
+        let tables = HashSet::from(["Achievement".to_string()]);
         
-        let result = service.download_with_retry("Achievement", &build, "ruRU").await;
+        let report = service.download_all(&tables, &[create_test_build()], &["ruRU".to_string()]).await.unwrap();
         
-        std::env::set_current_dir(original_dir).unwrap();
-        assert!(result.is_ok());
+        assert_eq!((report.total.downloaded, report.total.failed), (1, 0));
+        m1.assert_async().await;
+        m2.assert_async().await;
     }
 
     #[tokio::test]
     async fn test_rate_limiting() {
-        let mock_server = mockito::Server::new();
-        let _m = create_mock_response(200, "id,name\n1,Test");
+        let mut mock_server = mockito::Server::new_async().await;
+        let _m = create_mock_response(&mut mock_server, 200, "id,name\n1,Test").await;
         
+        let temp_dir = TempDir::new().unwrap();
         let mut service = DownloadService::new(mock_server.url()).unwrap();
-        let build = create_test_build();
-        
-        let start = std::time::Instant::now();
-        
-        for _ in 0..3 {
-            let _ = service.download_csv("Achievement", &build, "ruRU").await;
-        }
-        
-        let duration = start.elapsed();
-        
-        assert!(duration.as_secs() > 0, "Requests should be limited in frequency");
+        service.set_output_dir(temp_dir.path());
+        service.set_rate_limit(2);
+        let cancel = CancellationToken::new();
+        service.set_cancellation(cancel.clone(), Duration::from_secs(1));
+        let stop = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            stop.cancel();
+        });
+
+        let tables = HashSet::from(["Achievement".to_string()]);
+        let locales = vec!["ruRU".to_string(), "enUS".to_string(), "frFR".to_string()];
+        let report = service.download_all(&tables, &[create_test_build()], &locales).await.unwrap();
+        // The third request waits for the next minute
+        assert_eq!((report.total.downloaded, report.total.cancelled), (2, 1), "Requests should be limited in frequency");
     }
 
     #[tokio::test]
     async fn test_download_all() {
-        let mock_server = mockito::Server::new();
-        let _m = create_mock_response(200, "id,name\n1,Test");
+        let mut mock_server = mockito::Server::new_async().await;
+        let _m = create_mock_response(&mut mock_server, 200, "id,name\n1,Test").await;
         
         let temp_dir = TempDir::new().unwrap();
-        let original_dir = std::env::current_dir().unwrap();
-        std::env::set_current_dir(&temp_dir).unwrap();
 
         let mut service = DownloadService::new(mock_server.url()).unwrap();
+        service.set_output_dir(temp_dir.path());
         
-        let tables = vec!["Achievement".to_string()];
+        let tables = HashSet::from(["Achievement".to_string()]);
         let builds = vec![create_test_build()];
         let locales = vec!["ruRU".to_string()];
         
         let result = service.download_all(&tables, &builds, &locales).await;
         
-        std::env::set_current_dir(original_dir).unwrap();
         assert!(result.is_ok());
     }
 
     #[tokio::test]
     async fn test_skip_existing_file() {
-        let mock_server = mockito::Server::new();
-        let _m = create_mock_response(200, "id,name\n1,Test");
+        let mut mock_server = mockito::Server::new_async().await;
+        let m = create_mock_response(&mut mock_server, 200, "id,name\n1,Test").await.expect(0);
         
         let temp_dir = TempDir::new().unwrap();
-        let original_dir = std::env::current_dir().unwrap();
-        std::env::set_current_dir(&temp_dir).unwrap();
 
         let folder_path = temp_dir.path().join("11.0.5.57212").join("ruRU");
         fs::create_dir_all(&folder_path).unwrap();
@@ -350,38 +675,368 @@ mod tests {
         fs::write(&file_path, "existing content").unwrap();
 
         let mut service = DownloadService::new(mock_server.url()).unwrap();
-        let build = create_test_build();
+        service.set_output_dir(temp_dir.path());
+        let tables = HashSet::from(["Achievement".to_string()]);
         
-        let result = service.download_csv("Achievement", &build, "ruRU").await;
+        let report = service.download_all(&tables, &[create_test_build()], &["ruRU".to_string()]).await.unwrap();
         
-        std::env::set_current_dir(original_dir).unwrap();
-        assert!(result.is_ok());
+        assert_eq!(report.total.skipped, 1);
         
         let content = fs::read_to_string(file_path).unwrap();
         assert_eq!(content, "existing content");
+        m.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_compressed_download() {
+        let mut mock_server = mockito::Server::new_async().await;
+        let _m = create_mock_response(&mut mock_server, 200, "id,name\n1,Test").await;
+
+        let temp_dir = TempDir::new().unwrap();
+        let folder_path = temp_dir.path().join("11.0.5.57212").join("ruRU");
+        fs::create_dir_all(&folder_path).unwrap();
+        fs::write(folder_path.join("Achievement_Category.csv.gz"), "existing content").unwrap();
+
+        let mut service = DownloadService::new(mock_server.url()).unwrap();
+        service.set_output_dir(temp_dir.path());
+        service.set_compression(Compression::Zstd);
+        let tables = HashSet::from(["Achievement".to_string(), "Achievement_Category".to_string()]);
+        service.download_all(&tables, &[create_test_build()], &["ruRU".to_string()]).await.unwrap();
+
+        let content = zstd::decode_all(fs::read(folder_path.join("Achievement.csv.zst")).unwrap().as_slice()).unwrap();
+        assert_eq!(content, b"id,name\n1,Test");
+        assert!(!folder_path.join("Achievement.csv").exists());
+        // Already stored with another compression
+        assert!(!folder_path.join("Achievement_Category.csv.zst").exists());
+    }
+
+    #[tokio::test]
+    async fn test_transfer_encodings() {
+        let csv = "id,name\n".to_string() + &"1,Test\n".repeat(500);
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(csv.as_bytes()).unwrap();
+        let mut brotli = Vec::new();
+        brotli::BrotliCompress(&mut csv.as_bytes(), &mut brotli, &Default::default()).unwrap();
+        let bodies = [
+            ("gzip", gzip.finish().unwrap()),
+            ("br", brotli),
+            ("zstd", zstd::encode_all(csv.as_bytes(), 0).unwrap()),
+        ];
+
+        for (encoding, body) in bodies {
+            let mut mock_server = mockito::Server::new_async().await;
+            let _m = mock_server.mock("GET", "/Achievement/csv")
+                .match_query(mockito::Matcher::Any)
+                .match_header("accept-encoding", crate::utils::ACCEPT_ENCODING)
+                .with_header("content-encoding", encoding)
+                .with_body(&body)
+                .create_async()
+                .await;
+
+            let temp_dir = TempDir::new().unwrap();
+            let mut service = DownloadService::new(mock_server.url()).unwrap();
+            service.set_output_dir(temp_dir.path());
+            let tables = HashSet::from(["Achievement".to_string()]);
+            let report = service.download_all(&tables, &[create_test_build()], &["ruRU".to_string()]).await.unwrap().total;
+
+            let path = temp_dir.path().join("11.0.5.57212").join("ruRU").join("Achievement.csv");
+            assert_eq!(fs::read_to_string(path).unwrap(), csv, "{}", encoding);
+            assert_eq!(report, DownloadReport {
+                downloaded: 1,
+                bytes_on_wire: body.len() as u64,
+                bytes_decoded: csv.len() as u64,
+                bytes_written: csv.len() as u64,
+                ..Default::default()
+            });
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_downloads_are_reported() {
+        let mut mock_server = mockito::Server::new_async().await;
+        let _m = create_mock_response(&mut mock_server, 404, "Not Found").await;
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut service = DownloadService::new(mock_server.url()).unwrap();
+        service.set_output_dir(temp_dir.path());
+        let tables = HashSet::from(["Achievement".to_string()]);
+        let report = service.download_all(&tables, &[create_test_build()], &["ruRU".to_string()]).await.unwrap();
+        assert_eq!(report.total.failed, 1);
+        assert_eq!(report.total.downloaded, 0);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_before_start() {
+        let mut mock_server = mockito::Server::new_async().await;
+        let m = create_mock_response(&mut mock_server, 200, "id,name\n1,Test").await.expect(0);
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut service = DownloadService::new(mock_server.url()).unwrap();
+        service.set_output_dir(temp_dir.path());
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        service.set_cancellation(cancel, Duration::from_secs(1));
+        let tables = HashSet::from(["Achievement".to_string(), "Spell".to_string()]);
+        let report = service.download_all(&tables, &[create_test_build()], &["ruRU".to_string()]).await.unwrap();
+        assert_eq!(report.total.cancelled, 2);
+        assert!(report.total.summary().contains("2 cancelled"));
+        m.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_download_leaves_no_partial_file() {
+        let mut mock_server = mockito::Server::new_async().await;
+        let _m = mock_server.mock("GET", "/Achievement/csv")
+            .match_query(mockito::Matcher::Any)
+            .with_chunked_body(|w| {
+                w.write_all(b"id,name\n")?;
+                w.flush()?;
+                std::thread::sleep(std::time::Duration::from_secs(2));
+                w.write_all(b"1,Test\n")
+            })
+            .create_async()
+            .await;
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut service = DownloadService::new(mock_server.url()).unwrap();
+        service.set_output_dir(temp_dir.path());
+        let cancel = CancellationToken::new();
+        service.set_cancellation(cancel.clone(), Duration::from_millis(100));
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            cancel.cancel();
+        });
+        let tables = HashSet::from(["Achievement".to_string()]);
+        let report = service.download_all(&tables, &[create_test_build()], &["ruRU".to_string()]).await.unwrap();
+        assert_eq!(report.total.cancelled, 1);
+        let folder_path = temp_dir.path().join("11.0.5.57212").join("ruRU");
+        assert_eq!(fs::read_dir(folder_path).unwrap().count(), 0);
+    }
+
+    /// Counts the fetches in progress.
+    #[derive(Default)]
+    struct SlowSource {
+        in_flight: std::sync::atomic::AtomicUsize,
+        max_in_flight: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl DataSource for SlowSource {
+        fn describe(&self) -> String {
+            "slow".to_string()
+        }
+
+        async fn list_builds(&self) -> Result<Vec<Build>> {
+            Ok(vec![])
+        }
+
+        async fn list_tables(&self, _build: &Build) -> Result<Vec<String>> {
+            Ok(vec![])
+        }
+
+        async fn fetch_table(&self, _table: &str, _build: &Build, _locale: &str) -> Result<TableStream> {
+            use std::sync::atomic::Ordering;
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            let body = futures::stream::once(async { Ok(bytes::Bytes::from_static(b"ID\n1\n")) });
+            Ok(TableStream { status: None, content_encoding: None, body: body.boxed() })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bounded_concurrency() {
+        let source = Arc::new(SlowSource::default());
+        let temp_dir = TempDir::new().unwrap();
+        let mut service = DownloadService::with_source(source.clone());
+        service.set_output_dir(temp_dir.path());
+        service.set_rate_limit(60_000);
+        service.set_concurrent_downloads(3);
+
+        let tables: HashSet<String> = (0..20).map(|i| format!("Table{}", i)).collect();
+        let builds = vec![create_test_build(), Build::new("11.0.5", 57171)];
+        let report = service.download_all(&tables, &builds, &["enUS".to_string()]).await.unwrap();
+        assert_eq!(report.total.downloaded, 40);
+        assert_eq!(report.locales.len(), 2);
+        assert_eq!(source.max_in_flight.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_bandwidth_limit() {
+        let mut mock_server = mockito::Server::new_async().await;
+        let _m = mock_server.mock("GET", "/Spell/csv")
+            .match_query(mockito::Matcher::Any)
+            .with_body("x".repeat(15_000))
+            .create_async()
+            .await;
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut service = DownloadService::new(mock_server.url()).unwrap();
+        service.set_output_dir(temp_dir.path());
+        service.set_bandwidth_limiter(BandwidthLimiter::new(Some(crate::utils::ByteRate(10_000))));
+
+        let started = Instant::now();
+        let tables = HashSet::from(["Spell".to_string()]);
+        let report = service.download_all(&tables, &[create_test_build()], &["enUS".to_string()]).await.unwrap();
+        assert_eq!(report.total.downloaded, 1);
+        // One second of traffic goes through at once, the rest at 10 KB/s
+        assert!(started.elapsed() >= Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn test_retry_transient_errors() {
+        let mut mock_server = mockito::Server::new_async().await;
+        let unavailable = mock_server.mock("GET", "/Spell/csv")
+            .match_query(mockito::Matcher::Any)
+            .with_status(503)
+            .expect(1)
+            .create_async()
+            .await;
+        let available = mock_server.mock("GET", "/Spell/csv")
+            .match_query(mockito::Matcher::Any)
+            .with_body("ID\n1\n")
+            .expect(1)
+            .create_async()
+            .await;
+        let missing = mock_server.mock("GET", "/Map/csv")
+            .match_query(mockito::Matcher::Any)
+            .with_status(404)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut service = DownloadService::new(mock_server.url()).unwrap();
+        service.set_output_dir(temp_dir.path());
+        service.set_rate_limit(60_000);
+        service.set_retry_params(2, 0);
+        let metrics = Arc::new(DownloadMetrics::default());
+        service.set_metrics(Arc::clone(&metrics));
+        let tables = HashSet::from(["Spell".to_string(), "Map".to_string()]);
+        let report = service.download_all(&tables, &[create_test_build()], &["enUS".to_string()]).await.unwrap();
+        assert_eq!((report.total.downloaded, report.total.failed), (1, 1));
+
+        let mut rendered = String::new();
+        metrics.render(&mut rendered);
+        for line in [
+            "wago_db2_requests_total{status=\"200\"} 1",
+            "wago_db2_requests_total{status=\"404\"} 1",
+            "wago_db2_requests_total{status=\"503\"} 1",
+            "wago_db2_retries_total 1",
+            "wago_db2_files_total{outcome=\"failed\"} 1",
+            "wago_db2_downloaded_bytes_total 5",
+            "wago_db2_task_duration_seconds_count 2",
+        ] {
+            assert!(rendered.contains(&format!("{}\n", line)), "{} missing from\n{}", line, rendered);
+        }
+        unavailable.assert_async().await;
+        available.assert_async().await;
+        // Not worth retrying
+        missing.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_paused_outside_window() {
+        let mut mock_server = mockito::Server::new_async().await;
+        let mock = mock_server.mock("GET", "/Spell/csv")
+            .match_query(mockito::Matcher::Any)
+            .with_body("ID\n1\n")
+            .expect(0)
+            .create_async()
+            .await;
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut service = DownloadService::new(mock_server.url()).unwrap();
+        service.set_output_dir(temp_dir.path());
+        let now = Local::now().time();
+        let start = now + chrono::Duration::hours(2);
+        service.set_windows(vec![TimeWindow { start, end: start + chrono::Duration::hours(1) }]);
+        let cancel = CancellationToken::new();
+        service.set_cancellation(cancel.clone(), Duration::from_secs(1));
+
+        let stop = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            stop.cancel();
+        });
+        let tables = HashSet::from(["Spell".to_string()]);
+        let report = service.download_all(&tables, &[create_test_build()], &["enUS".to_string()]).await.unwrap();
+        assert_eq!(report.total.cancelled, 1);
+        mock.assert_async().await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_group_hooks() {
+        let mut mock_server = mockito::Server::new_async().await;
+        let mut mocks = Vec::new();
+        for (build, locale, status) in [
+            ("11.0.5.57171", "enUS", 200),
+            ("11.0.5.57171", "frFR", 200),
+            ("11.0.5.57212", "enUS", 200),
+            ("11.0.5.57212", "frFR", 404),
+        ] {
+            mocks.push(mock_server.mock("GET", format!("/Spell/csv?build={}&locale={}", build, locale).as_str())
+                .with_status(status)
+                .with_body("ID\n1\n")
+                .create_async()
+                .await);
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let log = temp_dir.path().join("hooks.log");
+        let mut service = DownloadService::new(mock_server.url()).unwrap();
+        service.set_output_dir(temp_dir.path());
+        service.set_rate_limit(60_000);
+        service.set_retry_params(0, 0);
+        service.set_hooks(HooksConfig {
+            after_locale: Some(format!("echo \"locale $WAGO_DB2_BUILD $WAGO_DB2_LOCALE\" >> '{}'", log.display())),
+            after_build: Some(format!("echo \"build $WAGO_DB2_BUILD\" >> '{}'", log.display())),
+            ..Default::default()
+        });
+        let tables = HashSet::from(["Spell".to_string()]);
+        let builds = [Build::new("11.0.5", 57171), create_test_build()];
+        let report = service.download_all(&tables, &builds, &["enUS".to_string(), "frFR".to_string()]).await.unwrap();
+        assert_eq!((report.total.downloaded, report.total.failed, report.hooks_failed), (3, 1, 0));
+
+        // Hooks of a failed locale or build are not run
+        let written = fs::read_to_string(&log).unwrap();
+        let mut lines: Vec<&str> = written.lines().collect();
+        lines.sort();
+        assert_eq!(lines, [
+            "build 11.0.5.57171",
+            "locale 11.0.5.57171 enUS",
+            "locale 11.0.5.57171 frFR",
+            "locale 11.0.5.57212 enUS",
+        ]);
+        for mock in mocks {
+            mock.assert_async().await;
+        }
     }
 
     #[tokio::test]
     async fn test_parallel_downloads() {
-        let mock_server = mockito::Server::new();
+        let mut mock_server = mockito::Server::new_async().await;
         
-        let _m1 = mock("GET", "/Achievement/csv")
+        let _m1 = mock_server.mock("GET", "/Achievement/csv")
             .match_query(mockito::Matcher::Any)
             .with_status(200)
             .with_body("data1")
-            .create();
+            .create_async()
+            .await;
             
-        let _m2 = mock("GET", "/Achievement_Category/csv")
+        let _m2 = mock_server.mock("GET", "/Achievement_Category/csv")
             .match_query(mockito::Matcher::Any)
             .with_status(200)
             .with_body("data2")
-            .create();
+            .create_async()
+            .await;
 
         let temp_dir = TempDir::new().unwrap();
-        let original_dir = std::env::current_dir().unwrap();
-        std::env::set_current_dir(&temp_dir).unwrap();
 
         let mut service = DownloadService::new(mock_server.url()).unwrap();
+        service.set_output_dir(temp_dir.path());
         service.set_concurrent_downloads(2); 
         
         let tables = HashSet::from([
@@ -395,7 +1050,6 @@ mod tests {
         let result = service.download_all(&tables, &builds, &locales).await;
         let duration = start.elapsed();
         
-        std::env::set_current_dir(original_dir).unwrap();
         
         assert!(result.is_ok());
         assert!(temp_dir.path()
@@ -413,10 +1067,12 @@ mod tests {
 
     #[tokio::test]
     async fn test_progress_bar() {
-        let mock_server = mockito::Server::new();
-        let _m = create_mock_response(200, "test data");
+        let mut mock_server = mockito::Server::new_async().await;
+        let _m = create_mock_response(&mut mock_server, 200, "test data").await;
         
+        let temp_dir = TempDir::new().unwrap();
         let mut service = DownloadService::new(mock_server.url()).unwrap();
+        service.set_output_dir(temp_dir.path());
         
         let tables = HashSet::from(["Achievement".to_string()]);
         let builds = vec![create_test_build()];
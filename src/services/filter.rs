@@ -0,0 +1,370 @@
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use anyhow::{Context, Result};
+use regex::Regex;
+use crate::utils::{open_table, split_table_file, Compression, TableWriter};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterMode {
+    /// Write `<table>.filtered.csv` next to the original
+    #[default]
+    Alongside,
+    /// Overwrite the original file
+    Replace,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+    Range { min: f64, max: f64, inclusive: bool },
+    List(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Condition {
+    column: String,
+    operator: Operator,
+    value: String,
+}
+
+impl Condition {
+    fn matches(&self, field: &str) -> bool {
+        match &self.operator {
+            Operator::Eq => compare(field, &self.value) == Ordering::Equal,
+            Operator::Ne => compare(field, &self.value) != Ordering::Equal,
+            Operator::Lt => compare(field, &self.value) == Ordering::Less,
+            Operator::Le => compare(field, &self.value) != Ordering::Greater,
+            Operator::Gt => compare(field, &self.value) == Ordering::Greater,
+            Operator::Ge => compare(field, &self.value) != Ordering::Less,
+            Operator::Contains => field.to_lowercase().contains(&self.value.to_lowercase()),
+            Operator::Range { min, max, inclusive } => match field.parse::<f64>() {
+                Ok(v) if *inclusive => *min <= v && v <= *max,
+                Ok(v) => *min <= v && v < *max,
+                Err(_) => false,
+            },
+            Operator::List(values) => values.iter().any(|v| compare(field, v) == Ordering::Equal),
+        }
+    }
+}
+
+/// Numbers compare numerically, anything else as text.
+fn compare(field: &str, value: &str) -> Ordering {
+    match (field.parse::<f64>(), value.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        _ => field.cmp(value),
+    }
+}
+
+/// Row filter expression such as `ID in 1000..2000 and ExpansionID = 10`.
+///
+/// Conditions are `=`, `!=`, `<`, `<=`, `>`, `>=`, `~` (substring), `in a..b`
+/// (end excluded), `in a..=b` and `in (a, b, c)`, combined with `and` / `or`.
+#[derive(Debug, Clone)]
+pub struct RowFilter {
+    expression: String,
+    // Disjunction of conjunctions
+    alternatives: Vec<Vec<Condition>>,
+}
+
+impl FromStr for RowFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(expression: &str) -> Result<Self> {
+        let condition_re = Regex::new(
+            r"(?i)^([A-Za-z_][A-Za-z0-9_]*)\s*(?:(<=|>=|!=|=|<|>|~)|\s(in)\s)\s*(.+)$",
+        )?;
+
+        let mut alternatives = Vec::new();
+        for alternative in split_keyword(expression, "or") {
+            let mut conditions = Vec::new();
+            for part in split_keyword(&alternative, "and") {
+                let part = part.trim();
+                let captures = condition_re.captures(part)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid filter condition '{}'", part))?;
+                let column = captures[1].to_string();
+                let value = unquote(captures[4].trim());
+                let operator = match captures.get(2).map(|m| m.as_str()) {
+                    Some("=") => Operator::Eq,
+                    Some("!=") => Operator::Ne,
+                    Some("<") => Operator::Lt,
+                    Some("<=") => Operator::Le,
+                    Some(">") => Operator::Gt,
+                    Some(">=") => Operator::Ge,
+                    Some("~") => Operator::Contains,
+                    // Unquoted item by item
+                    _ => parse_in(captures[4].trim())?,
+                };
+                conditions.push(Condition { column, operator, value });
+            }
+            alternatives.push(conditions);
+        }
+
+        Ok(Self { expression: expression.to_string(), alternatives })
+    }
+}
+
+impl RowFilter {
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    /// Binds the filter to a header row, `None` when a column is missing.
+    fn bind(&self, headers: &csv::StringRecord) -> Option<Vec<Vec<(usize, &Condition)>>> {
+        self.alternatives.iter()
+            .map(|conditions| {
+                conditions.iter()
+                    .map(|c| headers.iter().position(|h| h == c.column).map(|i| (i, c)))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+fn parse_in(value: &str) -> Result<Operator> {
+    if let Some((min, max)) = value.split_once("..") {
+        let (max, inclusive) = match max.strip_prefix('=') {
+            Some(max) => (max, true),
+            None => (max, false),
+        };
+        let min = min.trim().parse().with_context(|| format!("Invalid range '{}'", value))?;
+        let max = max.trim().parse().with_context(|| format!("Invalid range '{}'", value))?;
+        return Ok(Operator::Range { min, max, inclusive });
+    }
+    let list = value.trim_start_matches('(').trim_end_matches(')');
+    Ok(Operator::List(split_list(list).iter().map(|v| unquote(v.trim())).collect()))
+}
+
+/// Splits on commas, ignoring quoted text.
+fn split_list(list: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    for c in list.chars() {
+        match c {
+            ',' if !in_quotes => items.push(std::mem::take(&mut current)),
+            '"' => {
+                in_quotes = !in_quotes;
+                current.push(c);
+            }
+            _ => current.push(c),
+        }
+    }
+    items.push(current);
+    items
+}
+
+fn unquote(value: &str) -> String {
+    value.strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+        .to_string()
+}
+
+/// Splits on a keyword surrounded by whitespace, ignoring quoted text.
+fn split_keyword(expression: &str, keyword: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut in_quotes = false;
+
+    for word in expression.split_whitespace() {
+        // Parts are sliced from the expression to keep the whitespace of quoted text
+        let offset = word.as_ptr() as usize - expression.as_ptr() as usize;
+        let current = &expression[start..offset];
+        if !in_quotes && word.eq_ignore_ascii_case(keyword) && !current.trim().is_empty() {
+            parts.push(current.trim().to_string());
+            start = offset + word.len();
+            continue;
+        }
+        in_quotes ^= word.matches('"').count() % 2 == 1;
+    }
+    parts.push(expression[start..].trim().to_string());
+    parts
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilterResult {
+    pub kept: u64,
+    pub total: u64,
+}
+
+/// `Spell.csv` → `Spell.filtered.csv`, keeping the compression of the table.
+pub fn filtered_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let (stem, compression) = split_table_file(&name).unwrap_or((&name, Compression::None));
+    path.with_file_name(format!("{}.filtered.{}", stem, compression.extension()))
+}
+
+/// Copies the rows of `input` matching `filter` to `output` in a single streaming pass.
+/// Returns `None` without writing anything when the table lacks a filtered column.
+pub fn filter_file(filter: &RowFilter, input: &Path, output: &Path) -> Result<Option<FilterResult>> {
+    let mut reader = open_table(input)?;
+    let headers = reader.headers()?.clone();
+    let Some(bound) = filter.bind(&headers) else {
+        return Ok(None);
+    };
+
+    let mut writer = csv::Writer::from_writer(TableWriter::create(output, Compression::of_path(input))?);
+    writer.write_record(&headers)?;
+
+    let mut result = FilterResult { kept: 0, total: 0 };
+    let mut record = csv::StringRecord::new();
+    while reader.read_record(&mut record)? {
+        result.total += 1;
+        let matches = bound.iter().any(|conditions| {
+            conditions.iter().all(|(i, c)| c.matches(record.get(*i).unwrap_or_default()))
+        });
+        if matches {
+            writer.write_record(&record)?;
+            result.kept += 1;
+        }
+    }
+    writer.into_inner().map_err(|e| anyhow::anyhow!("Unable to write {}: {}", output.display(), e.error()))?
+        .finish()?;
+    Ok(Some(result))
+}
+
+/// Filters a downloaded table according to `mode`.
+pub fn apply_filter(filter: &RowFilter, path: &Path, mode: FilterMode) -> Result<Option<FilterResult>> {
+    match mode {
+        FilterMode::Alongside => filter_file(filter, path, &filtered_path(path)),
+        FilterMode::Replace => {
+            let mut temp = path.as_os_str().to_owned();
+            temp.push(".tmp");
+            let temp = PathBuf::from(temp);
+            let result = filter_file(filter, path, &temp);
+            match result {
+                Ok(Some(_)) => std::fs::rename(&temp, path)?,
+                _ => {
+                    let _ = std::fs::remove_file(&temp);
+                }
+            }
+            result
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    const SPELLS: &str = "ID,Name,ExpansionID\n999,Blink,0\n1000,Fireball,10\n1500,\"Frost, Bolt\",10\n2000,Polymorph,9\n";
+
+    fn run(expression: &str) -> (Option<FilterResult>, String) {
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.path().join("Spell.csv");
+        fs::write(&input, SPELLS).unwrap();
+
+        let filter: RowFilter = expression.parse().unwrap();
+        let result = apply_filter(&filter, &input, FilterMode::Alongside).unwrap();
+        let output = fs::read_to_string(filtered_path(&input)).unwrap_or_default();
+        (result, output)
+    }
+
+    #[test]
+    fn test_range_filter() {
+        let (result, output) = run("ID in 1000..2000");
+        assert_eq!(result, Some(FilterResult { kept: 2, total: 4 }));
+        assert_eq!(output, "ID,Name,ExpansionID\n1000,Fireball,10\n1500,\"Frost, Bolt\",10\n");
+
+        let (result, _) = run("ID in 1000..=2000");
+        assert_eq!(result.unwrap().kept, 3);
+    }
+
+    #[test]
+    fn test_list_with_quoted_commas() {
+        let (result, output) = run("Name in (\"Frost, Bolt\", Blink)");
+        assert_eq!(result, Some(FilterResult { kept: 2, total: 4 }));
+        assert_eq!(output, "ID,Name,ExpansionID\n999,Blink,0\n1500,\"Frost, Bolt\",10\n");
+        assert_eq!(run("ID in (999, 2000)").0.unwrap().kept, 2);
+    }
+
+    #[test]
+    fn test_combined_conditions() {
+        assert_eq!(run("ExpansionID = 10").0.unwrap().kept, 2);
+        assert_eq!(run("ExpansionID = 10 and Name ~ frost").0.unwrap().kept, 1);
+        assert_eq!(run("ID < 1000 or ID >= 2000").0.unwrap().kept, 2);
+        assert_eq!(run("ID in (999, 2000)").0.unwrap().kept, 2);
+        assert_eq!(run("Name = \"Frost, Bolt\"").0.unwrap().kept, 1);
+        assert_eq!(run("Name != Blink AND ExpansionID != 10").0.unwrap().kept, 1);
+    }
+
+    #[test]
+    fn test_tabs_and_newlines() {
+        assert_eq!(run("ExpansionID = 10\tand\tName ~ frost").0.unwrap().kept, 1);
+        assert_eq!(run("ID < 1000\nor\nID >= 2000").0.unwrap().kept, 2);
+        assert_eq!(run("ID\tin\t(999, 2000)\n  and ExpansionID = 9").0.unwrap().kept, 1);
+        // Keywords inside quotes are part of the value
+        assert_eq!(run("Name = \"Frost\tand Bolt\" or ID = 999").0.unwrap().kept, 1);
+    }
+
+    #[test]
+    fn test_missing_column_is_skipped() {
+        let (result, output) = run("MapID = 1");
+        assert_eq!(result, None);
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_replace_mode() {
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.path().join("Spell.csv");
+        fs::write(&input, SPELLS).unwrap();
+
+        let filter: RowFilter = "ExpansionID = 9".parse().unwrap();
+        apply_filter(&filter, &input, FilterMode::Replace).unwrap();
+        assert_eq!(fs::read_to_string(&input).unwrap(), "ID,Name,ExpansionID\n2000,Polymorph,9\n");
+        assert!(!input.with_extension("csv.tmp").exists());
+    }
+
+    #[test]
+    fn test_replace_mode_removes_temp_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.path().join("Spell.csv");
+        let temp = input.with_extension("csv.tmp");
+
+        // Left over by an earlier run, the table lacks the column
+        fs::write(&input, SPELLS).unwrap();
+        fs::write(&temp, "ID\n").unwrap();
+        let filter: RowFilter = "MapID = 1".parse().unwrap();
+        assert_eq!(apply_filter(&filter, &input, FilterMode::Replace).unwrap(), None);
+        assert!(!temp.exists());
+
+        // Fails halfway through on a row that is not UTF-8
+        let malformed = b"ID,Name,ExpansionID\n999,Blink,0\n1000,\xff,10\n";
+        fs::write(&input, malformed).unwrap();
+        let filter: RowFilter = "ExpansionID = 0".parse().unwrap();
+        assert!(apply_filter(&filter, &input, FilterMode::Replace).is_err());
+        assert!(!temp.exists());
+        assert_eq!(fs::read(&input).unwrap(), malformed);
+    }
+
+    #[test]
+    fn test_compressed_table() {
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.path().join("Spell.csv.zst");
+        fs::write(&input, zstd::encode_all(SPELLS.as_bytes(), 0).unwrap()).unwrap();
+
+        let filter: RowFilter = "ExpansionID = 9".parse().unwrap();
+        assert_eq!(apply_filter(&filter, &input, FilterMode::Alongside).unwrap().unwrap().kept, 1);
+        let output = temp_dir.path().join("Spell.filtered.csv.zst");
+        assert_eq!(filtered_path(&input), output);
+        let content = zstd::decode_all(fs::read(output).unwrap().as_slice()).unwrap();
+        assert_eq!(String::from_utf8(content).unwrap(), "ID,Name,ExpansionID\n2000,Polymorph,9\n");
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        assert!("ID".parse::<RowFilter>().is_err());
+        assert!("ID in a..b".parse::<RowFilter>().is_err());
+    }
+}
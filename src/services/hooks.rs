@@ -0,0 +1,260 @@
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use tokio::process::Command;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use crate::config::HttpConfig;
+use crate::services::downloader::{DownloadReport, LocaleReport, RunReport};
+use crate::services::http::build_client;
+
+/// Written in the output directory when hooks are configured.
+pub const REPORT_FILE: &str = "last-run.json";
+
+/// `[hooks]` section: shell commands run once the tables of a locale or of a
+/// build are downloaded, and once the whole run is post-processed.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HooksConfig {
+    /// Run for each locale downloaded without errors
+    pub after_locale: Option<String>,
+    /// Run for each build downloaded without errors
+    pub after_build: Option<String>,
+    /// Run after every run, failed or not
+    pub after_run: Option<String>,
+    /// URL receiving the JSON run report in a POST request
+    pub webhook: Option<String>,
+}
+
+impl HooksConfig {
+    /// Whether hooks run while the tables are downloading.
+    pub fn has_group_hooks(&self) -> bool {
+        self.after_locale.is_some() || self.after_build.is_some()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.after_locale.is_none() && self.after_build.is_none()
+            && self.after_run.is_none() && self.webhook.is_none()
+    }
+}
+
+/// Runs `command` through the system shell with `env` added to its
+/// environment, failing when it exits with an error status.
+pub async fn run_command(command: &str, env: &[(&str, String)]) -> Result<()> {
+    let mut process = if cfg!(windows) {
+        let mut process = Command::new("cmd");
+        process.arg("/C").arg(command);
+        process
+    } else {
+        let mut process = Command::new("sh");
+        process.arg("-c").arg(command);
+        process
+    };
+    let status = process
+        .envs(env.iter().map(|(key, value)| (key, value)))
+        .status()
+        .await
+        .with_context(|| format!("Unable to run hook '{}'", command))?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("Hook '{}' failed with {}", command, status));
+    }
+    Ok(())
+}
+
+/// Environment of the hooks run for `build`.
+pub fn build_env(output_dir: &Path, build: &str) -> Vec<(&'static str, String)> {
+    vec![
+        ("WAGO_DB2_BUILD", build.to_string()),
+        ("WAGO_DB2_BUILD_DIR", output_dir.join(build).display().to_string()),
+        ("WAGO_DB2_OUTPUT_DIR", output_dir.display().to_string()),
+    ]
+}
+
+fn report_env(event: &str, report: &DownloadReport) -> Vec<(&'static str, String)> {
+    vec![
+        ("WAGO_DB2_EVENT", event.to_string()),
+        ("WAGO_DB2_DOWNLOADED", report.downloaded.to_string()),
+        ("WAGO_DB2_SKIPPED", report.skipped.to_string()),
+        ("WAGO_DB2_FAILED", report.failed.to_string()),
+    ]
+}
+
+/// Runs `after_locale` for a locale whose tables are all downloaded.
+pub async fn after_locale(hooks: &HooksConfig, output_dir: &Path, locale: &LocaleReport) -> Result<()> {
+    let Some(command) = &hooks.after_locale else { return Ok(()) };
+    if locale.report.failed > 0 || locale.report.cancelled > 0 {
+        return Ok(());
+    }
+    let mut env = build_env(output_dir, &locale.build);
+    env.push(("WAGO_DB2_LOCALE", locale.locale.clone()));
+    env.push(("WAGO_DB2_LOCALE_DIR", output_dir.join(&locale.build).join(&locale.locale).display().to_string()));
+    env.extend(report_env("locale", &locale.report));
+    run_command(command, &env).await
+}
+
+/// Runs `after_build` for a build whose tables are all downloaded, `report`
+/// summing its locales.
+pub async fn after_build(hooks: &HooksConfig, output_dir: &Path, build: &str, report: &DownloadReport) -> Result<()> {
+    let Some(command) = &hooks.after_build else { return Ok(()) };
+    if report.failed > 0 || report.cancelled > 0 {
+        return Ok(());
+    }
+    let mut env = build_env(output_dir, build);
+    env.extend(report_env("build", report));
+    run_command(command, &env).await
+}
+
+/// Locale or build whose tables are all downloaded.
+pub enum GroupEvent {
+    Locale(LocaleReport),
+    Build(String, DownloadReport),
+}
+
+/// Runs the hooks of the groups sent to the returned channel one after the
+/// other, without holding up the downloads. The task ends once the channel is
+/// dropped and returns the number of hooks that failed.
+pub fn spawn_group_hooks(hooks: HooksConfig, output_dir: PathBuf) -> (mpsc::UnboundedSender<GroupEvent>, JoinHandle<usize>) {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let runner = tokio::spawn(async move {
+        let mut failed = 0;
+        while let Some(event) = receiver.recv().await {
+            let ran = match &event {
+                GroupEvent::Locale(locale) => after_locale(&hooks, &output_dir, locale).await,
+                GroupEvent::Build(build, report) => after_build(&hooks, &output_dir, build, report).await,
+            };
+            if let Err(e) = ran {
+                tracing::error!("{:#}", e);
+                failed += 1;
+            }
+        }
+        failed
+    });
+    (sender, runner)
+}
+
+/// Writes the report of a finished run, runs `after_run` and posts the report
+/// to the webhook. Both are attempted even when one of them fails.
+pub async fn after_run(hooks: &HooksConfig, http: &HttpConfig, output_dir: &Path, report: &RunReport) -> Result<()> {
+    if hooks.is_empty() {
+        return Ok(());
+    }
+    let report_path = output_dir.join(REPORT_FILE);
+    std::fs::write(&report_path, serde_json::to_string_pretty(report)?)?;
+
+    let mut failures = Vec::new();
+    if let Some(command) = &hooks.after_run {
+        let mut env = vec![("WAGO_DB2_OUTPUT_DIR", output_dir.display().to_string())];
+        env.extend(report_env("run", &report.total));
+        env.push(("WAGO_DB2_REPORT", report_path.display().to_string()));
+        if let Err(e) = run_command(command, &env).await {
+            failures.push(e);
+        }
+    }
+    if let Some(url) = &hooks.webhook {
+        let posted = async {
+            build_client(http)?.post(url).json(report).send().await?.error_for_status()?;
+            anyhow::Ok(())
+        }.await;
+        if let Err(e) = posted {
+            failures.push(e.context(format!("Webhook {} failed", url)));
+        }
+    }
+
+    for failure in &failures {
+        tracing::error!("{:#}", failure);
+    }
+    let failed = failures.len() + report.hooks_failed;
+    if failed > 0 {
+        return Err(anyhow::anyhow!("{} hook(s) failed", failed));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn run_report() -> RunReport {
+        let locale = |build: &str, locale: &str, downloaded, failed| LocaleReport {
+            build: build.to_string(),
+            locale: locale.to_string(),
+            report: DownloadReport { downloaded, failed, ..Default::default() },
+        };
+        RunReport {
+            started_at: "2024-11-05T10:00:00Z".to_string(),
+            finished_at: "2024-11-05T10:05:00Z".to_string(),
+            total: DownloadReport { downloaded: 5, failed: 1, ..Default::default() },
+            locales: vec![
+                locale("11.0.5.57171", "enUS", 2, 0),
+                locale("11.0.5.57212", "enUS", 2, 0),
+                locale("11.0.5.57212", "frFR", 1, 1),
+            ],
+            hooks_failed: 0,
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_command() {
+        let temp_dir = TempDir::new().unwrap();
+        let out = temp_dir.path().join("out.txt");
+        let command = format!("echo \"$WAGO_DB2_BUILD\" > '{}'", out.display());
+        run_command(&command, &[("WAGO_DB2_BUILD", "11.0.5.57212".to_string())]).await.unwrap();
+        assert_eq!(fs::read_to_string(out).unwrap(), "11.0.5.57212\n");
+        assert!(run_command("exit 3", &[]).await.is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_hooks_skip_failed_locales_and_builds() {
+        let temp_dir = TempDir::new().unwrap();
+        let log = temp_dir.path().join("hooks.log");
+        let hook = |fields: &str| Some(format!("echo \"{}\" >> '{}'", fields, log.display()));
+        let hooks = HooksConfig {
+            after_locale: hook("$WAGO_DB2_EVENT $WAGO_DB2_BUILD $WAGO_DB2_LOCALE"),
+            after_build: hook("$WAGO_DB2_EVENT $WAGO_DB2_BUILD $WAGO_DB2_DOWNLOADED"),
+            after_run: hook("$WAGO_DB2_EVENT $WAGO_DB2_FAILED $(basename $WAGO_DB2_REPORT)"),
+            webhook: None,
+        };
+
+        let report = run_report();
+        for locale in &report.locales {
+            after_locale(&hooks, temp_dir.path(), locale).await.unwrap();
+        }
+        for (build, downloaded, failed) in [("11.0.5.57171", 2, 0), ("11.0.5.57212", 3, 1)] {
+            let total = DownloadReport { downloaded, failed, ..Default::default() };
+            after_build(&hooks, temp_dir.path(), build, &total).await.unwrap();
+        }
+        after_run(&hooks, &HttpConfig::default(), temp_dir.path(), &report).await.unwrap();
+        assert_eq!(fs::read_to_string(log).unwrap(), "\
+locale 11.0.5.57171 enUS
+locale 11.0.5.57212 enUS
+build 11.0.5.57171 2
+run 1 last-run.json
+");
+        let written: serde_json::Value = serde_json::from_str(&fs::read_to_string(temp_dir.path().join(REPORT_FILE)).unwrap()).unwrap();
+        assert_eq!(written["locales"][2]["failed"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_webhook() {
+        let mut server = mockito::Server::new_async().await;
+        let webhook = server.mock("POST", "/db2")
+            .match_header("content-type", "application/json")
+            .match_body(mockito::Matcher::PartialJsonString(
+                r#"{"total": {"downloaded": 5, "failed": 1}, "locales": [{"build": "11.0.5.57171", "locale": "enUS"}]}"#.to_string(),
+            ))
+            .create_async()
+            .await;
+        let temp_dir = TempDir::new().unwrap();
+        let mut hooks = HooksConfig { webhook: Some(format!("{}/db2", server.url())), ..Default::default() };
+
+        after_run(&hooks, &HttpConfig::default(), temp_dir.path(), &run_report()).await.unwrap();
+        webhook.assert_async().await;
+
+        hooks.webhook = Some(format!("{}/missing", server.url()));
+        assert!(after_run(&hooks, &HttpConfig::default(), temp_dir.path(), &run_report()).await.is_err());
+    }
+}
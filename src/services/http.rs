@@ -0,0 +1,79 @@
+use std::str::FromStr;
+use std::time::Duration;
+use anyhow::{Context, Result};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, ACCEPT_ENCODING};
+use reqwest::{Certificate, Client, Proxy};
+use crate::config::HttpConfig;
+
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Client shared by every request: keep-alive pool, HTTP/2 when the server offers it,
+/// and everything `[http]` configures.
+pub fn build_client(config: &HttpConfig) -> Result<Client> {
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT_ENCODING, HeaderValue::from_static(crate::utils::ACCEPT_ENCODING));
+    for (name, value) in &config.headers {
+        let name = HeaderName::from_str(name)
+            .with_context(|| format!("Invalid header name '{}'", name))?;
+        let mut value = HeaderValue::from_str(value)
+            .with_context(|| format!("Invalid value for header {}", name))?;
+        value.set_sensitive(true);
+        headers.insert(name, value);
+    }
+
+    let mut builder = Client::builder()
+        .user_agent(&config.user_agent)
+        .default_headers(headers)
+        .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+        .read_timeout(Duration::from_secs(config.read_timeout_secs))
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .tcp_keepalive(POOL_IDLE_TIMEOUT);
+    if let Some(secs) = config.request_timeout_secs {
+        builder = builder.timeout(Duration::from_secs(secs));
+    }
+    if let Some(proxy) = &config.proxy {
+        builder = builder.proxy(Proxy::all(proxy).with_context(|| format!("Invalid proxy '{}'", proxy))?);
+    }
+    for path in &config.ca_certificates {
+        let pem = std::fs::read(path)
+            .with_context(|| format!("Unable to read certificate {}", path.display()))?;
+        let certificate = Certificate::from_pem(&pem)
+            .with_context(|| format!("Invalid certificate {}", path.display()))?;
+        builder = builder.add_root_certificate(certificate);
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_configured_headers_are_sent() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("GET", "/")
+            .match_header("user-agent", "db2-team/1.0")
+            .match_header("authorization", "Bearer secret")
+            .match_header("accept-encoding", crate::utils::ACCEPT_ENCODING)
+            .create_async()
+            .await;
+
+        let mut config = HttpConfig { user_agent: "db2-team/1.0".to_string(), ..Default::default() };
+        config.headers.insert("Authorization".to_string(), "Bearer secret".to_string());
+        build_client(&config).unwrap().get(server.url()).send().await.unwrap();
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn test_invalid_settings() {
+        let proxy = HttpConfig { proxy: Some("not a url".to_string()), ..Default::default() };
+        assert!(build_client(&proxy).is_err());
+
+        let mut header = HttpConfig::default();
+        header.headers.insert("Bad Header".to_string(), "x".to_string());
+        assert!(build_client(&header).is_err());
+
+        let certificate = HttpConfig { ca_certificates: vec!["missing.pem".into()], ..Default::default() };
+        assert!(build_client(&certificate).is_err());
+    }
+}
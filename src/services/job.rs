@@ -0,0 +1,136 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use crate::services::archive::ArchiveFormat;
+use crate::services::concurrency::Concurrency;
+use crate::services::filter::FilterMode;
+use crate::services::scheduler::TableOrder;
+use crate::services::store::StoreMode;
+use crate::utils::{Compression, TimeWindow};
+
+/// Kept in the output directory until the download it describes completes,
+/// in the build directory for the downloads of `watch`.
+pub const JOB_FILE: &str = ".download-job.json";
+
+/// What a download run was asked to fetch, so that an interrupted or failed
+/// run can be resumed. Tables already on disk are skipped when resuming.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DownloadJob {
+    pub started_at: String,
+    pub builds: Vec<String>,
+    pub locales: Vec<String>,
+    /// Requested tables once expanded to their dependencies
+    pub tables: BTreeSet<String>,
+    #[serde(flatten)]
+    pub options: JobOptions,
+}
+
+/// How the tables of a job are downloaded and post-processed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JobOptions {
+    pub compress: Compression,
+    /// Times of day the download may run, any time when empty
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub windows: Vec<TimeWindow>,
+    pub store: StoreMode,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    pub filter_mode: FilterMode,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive: Option<ArchiveFormat>,
+    pub order: TableOrder,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority_locale: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<Concurrency>,
+}
+
+impl DownloadJob {
+    pub fn new(builds: Vec<String>, locales: Vec<String>, tables: BTreeSet<String>, options: JobOptions) -> Self {
+        Self {
+            started_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            builds,
+            locales,
+            tables,
+            options,
+        }
+    }
+
+    fn path(output_dir: &Path) -> PathBuf {
+        output_dir.join(JOB_FILE)
+    }
+
+    pub fn save(&self, output_dir: &Path) -> Result<()> {
+        fs::create_dir_all(output_dir)?;
+        fs::write(Self::path(output_dir), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// The unfinished job of `output_dir`, if any.
+    pub fn load(output_dir: &Path) -> Result<Option<Self>> {
+        let path = Self::path(output_dir);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)?;
+        let job = serde_json::from_str(&content)
+            .with_context(|| format!("Invalid download job {}", path.display()))?;
+        Ok(Some(job))
+    }
+
+    pub fn remove(output_dir: &Path) -> Result<()> {
+        let path = Self::path(output_dir);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_job_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        assert_eq!(DownloadJob::load(temp_dir.path()).unwrap(), None);
+
+        let job = DownloadJob::new(
+            vec!["11.0.5.57212".to_string()],
+            vec!["enUS".to_string(), "frFR".to_string()],
+            BTreeSet::from(["Spell".to_string(), "SpellMisc".to_string()]),
+            JobOptions {
+                compress: Compression::Zstd,
+                windows: vec!["22:00-06:00".parse().unwrap()],
+                store: StoreMode::Cas,
+                filter: Some("Name contains \"Frost\"".to_string()),
+                filter_mode: FilterMode::Replace,
+                archive: Some(ArchiveFormat::TarZst),
+                order: TableOrder::SmallFirst,
+                priority_locale: Some("frFR".to_string()),
+                concurrency: Some(Concurrency::Auto),
+            },
+        );
+        job.save(temp_dir.path()).unwrap();
+        let written = fs::read_to_string(temp_dir.path().join(JOB_FILE)).unwrap();
+        for field in ["\"compress\": \"zstd\"", "\"22:00-06:00\"", "\"archive\": \"tar.zst\"", "\"order\": \"small-first\"", "\"concurrency\": \"auto\""] {
+            assert!(written.contains(field), "{} missing from\n{}", field, written);
+        }
+        assert_eq!(DownloadJob::load(temp_dir.path()).unwrap(), Some(job));
+
+        // Options left out of the file read back as their defaults
+        let job = DownloadJob::new(vec!["11.0.5.57212".to_string()], vec![], BTreeSet::new(), JobOptions::default());
+        job.save(temp_dir.path()).unwrap();
+        assert!(!fs::read_to_string(temp_dir.path().join(JOB_FILE)).unwrap().contains("windows"));
+        assert_eq!(DownloadJob::load(temp_dir.path()).unwrap(), Some(job));
+
+        DownloadJob::remove(temp_dir.path()).unwrap();
+        assert_eq!(DownloadJob::load(temp_dir.path()).unwrap(), None);
+    }
+}
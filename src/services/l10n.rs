@@ -0,0 +1,337 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use anyhow::{Context, Result};
+use crate::entities::Build;
+use crate::services::definitions::Definitions;
+use crate::utils::{list_tables, table_path, TableData};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueKind {
+    MissingRow,
+    Empty,
+    Untranslated,
+}
+
+impl IssueKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            IssueKind::MissingRow => "missing_row",
+            IssueKind::Empty => "empty",
+            IssueKind::Untranslated => "untranslated",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Issue {
+    pub table: String,
+    pub locale: String,
+    pub id: String,
+    pub column: String,
+    pub kind: IssueKind,
+    pub reference: String,
+    pub text: String,
+}
+
+/// Translation state of one table in one locale, counted in string cells.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    pub table: String,
+    pub locale: String,
+    /// Non-empty strings of the reference locale
+    pub strings: usize,
+    pub translated: usize,
+    pub missing_rows: usize,
+    pub empty: usize,
+    pub untranslated: usize,
+}
+
+impl Coverage {
+    pub fn completion(&self) -> f64 {
+        if self.strings == 0 {
+            100.0
+        } else {
+            self.translated as f64 * 100.0 / self.strings as f64
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct L10nReport {
+    pub build: String,
+    pub reference: String,
+    pub coverage: Vec<Coverage>,
+    pub issues: Vec<Issue>,
+}
+
+impl L10nReport {
+    /// Coverage summed over every table, per locale.
+    pub fn by_locale(&self) -> Vec<Coverage> {
+        let mut totals: BTreeMap<&str, Coverage> = BTreeMap::new();
+        for c in &self.coverage {
+            let total = totals.entry(&c.locale).or_insert_with(|| Coverage {
+                table: "*".to_string(),
+                locale: c.locale.clone(),
+                ..Default::default()
+            });
+            total.strings += c.strings;
+            total.translated += c.translated;
+            total.missing_rows += c.missing_rows;
+            total.empty += c.empty;
+            total.untranslated += c.untranslated;
+        }
+        totals.into_values().collect()
+    }
+
+    pub fn write_summary_csv(&self, path: &Path) -> Result<()> {
+        let mut writer = csv::Writer::from_path(path)
+            .with_context(|| format!("Unable to write {}", path.display()))?;
+        writer.write_record(["table", "locale", "strings", "translated", "missing_rows", "empty", "untranslated", "completion"])?;
+        for c in self.coverage.iter().chain(self.by_locale().iter()) {
+            writer.write_record([
+                c.table.clone(),
+                c.locale.clone(),
+                c.strings.to_string(),
+                c.translated.to_string(),
+                c.missing_rows.to_string(),
+                c.empty.to_string(),
+                c.untranslated.to_string(),
+                format!("{:.2}", c.completion()),
+            ])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn write_details_csv(&self, path: &Path) -> Result<()> {
+        let mut writer = csv::Writer::from_path(path)
+            .with_context(|| format!("Unable to write {}", path.display()))?;
+        writer.write_record(["table", "locale", "id", "column", "issue", &self.reference, "text"])?;
+        for issue in &self.issues {
+            writer.write_record([
+                issue.table.as_str(),
+                issue.locale.as_str(),
+                issue.id.as_str(),
+                issue.column.as_str(),
+                issue.kind.as_str(),
+                issue.reference.as_str(),
+                issue.text.as_str(),
+            ])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Localized columns of a table: from the definitions when they know the table,
+/// otherwise the `*_lang` columns of the wago.tools export.
+fn localized_columns(table: &str, data: &TableData, definitions: Option<(&Definitions, &Build)>) -> Vec<String> {
+    if let Some(layout) = definitions.and_then(|(d, build)| d.layout(table, build)) {
+        return layout.localized_columns();
+    }
+    data.headers.iter()
+        .filter(|h| h.ends_with("_lang"))
+        .cloned()
+        .collect()
+}
+
+/// Something a translator has to look at, i.e. more than numbers and punctuation.
+fn is_text(value: &str) -> bool {
+    value.chars().any(char::is_alphabetic)
+}
+
+pub fn compare_locale(
+    table: &str,
+    locale: &str,
+    columns: &[String],
+    reference: &TableData,
+    target: &TableData,
+    issues: &mut Vec<Issue>,
+) -> Coverage {
+    let mut coverage = Coverage { table: table.to_string(), locale: locale.to_string(), ..Default::default() };
+    let pairs: Vec<(usize, Option<usize>, &String)> = columns.iter()
+        .filter_map(|c| reference.column(c).map(|i| (i, target.column(c), c)))
+        .collect();
+
+    let mut ids: Vec<&String> = reference.ids.keys().collect();
+    ids.sort_by(|a, b| crate::services::changelog::compare_ids(a, b));
+
+    for id in ids {
+        let reference_row = reference.row(id).expect("id comes from the index");
+        let target_row = target.row(id);
+        let mut row_missing = false;
+
+        for &(ref_index, target_index, column) in &pairs {
+            let reference_text = reference_row.get(ref_index).map(String::as_str).unwrap_or_default();
+            if reference_text.is_empty() {
+                continue;
+            }
+            coverage.strings += 1;
+
+            let text = target_row
+                .zip(target_index)
+                .and_then(|(row, i)| row.get(i))
+                .map(String::as_str)
+                .unwrap_or_default();
+            let kind = if target_row.is_none() {
+                Some(IssueKind::MissingRow)
+            } else if text.is_empty() {
+                Some(IssueKind::Empty)
+            } else if text == reference_text && is_text(text) {
+                Some(IssueKind::Untranslated)
+            } else {
+                None
+            };
+
+            match kind {
+                None => coverage.translated += 1,
+                Some(kind) => {
+                    match kind {
+                        IssueKind::Empty => coverage.empty += 1,
+                        IssueKind::Untranslated => coverage.untranslated += 1,
+                        IssueKind::MissingRow => row_missing = true,
+                    }
+                    issues.push(Issue {
+                        table: table.to_string(),
+                        locale: locale.to_string(),
+                        id: id.clone(),
+                        column: column.clone(),
+                        kind,
+                        reference: reference_text.to_string(),
+                        text: text.to_string(),
+                    });
+                }
+            }
+        }
+        // Rows without any reference text have nothing to translate
+        if row_missing {
+            coverage.missing_rows += 1;
+        }
+    }
+
+    coverage
+}
+
+/// Compares every locale of `build` with the reference locale, table by table.
+/// Tables not downloaded for a locale are left out rather than counted as missing.
+pub fn build_report(
+    root: &Path,
+    build: &Build,
+    reference: &str,
+    locales: &[String],
+    tables: &[String],
+    definitions: Option<&Definitions>,
+) -> Result<L10nReport> {
+    let build_name = build.format_full_version();
+    let tables = if tables.is_empty() {
+        list_tables(&root.join(&build_name).join(reference))?
+    } else {
+        tables.to_vec()
+    };
+
+    let mut coverage = Vec::new();
+    let mut issues = Vec::new();
+    for table in &tables {
+        let reference_path = table_path(root, &build_name, reference, table);
+        if !reference_path.exists() {
+            continue;
+        }
+        let reference_data = TableData::load(&reference_path)?;
+        let columns = localized_columns(table, &reference_data, definitions.map(|d| (d, build)));
+        if columns.is_empty() {
+            continue;
+        }
+
+        for locale in locales.iter().filter(|l| l.as_str() != reference) {
+            let path = table_path(root, &build_name, locale, table);
+            if !path.exists() {
+                continue;
+            }
+            let target = TableData::load(&path)?;
+            coverage.push(compare_locale(table, locale, &columns, &reference_data, &target, &mut issues));
+        }
+    }
+
+    Ok(L10nReport { build: build_name, reference: reference.to_string(), coverage, issues })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    const BUILD: &str = "11.0.5.57212";
+
+    fn write_table(root: &Path, locale: &str, table: &str, content: &str) {
+        let path = table_path(root, BUILD, locale, table);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn create_test_report() -> L10nReport {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        write_table(root, "enUS", "SpellName", "ID,Name_lang\n1,Fireball\n2,Frostbolt\n3,Blink\n4,Polymorph\n5,\n");
+        write_table(root, "frFR", "SpellName", "ID,Name_lang\n1,Boule de feu\n2,\n3,Blink\n5,\n");
+        write_table(root, "esES", "SpellName", "ID,Name_lang\n1,Bola de fuego\n2,Descarga de escarcha\n3,Traslación\n4,Polimorfia\n");
+        write_table(root, "enUS", "SpellMisc", "ID,SpellID\n1,1\n");
+        write_table(root, "frFR", "SpellMisc", "ID,SpellID\n1,1\n");
+
+        let locales = vec!["enUS".to_string(), "frFR".to_string(), "esES".to_string()];
+        build_report(root, &BUILD.parse().unwrap(), "enUS", &locales, &[], None).unwrap()
+    }
+
+    #[test]
+    fn test_coverage_counts() {
+        let report = create_test_report();
+        assert_eq!(report.coverage.len(), 2);
+
+        let fr = report.coverage.iter().find(|c| c.locale == "frFR").unwrap();
+        assert_eq!(fr, &Coverage {
+            table: "SpellName".to_string(),
+            locale: "frFR".to_string(),
+            strings: 4,
+            translated: 1,
+            missing_rows: 1,
+            empty: 1,
+            untranslated: 1,
+        });
+        assert_eq!(fr.completion(), 25.0);
+
+        let es = report.coverage.iter().find(|c| c.locale == "esES").unwrap();
+        assert_eq!(es.completion(), 100.0);
+    }
+
+    #[test]
+    fn test_issues() {
+        let report = create_test_report();
+        let issues: Vec<(&str, IssueKind)> = report.issues.iter()
+            .map(|i| (i.id.as_str(), i.kind))
+            .collect();
+        assert_eq!(issues, vec![
+            ("2", IssueKind::Empty),
+            ("3", IssueKind::Untranslated),
+            ("4", IssueKind::MissingRow),
+        ]);
+        assert_eq!(report.issues[1].reference, "Blink");
+    }
+
+    #[test]
+    fn test_csv_export() {
+        let report = create_test_report();
+        let temp_dir = TempDir::new().unwrap();
+        let summary = temp_dir.path().join("summary.csv");
+        let details = temp_dir.path().join("details.csv");
+        report.write_summary_csv(&summary).unwrap();
+        report.write_details_csv(&details).unwrap();
+
+        let summary = fs::read_to_string(summary).unwrap();
+        assert!(summary.contains("SpellName,frFR,4,1,1,1,1,25.00\n"));
+        assert!(summary.contains("*,esES,4,4,0,0,0,100.00\n"));
+
+        let details = fs::read_to_string(details).unwrap();
+        assert!(details.starts_with("table,locale,id,column,issue,enUS,text\n"));
+        assert!(details.contains("SpellName,frFR,4,Name_lang,missing_row,Polymorph,\n"));
+    }
+}
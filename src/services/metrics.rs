@@ -0,0 +1,219 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::{header, HeaderValue};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use crate::services::source::StatusError;
+
+const LATENCY_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+const WAIT_BUCKETS: &[f64] = &[0.01, 0.1, 0.5, 1.0, 5.0, 15.0, 30.0, 60.0];
+const TASK_BUCKETS: &[f64] = &[0.1, 0.5, 1.0, 5.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0];
+
+/// Cumulative histogram of durations in seconds.
+pub struct Histogram {
+    buckets: &'static [f64],
+    state: Mutex<HistogramState>,
+}
+
+#[derive(Default)]
+struct HistogramState {
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(buckets: &'static [f64]) -> Self {
+        Self {
+            buckets,
+            state: Mutex::new(HistogramState { counts: vec![0; buckets.len()], ..Default::default() }),
+        }
+    }
+
+    pub fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let mut state = self.state.lock().unwrap();
+        for (bound, count) in self.buckets.iter().zip(state.counts.iter_mut()) {
+            if seconds <= *bound {
+                *count += 1;
+            }
+        }
+        state.sum += seconds;
+        state.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let state = self.state.lock().unwrap();
+        let _ = writeln!(out, "# HELP {} {}\n# TYPE {} histogram", name, help, name);
+        for (bound, count) in self.buckets.iter().zip(&state.counts) {
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, count);
+        }
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, state.count);
+        let _ = writeln!(out, "{}_sum {}\n{}_count {}", name, state.sum, name, state.count);
+    }
+}
+
+fn render_counter(out: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} counter\n{} {}", name, help, name, name, value);
+}
+
+/// What the download engine did since the process started, for `/metrics`.
+pub struct DownloadMetrics {
+    /// Responses by status code, "error" when none was received
+    requests: Mutex<BTreeMap<String, u64>>,
+    /// Tables by outcome: downloaded, skipped, failed or cancelled
+    files: Mutex<BTreeMap<&'static str, u64>>,
+    bytes_downloaded: AtomicU64,
+    bytes_written: AtomicU64,
+    retries: AtomicU64,
+    pub rate_limit_wait: Histogram,
+    /// Time to the response headers
+    pub request_latency: Histogram,
+    /// Time to download a table, retries included
+    pub task_duration: Histogram,
+}
+
+impl Default for DownloadMetrics {
+    fn default() -> Self {
+        Self {
+            requests: Mutex::default(),
+            files: Mutex::default(),
+            bytes_downloaded: AtomicU64::new(0),
+            bytes_written: AtomicU64::new(0),
+            retries: AtomicU64::new(0),
+            rate_limit_wait: Histogram::new(WAIT_BUCKETS),
+            request_latency: Histogram::new(LATENCY_BUCKETS),
+            task_duration: Histogram::new(TASK_BUCKETS),
+        }
+    }
+}
+
+impl DownloadMetrics {
+    /// Counts the response of a table request. Tables that were not fetched
+    /// over HTTP are not counted.
+    pub fn record_request<T>(&self, result: &anyhow::Result<T>, status: impl FnOnce(&T) -> Option<reqwest::StatusCode>) {
+        let label = match result {
+            Ok(fetched) => status(fetched).map(|s| s.as_u16().to_string()),
+            Err(e) => match e.downcast_ref::<StatusError>() {
+                Some(e) => Some(e.status.as_u16().to_string()),
+                None => e.downcast_ref::<reqwest::Error>().map(|_| "error".to_string()),
+            },
+        };
+        if let Some(label) = label {
+            *self.requests.lock().unwrap().entry(label).or_default() += 1;
+        }
+    }
+
+    pub fn record_file(&self, outcome: &'static str) {
+        *self.files.lock().unwrap().entry(outcome).or_default() += 1;
+    }
+
+    pub fn record_bytes(&self, downloaded: u64, written: u64) {
+        self.bytes_downloaded.fetch_add(downloaded, Ordering::Relaxed);
+        self.bytes_written.fetch_add(written, Ordering::Relaxed);
+    }
+
+    pub fn record_retry(&self) {
+        self.retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Prometheus text format.
+    pub fn render(&self, out: &mut String) {
+        let _ = writeln!(out, "# HELP wago_db2_requests_total Table requests by response status\n# TYPE wago_db2_requests_total counter");
+        for (status, count) in self.requests.lock().unwrap().iter() {
+            let _ = writeln!(out, "wago_db2_requests_total{{status=\"{}\"}} {}", status, count);
+        }
+        let _ = writeln!(out, "# HELP wago_db2_files_total Tables by outcome\n# TYPE wago_db2_files_total counter");
+        for (outcome, count) in self.files.lock().unwrap().iter() {
+            let _ = writeln!(out, "wago_db2_files_total{{outcome=\"{}\"}} {}", outcome, count);
+        }
+        render_counter(out, "wago_db2_downloaded_bytes_total", "Response bodies received, before content decoding",
+            self.bytes_downloaded.load(Ordering::Relaxed));
+        render_counter(out, "wago_db2_written_bytes_total", "Table files written to disk",
+            self.bytes_written.load(Ordering::Relaxed));
+        render_counter(out, "wago_db2_retries_total", "Table requests retried after a transient error",
+            self.retries.load(Ordering::Relaxed));
+        self.rate_limit_wait.render(out, "wago_db2_rate_limit_wait_seconds", "Time spent waiting on the request rate limit");
+        self.request_latency.render(out, "wago_db2_request_duration_seconds", "Time to the response headers of a table request");
+        self.task_duration.render(out, "wago_db2_task_duration_seconds", "Time to download a table, retries included");
+    }
+}
+
+/// Requests answered by `serve` and `proxy`, by route and status.
+#[derive(Default)]
+pub struct HttpMetrics {
+    requests: Mutex<BTreeMap<(String, u16), u64>>,
+}
+
+impl HttpMetrics {
+    pub fn render(&self, out: &mut String) {
+        let _ = writeln!(out, "# HELP wago_db2_http_requests_total Requests answered by route and status\n# TYPE wago_db2_http_requests_total counter");
+        for ((route, status), count) in self.requests.lock().unwrap().iter() {
+            let _ = writeln!(out, "wago_db2_http_requests_total{{route=\"{}\",status=\"{}\"}} {}", route, status, count);
+        }
+    }
+}
+
+/// Middleware counting the responses in `metrics`. Requests matching no
+/// route are counted together so that scanners do not add labels.
+pub async fn track_requests(State(metrics): State<Arc<HttpMetrics>>, request: Request, next: Next) -> Response {
+    let route = request.extensions().get::<MatchedPath>()
+        .map_or("unmatched".to_string(), |p| p.as_str().to_string());
+    let response = next.run(request).await;
+    *metrics.requests.lock().unwrap().entry((route, response.status().as_u16())).or_default() += 1;
+    response
+}
+
+pub fn metrics_response(body: String) -> Response {
+    let content_type = HeaderValue::from_static("text/plain; version=0.0.4; charset=utf-8");
+    ([(header::CONTENT_TYPE, content_type)], body).into_response()
+}
+
+/// `/metrics` alone, for `watch`.
+pub fn router(metrics: Arc<DownloadMetrics>) -> Router {
+    Router::new()
+        .route("/metrics", get(|State(metrics): State<Arc<DownloadMetrics>>| async move {
+            let mut body = String::new();
+            metrics.render(&mut body);
+            metrics_response(body)
+        }))
+        .with_state(metrics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = DownloadMetrics::default();
+        let fetched: anyhow::Result<Option<reqwest::StatusCode>> = Ok(Some(reqwest::StatusCode::OK));
+        metrics.record_request(&fetched, |s| *s);
+        let missing: anyhow::Result<Option<reqwest::StatusCode>> = Err(StatusError {
+            table: "Spell".to_string(),
+            status: reqwest::StatusCode::NOT_FOUND,
+        }.into());
+        metrics.record_request(&missing, |s| *s);
+        // Local sources are not counted
+        metrics.record_request(&Ok(None), |s: &Option<reqwest::StatusCode>| *s);
+        metrics.record_file("skipped");
+        metrics.record_bytes(100, 40);
+        metrics.rate_limit_wait.observe(Duration::from_millis(300));
+
+        let mut out = String::new();
+        metrics.render(&mut out);
+        assert!(out.contains("wago_db2_requests_total{status=\"200\"} 1\n"));
+        assert!(out.contains("wago_db2_requests_total{status=\"404\"} 1\n"));
+        assert!(out.contains("wago_db2_files_total{outcome=\"skipped\"} 1\n"));
+        assert!(out.contains("wago_db2_downloaded_bytes_total 100\n"));
+        assert!(out.contains("wago_db2_rate_limit_wait_seconds_bucket{le=\"0.1\"} 0\n"));
+        assert!(out.contains("wago_db2_rate_limit_wait_seconds_bucket{le=\"0.5\"} 1\n"));
+        assert!(out.contains("wago_db2_rate_limit_wait_seconds_count 1\n"));
+    }
+}
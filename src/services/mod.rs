@@ -1,2 +1,23 @@
+pub mod archive;
+pub mod changelog;
+pub mod concurrency;
+pub mod definitions;
+pub mod dependencies;
+pub mod download_task;
 pub mod downloader;
+pub mod filter;
+pub mod hooks;
+pub mod http;
+pub mod job;
+pub mod l10n;
+pub mod metrics;
+pub mod proxy;
+pub mod query;
+pub mod scheduler;
+pub mod schema;
+pub mod search;
+pub mod server;
+pub mod source;
+pub mod store;
+pub mod watch;
 
@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use axum::extract::{Path, Query, State};
+use axum::http::HeaderMap;
+use axum::response::Response;
+use axum::routing::get;
+use axum::{middleware, Json, Router};
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use crate::entities::Build;
+use crate::services::downloader::save_table;
+use crate::services::metrics::{metrics_response, track_requests, DownloadMetrics, HttpMetrics};
+use crate::services::server::{resolve_build, resolve_request, send_table, ApiError};
+use crate::services::source::DataSource;
+use crate::utils::{ensure_dir_exists, file_exists_with_size, table_path, Compression, RateLimiter};
+
+/// Cache counters reported by `/status`.
+#[derive(Debug, Default)]
+pub struct CacheStats {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    upstream_errors: AtomicU64,
+    bytes_fetched: AtomicU64,
+    bytes_stored: AtomicU64,
+    rate_limit_wait_ms: AtomicU64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CacheStatus {
+    pub upstream: String,
+    pub hits: u64,
+    pub misses: u64,
+    pub upstream_errors: u64,
+    pub hit_ratio: f64,
+    /// Response bodies received from upstream, before content decoding
+    pub bytes_fetched: u64,
+    pub bytes_stored: u64,
+    pub rate_limit_wait_ms: u64,
+}
+
+/// State of the caching proxy: files already in `root` are served as the
+/// mirror does, the others are fetched from `source` and kept.
+pub struct ProxyState {
+    pub root: PathBuf,
+    pub source: Arc<dyn DataSource>,
+    pub rate_limiter: RateLimiter,
+    pub compression: Compression,
+    pub stats: CacheStats,
+    /// Fetches from upstream, in the format of the download engine
+    pub downloads: DownloadMetrics,
+    pub http: Arc<HttpMetrics>,
+    /// One lock per file so concurrent misses fetch it only once
+    fetching: Mutex<HashMap<PathBuf, Arc<Mutex<()>>>>,
+}
+
+impl ProxyState {
+    pub fn new(root: PathBuf, source: Arc<dyn DataSource>, rate_limiter: RateLimiter, compression: Compression) -> Self {
+        Self {
+            root,
+            source,
+            rate_limiter,
+            compression,
+            stats: CacheStats::default(),
+            downloads: DownloadMetrics::default(),
+            http: Arc::default(),
+            fetching: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn status(&self) -> CacheStatus {
+        let hits = self.stats.hits.load(Ordering::Relaxed);
+        let misses = self.stats.misses.load(Ordering::Relaxed);
+        CacheStatus {
+            upstream: self.source.describe(),
+            hits,
+            misses,
+            upstream_errors: self.stats.upstream_errors.load(Ordering::Relaxed),
+            hit_ratio: if hits + misses > 0 { hits as f64 / (hits + misses) as f64 } else { 0.0 },
+            bytes_fetched: self.stats.bytes_fetched.load(Ordering::Relaxed),
+            bytes_stored: self.stats.bytes_stored.load(Ordering::Relaxed),
+            rate_limit_wait_ms: self.stats.rate_limit_wait_ms.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Path of the cached table, fetched from upstream first when missing.
+    async fn cached_table(&self, table: &str, build: &Build, locale: &str) -> Result<PathBuf, ApiError> {
+        let version = build.format_full_version();
+        let existing = table_path(&self.root, &version, locale, table);
+        if file_exists_with_size(&existing) {
+            self.stats.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(existing);
+        }
+
+        let folder_path = self.root.join(&version).join(locale);
+        let file_path = folder_path.join(format!("{}.{}", table, self.compression.extension()));
+        let lock = Arc::clone(self.fetching.lock().await.entry(file_path.clone()).or_default());
+        let _guard = lock.lock().await;
+        // Fetched by another request while this one was waiting
+        let existing = table_path(&self.root, &version, locale, table);
+        if file_exists_with_size(&existing) {
+            self.stats.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(existing);
+        }
+
+        self.stats.misses.fetch_add(1, Ordering::Relaxed);
+        let result = async {
+            ensure_dir_exists(&folder_path)?;
+            let waited = self.rate_limiter.wait().await;
+            self.stats.rate_limit_wait_ms.fetch_add(waited.as_millis() as u64, Ordering::Relaxed);
+            self.downloads.rate_limit_wait.observe(waited);
+            let started = Instant::now();
+            let fetched = self.source.fetch_table(table, build, locale).await;
+            self.downloads.record_request(&fetched, |f| f.status);
+            if fetched.as_ref().is_ok_and(|f| f.status.is_some()) {
+                self.downloads.request_latency.observe(started.elapsed());
+            }
+            // Written to a `.part` file first so that no request is served a partial table
+            let report = save_table(fetched?, &file_path, self.compression).await?;
+            self.downloads.task_duration.observe(started.elapsed());
+            self.stats.bytes_fetched.fetch_add(report.bytes_on_wire, Ordering::Relaxed);
+            self.stats.bytes_stored.fetch_add(report.bytes_written, Ordering::Relaxed);
+            self.downloads.record_bytes(report.bytes_on_wire, report.bytes_written);
+            anyhow::Ok(())
+        }.await;
+        self.fetching.lock().await.remove(&file_path);
+
+        match result {
+            Ok(()) => {
+                self.downloads.record_file("downloaded");
+                Ok(file_path)
+            }
+            Err(e) => {
+                self.downloads.record_file("failed");
+                self.stats.upstream_errors.fetch_add(1, Ordering::Relaxed);
+                Err(ApiError::bad_gateway(format!("{} {} {}: {}", table, build, locale, e)))
+            }
+        }
+    }
+}
+
+pub type SharedProxyState = Arc<ProxyState>;
+
+/// Same routes as the mirror, plus `/status`. Build and table listings come
+/// from upstream.
+pub fn router(state: SharedProxyState) -> Router {
+    Router::new()
+        .route("/db2/{table}/csv", get(table_csv))
+        .route("/builds", get(builds))
+        .route("/tables", get(tables))
+        .route("/status", get(status))
+        .route("/metrics", get(metrics))
+        .layer(middleware::from_fn_with_state(Arc::clone(&state.http), track_requests))
+        .with_state(state)
+}
+
+async fn table_csv(
+    State(state): State<SharedProxyState>,
+    Path(table): Path<String>,
+    Query(mut params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let build = match params.remove("build") {
+        Some(build) => build,
+        None => newest_build(&state).await?.to_string(),
+    };
+    params.insert("build".to_string(), build);
+    let (build, locale) = resolve_request(&state.root, &table, &params)?;
+    let path = state.cached_table(&table, &build, &locale).await?;
+    send_table(&path, &headers).await
+}
+
+async fn newest_build(state: &ProxyState) -> Result<Build, ApiError> {
+    state.source.list_builds().await
+        .map_err(|e| ApiError::bad_gateway(e.to_string()))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| ApiError::not_found("No build available upstream".to_string()))
+}
+
+async fn builds(State(state): State<SharedProxyState>) -> Result<Json<Vec<String>>, ApiError> {
+    let builds = state.source.list_builds().await
+        .map_err(|e| ApiError::bad_gateway(e.to_string()))?;
+    Ok(Json(builds.iter().map(|b| b.to_string()).collect()))
+}
+
+async fn tables(
+    State(state): State<SharedProxyState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Vec<String>>, ApiError> {
+    let build = match params.get("build") {
+        Some(_) => resolve_build(&state.root, &params)?,
+        None => newest_build(&state).await?,
+    };
+    let mut tables = state.source.list_tables(&build).await
+        .map_err(|e| ApiError::bad_gateway(e.to_string()))?;
+    tables.sort();
+    Ok(Json(tables))
+}
+
+async fn status(State(state): State<SharedProxyState>) -> Json<CacheStatus> {
+    Json(state.status())
+}
+
+async fn metrics(State(state): State<SharedProxyState>) -> Response {
+    let mut body = String::new();
+    state.http.render(&mut body);
+    state.downloads.render(&mut body);
+    metrics_response(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+    use crate::config::HttpConfig;
+    use crate::services::http::build_client;
+    use crate::services::source::WagoSource;
+
+    async fn start(state: SharedProxyState) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = router(state);
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_proxy_caches_tables() {
+        let mut upstream = mockito::Server::new_async().await;
+        let mock = upstream.mock("GET", "/Spell/csv")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("build".into(), "11.0.5.57212".into()),
+                mockito::Matcher::UrlEncoded("locale".into(), "frFR".into()),
+            ]))
+            .with_body("ID,Name_lang\n1,Boule de feu\n")
+            .expect(1)
+            .create_async()
+            .await;
+        let failing = upstream.mock("GET", "/Missing/csv")
+            .match_query(mockito::Matcher::Any)
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let cache = TempDir::new().unwrap();
+        let client = build_client(&HttpConfig::default()).unwrap();
+        let source = Arc::new(WagoSource::new(client, &upstream.url(), "http://127.0.0.1:1/builds"));
+        let state = Arc::new(ProxyState::new(cache.path().to_path_buf(), source, RateLimiter::new(6000), Compression::Zstd));
+        let url = start(Arc::clone(&state)).await;
+
+        let client = reqwest::Client::new();
+        let table_url = format!("{}/db2/Spell/csv?build=11.0.5.57212&locale=frFR", url);
+        for _ in 0..2 {
+            let response = client.get(&table_url).send().await.unwrap();
+            assert_eq!(response.status(), 200);
+            assert_eq!(response.text().await.unwrap(), "ID,Name_lang\n1,Boule de feu\n");
+        }
+        mock.assert_async().await;
+        assert!(cache.path().join("11.0.5.57212").join("frFR").join("Spell.csv.zst").exists());
+
+        let missing = client.get(format!("{}/db2/Missing/csv?build=11.0.5.57212", url)).send().await.unwrap();
+        assert_eq!(missing.status(), 502);
+        failing.assert_async().await;
+        assert!(!cache.path().join("11.0.5.57212").join("enUS").join("Missing.csv.zst").exists());
+
+        let status: serde_json::Value = client.get(format!("{}/status", url)).send().await.unwrap().json().await.unwrap();
+        assert_eq!(status["hits"], 1);
+        assert_eq!(status["misses"], 2);
+        assert_eq!(status["upstream_errors"], 1);
+        assert!((status["hit_ratio"].as_f64().unwrap() - 1.0 / 3.0).abs() < 1e-9);
+        assert!(status["bytes_stored"].as_u64().unwrap() > 0);
+
+        let metrics = client.get(format!("{}/metrics", url)).send().await.unwrap().text().await.unwrap();
+        assert!(metrics.contains("wago_db2_requests_total{status=\"200\"} 1\n"));
+        assert!(metrics.contains("wago_db2_requests_total{status=\"404\"} 1\n"));
+        assert!(metrics.contains("wago_db2_http_requests_total{route=\"/db2/{table}/csv\",status=\"502\"} 1\n"));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_misses_fetch_once() {
+        let mut upstream = mockito::Server::new_async().await;
+        let mock = upstream.mock("GET", "/Map/csv")
+            .match_query(mockito::Matcher::Any)
+            .with_body("ID\n0\n")
+            .expect(1)
+            .create_async()
+            .await;
+
+        let cache = TempDir::new().unwrap();
+        let client = build_client(&HttpConfig::default()).unwrap();
+        let source = Arc::new(WagoSource::new(client, &upstream.url(), "http://127.0.0.1:1/builds"));
+        let state = Arc::new(ProxyState::new(cache.path().to_path_buf(), source, RateLimiter::new(6000), Compression::None));
+        let build: Build = "11.0.5.57212".parse().unwrap();
+
+        let requests = (0..4).map(|_| state.cached_table("Map", &build, "enUS"));
+        for path in futures::future::join_all(requests).await {
+            assert_eq!(fs::read_to_string(path.ok().unwrap()).unwrap(), "ID\n0\n");
+        }
+        mock.assert_async().await;
+        assert_eq!(state.status().hits, 3);
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+use std::str::FromStr;
+use anyhow::Result;
+use serde_json::{Map, Value};
+use crate::services::schema::ColumnType;
+use crate::utils::{table_path, TableData};
+
+const MAX_CELL_WIDTH: usize = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum QueryFormat {
+    Table,
+    Json,
+}
+
+/// `Table[:LocalColumn[=OtherColumn]]`, both columns defaulting to `ID`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JoinSpec {
+    pub table: String,
+    pub local_column: String,
+    pub other_column: String,
+}
+
+impl FromStr for JoinSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (table, columns) = match s.split_once(':') {
+            Some((table, columns)) => (table, Some(columns)),
+            None => (s, None),
+        };
+        let (local_column, other_column) = match columns {
+            Some(columns) => match columns.split_once('=') {
+                Some((local, other)) => (local, other),
+                None => (columns, "ID"),
+            },
+            None => ("ID", "ID"),
+        };
+        if table.is_empty() || local_column.is_empty() || other_column.is_empty() {
+            return Err(anyhow::anyhow!("Invalid join '{}', expected Table[:LocalColumn[=OtherColumn]]", s));
+        }
+        Ok(Self {
+            table: table.to_string(),
+            local_column: local_column.to_string(),
+            other_column: other_column.to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    pub ids: Vec<String>,
+    pub equals: Vec<(String, String)>,
+    pub contains: Option<String>,
+    pub join: Option<JoinSpec>,
+    pub columns: Vec<String>,
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct QueryResult {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    /// Matching rows before `limit` was applied
+    pub total: usize,
+}
+
+/// Columns holding at least one non-numeric value.
+fn string_columns(table: &TableData) -> Vec<usize> {
+    (0..table.headers.len())
+        .filter(|&i| {
+            table.rows.iter()
+                .filter_map(|r| r.get(i))
+                .any(|v| ColumnType::of_value(v) == ColumnType::String)
+        })
+        .collect()
+}
+
+fn column_index(table: &TableData, name: &str, table_name: &str) -> Result<usize> {
+    table.column(name)
+        .ok_or_else(|| anyhow::anyhow!("{} has no column {}", table_name, name))
+}
+
+pub fn run_query(root: &Path, build: &str, locale: &str, table_name: &str, query: &Query) -> Result<QueryResult> {
+    let table = TableData::load(&table_path(root, build, locale, table_name))?;
+
+    // ID lookups go through the ID index, everything else scans the rows
+    let mut matches: Vec<usize> = if query.ids.is_empty() {
+        (0..table.rows.len()).collect()
+    } else {
+        query.ids.iter().filter_map(|id| table.ids.get(id).copied()).collect()
+    };
+
+    for (column, value) in &query.equals {
+        let i = column_index(&table, column, table_name)?;
+        matches.retain(|&r| table.rows[r].get(i).is_some_and(|v| v == value));
+    }
+
+    if let Some(text) = &query.contains {
+        let text = text.to_lowercase();
+        let columns = string_columns(&table);
+        matches.retain(|&r| {
+            columns.iter().any(|&i| {
+                table.rows[r].get(i).is_some_and(|v| v.to_lowercase().contains(&text))
+            })
+        });
+    }
+
+    let mut headers = table.headers.clone();
+    let mut rows: Vec<Vec<String>> = match &query.join {
+        None => matches.iter().map(|&r| table.rows[r].clone()).collect(),
+        Some(join) => {
+            let other = TableData::load(&table_path(root, build, locale, &join.table))?;
+            let local = column_index(&table, &join.local_column, table_name)?;
+            let index = other.index(column_index(&other, &join.other_column, &join.table)?);
+
+            headers.extend(other.headers.iter().map(|h| format!("{}.{}", join.table, h)));
+            let empty = vec![String::new(); other.headers.len()];
+            let mut joined = Vec::new();
+            for &r in &matches {
+                let row = &table.rows[r];
+                let key = row.get(local).map(String::as_str).unwrap_or_default();
+                // Left join: rows without a match keep empty joined columns
+                match index.get(key) {
+                    Some(others) => {
+                        for &o in others {
+                            joined.push([row.clone(), other.rows[o].clone()].concat());
+                        }
+                    }
+                    None => joined.push([row.clone(), empty.clone()].concat()),
+                }
+            }
+            joined
+        }
+    };
+
+    if !query.columns.is_empty() {
+        let selected = query.columns.iter()
+            .map(|c| {
+                headers.iter()
+                    .position(|h| h == c)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown column {}", c))
+            })
+            .collect::<Result<Vec<usize>>>()?;
+        rows = rows.into_iter()
+            .map(|row| selected.iter().map(|&i| row.get(i).cloned().unwrap_or_default()).collect())
+            .collect();
+        headers = query.columns.clone();
+    }
+
+    let total = rows.len();
+    if query.limit > 0 {
+        rows.truncate(query.limit);
+    }
+    Ok(QueryResult { headers, rows, total })
+}
+
+impl QueryResult {
+    pub fn render(&self, format: QueryFormat) -> String {
+        match format {
+            QueryFormat::Table => self.render_table(),
+            QueryFormat::Json => self.render_json(),
+        }
+    }
+
+    pub fn render_table(&self) -> String {
+        let cell = |value: &str| -> String {
+            let value = value.replace(['\r', '\n'], " ");
+            if value.chars().count() > MAX_CELL_WIDTH {
+                let truncated: String = value.chars().take(MAX_CELL_WIDTH - 1).collect();
+                format!("{}…", truncated)
+            } else {
+                value
+            }
+        };
+        let rows: Vec<Vec<String>> = self.rows.iter()
+            .map(|row| row.iter().map(|v| cell(v)).collect())
+            .collect();
+
+        let mut widths: Vec<usize> = self.headers.iter().map(|h| h.chars().count()).collect();
+        for row in &rows {
+            for (width, value) in widths.iter_mut().zip(row) {
+                *width = (*width).max(value.chars().count());
+            }
+        }
+
+        let line = |values: &[String]| -> String {
+            values.iter()
+                .zip(&widths)
+                .map(|(v, &w)| format!("{}{}", v, " ".repeat(w - v.chars().count())))
+                .collect::<Vec<_>>()
+                .join(" | ")
+                .trim_end()
+                .to_string()
+        };
+
+        let mut out = String::new();
+        let _ = writeln!(out, "{}", line(&self.headers));
+        let _ = writeln!(out, "{}", widths.iter().map(|&w| "-".repeat(w)).collect::<Vec<_>>().join("-+-"));
+        for row in &rows {
+            let _ = writeln!(out, "{}", line(row));
+        }
+        if self.total > self.rows.len() {
+            let _ = writeln!(out, "({} of {} rows)", self.rows.len(), self.total);
+        } else {
+            let _ = writeln!(out, "({} rows)", self.total);
+        }
+        out
+    }
+
+    pub fn render_json(&self) -> String {
+        // Numeric columns become JSON numbers
+        let kinds: HashMap<usize, ColumnType> = (0..self.headers.len())
+            .map(|i| {
+                let kind = self.rows.iter()
+                    .filter_map(|r| r.get(i))
+                    .fold(ColumnType::Empty, |k, v| k.merge(ColumnType::of_value(v)));
+                (i, kind)
+            })
+            .collect();
+
+        let rows: Vec<Value> = self.rows.iter()
+            .map(|row| {
+                let mut object = Map::new();
+                for (i, (header, value)) in self.headers.iter().zip(row).enumerate() {
+                    let json = match kinds[&i] {
+                        ColumnType::Int => value.parse::<i64>().map(Value::from).ok(),
+                        ColumnType::Float => value.parse::<f64>().ok().map(Value::from),
+                        _ => None,
+                    };
+                    object.insert(header.clone(), json.unwrap_or_else(|| Value::from(value.as_str())));
+                }
+                Value::Object(object)
+            })
+            .collect();
+        serde_json::to_string_pretty(&rows).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn create_test_tree() -> TempDir {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().join("11.0.5.57212").join("enUS");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("SpellName.csv"), "ID,Name_lang\n133,Fireball\n116,Frostbolt\n1953,Blink\n").unwrap();
+        fs::write(dir.join("SpellMisc.csv"), "ID,SpellID,Speed\n10,133,24.5\n11,116,28\n12,116,30\n").unwrap();
+        temp_dir
+    }
+
+    fn query(root: &Path, table: &str, query: &Query) -> QueryResult {
+        run_query(root, "11.0.5.57212", "enUS", table, query).unwrap()
+    }
+
+    #[test]
+    fn test_lookup_by_id_and_equality() {
+        let tree = create_test_tree();
+        let by_id = query(tree.path(), "SpellName", &Query { ids: vec!["116".into()], ..Default::default() });
+        assert_eq!(by_id.rows, vec![vec!["116".to_string(), "Frostbolt".to_string()]]);
+
+        let equals = Query { equals: vec![("SpellID".into(), "116".into())], ..Default::default() };
+        assert_eq!(query(tree.path(), "SpellMisc", &equals).rows.len(), 2);
+    }
+
+    #[test]
+    fn test_substring_search() {
+        let tree = create_test_tree();
+        let found = query(tree.path(), "SpellName", &Query { contains: Some("FROST".into()), ..Default::default() });
+        assert_eq!(found.rows.len(), 1);
+        assert_eq!(found.rows[0][0], "116");
+
+        // Numeric columns are not searched
+        let numeric = query(tree.path(), "SpellName", &Query { contains: Some("13".into()), ..Default::default() });
+        assert!(numeric.rows.is_empty());
+    }
+
+    #[test]
+    fn test_join() {
+        let tree = create_test_tree();
+        let join = Query {
+            join: Some("SpellMisc:ID=SpellID".parse().unwrap()),
+            columns: vec!["ID".into(), "Name_lang".into(), "SpellMisc.Speed".into()],
+            ..Default::default()
+        };
+        let result = query(tree.path(), "SpellName", &join);
+        assert_eq!(result.rows, vec![
+            vec!["133".to_string(), "Fireball".to_string(), "24.5".to_string()],
+            vec!["116".to_string(), "Frostbolt".to_string(), "28".to_string()],
+            vec!["116".to_string(), "Frostbolt".to_string(), "30".to_string()],
+            vec!["1953".to_string(), "Blink".to_string(), String::new()],
+        ]);
+    }
+
+    #[test]
+    fn test_render() {
+        let tree = create_test_tree();
+        let result = query(tree.path(), "SpellMisc", &Query { limit: 1, ..Default::default() });
+
+        assert_eq!(result.render_table(), "ID | SpellID | Speed\n---+---------+------\n10 | 133     | 24.5\n(1 of 3 rows)\n");
+        let json: Value = serde_json::from_str(&result.render_json()).unwrap();
+        assert_eq!(json[0]["SpellID"], Value::from(133));
+        assert_eq!(json[0]["Speed"], Value::from(24.5));
+    }
+
+    #[test]
+    fn test_compressed_tables() {
+        let tree = create_test_tree();
+        let dir = tree.path().join("11.0.5.57212").join("enUS");
+        let content = fs::read(dir.join("SpellMisc.csv")).unwrap();
+        fs::remove_file(dir.join("SpellMisc.csv")).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, &content).unwrap();
+        fs::write(dir.join("SpellMisc.csv.gz"), encoder.finish().unwrap()).unwrap();
+        let content = fs::read(dir.join("SpellName.csv")).unwrap();
+        fs::remove_file(dir.join("SpellName.csv")).unwrap();
+        fs::write(dir.join("SpellName.csv.zst"), zstd::encode_all(content.as_slice(), 0).unwrap()).unwrap();
+
+        let join = Query { join: Some("SpellMisc:ID=SpellID".parse().unwrap()), ..Default::default() };
+        assert_eq!(query(tree.path(), "SpellName", &join).rows.len(), 4);
+        assert_eq!(crate::utils::list_tables(&dir).unwrap(), vec!["SpellMisc", "SpellName"]);
+    }
+
+    #[test]
+    fn test_join_spec() {
+        let spec: JoinSpec = "SpellName".parse().unwrap();
+        assert_eq!((spec.local_column.as_str(), spec.other_column.as_str()), ("ID", "ID"));
+        let spec: JoinSpec = "Map:MapID".parse().unwrap();
+        assert_eq!((spec.local_column.as_str(), spec.other_column.as_str()), ("MapID", "ID"));
+        assert!("Map:".parse::<JoinSpec>().is_err());
+    }
+}
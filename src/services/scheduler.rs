@@ -0,0 +1,121 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use crate::entities::Build;
+use crate::services::download_task::DownloadTask;
+use crate::utils::{list_builds, list_dirs, split_table_file};
+
+/// Local builds looked at to estimate the size of the tables.
+const SIZE_SAMPLE_BUILDS: usize = 3;
+
+/// Order in which the tables of a build and locale are downloaded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TableOrder {
+    /// Alphabetical
+    #[default]
+    Name,
+    /// Smallest first according to previous downloads, unknown tables last
+    SmallFirst,
+}
+
+/// Size of each table in the newest local builds, any locale.
+pub fn estimate_sizes(output_dir: &Path) -> HashMap<String, u64> {
+    let mut sizes = HashMap::new();
+    let builds = list_builds(output_dir).unwrap_or_default();
+    for build in builds.iter().rev().take(SIZE_SAMPLE_BUILDS) {
+        let build_dir = output_dir.join(build.format_full_version());
+        for locale in list_dirs(&build_dir).unwrap_or_default() {
+            let Ok(entries) = std::fs::read_dir(build_dir.join(locale)) else { continue };
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let Some((table, _)) = split_table_file(&name).filter(|(t, _)| !t.contains('.')) else { continue };
+                if let Ok(metadata) = entry.metadata() {
+                    sizes.entry(table.to_string()).or_insert(metadata.len());
+                }
+            }
+        }
+    }
+    sizes
+}
+
+/// Every table × build × locale download, in the order they are started.
+///
+/// Tables follow `order` and every build and locale advances at the same
+/// pace: the first table of each of them comes before any second table.
+/// The tables of `priority_locale` are all scheduled before the other locales.
+pub fn schedule_tasks(
+    tables: &HashSet<String>,
+    builds: &[Build],
+    locales: &[String],
+    order: TableOrder,
+    priority_locale: Option<&str>,
+    output_dir: &Path,
+) -> Vec<DownloadTask> {
+    let mut ordered: Vec<&String> = tables.iter().collect();
+    match order {
+        TableOrder::Name => ordered.sort(),
+        TableOrder::SmallFirst => {
+            let sizes = estimate_sizes(output_dir);
+            ordered.sort_by_key(|t| (sizes.get(*t).copied().unwrap_or(u64::MAX), *t));
+        }
+    }
+
+    let (first, others): (Vec<&String>, Vec<&String>) = locales.iter()
+        .partition(|l| Some(l.as_str()) == priority_locale);
+    let mut tasks = Vec::with_capacity(tables.len() * builds.len() * locales.len());
+    for group in [first, others] {
+        for table in &ordered {
+            for locale in &group {
+                for build in builds {
+                    tasks.push(DownloadTask::new(table.to_string(), build.clone(), locale.to_string()));
+                }
+            }
+        }
+    }
+    tasks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn names(tasks: &[DownloadTask]) -> Vec<String> {
+        tasks.iter().map(|t| format!("{} {} {}", t.table, t.build.build_number(), t.locale)).collect()
+    }
+
+    #[test]
+    fn test_interleaved_schedule() {
+        let tables = HashSet::from(["Spell".to_string(), "Map".to_string()]);
+        let builds = vec![Build::new("11.0.5", 57171), Build::new("11.0.5", 57212)];
+        let locales = vec!["frFR".to_string(), "enUS".to_string()];
+
+        let tasks = schedule_tasks(&tables, &builds, &locales, TableOrder::Name, None, Path::new("missing"));
+        assert_eq!(names(&tasks), vec![
+            "Map 57171 frFR", "Map 57212 frFR", "Map 57171 enUS", "Map 57212 enUS",
+            "Spell 57171 frFR", "Spell 57212 frFR", "Spell 57171 enUS", "Spell 57212 enUS",
+        ]);
+
+        let tasks = schedule_tasks(&tables, &builds, &locales, TableOrder::Name, Some("enUS"), Path::new("missing"));
+        assert_eq!(names(&tasks), vec![
+            "Map 57171 enUS", "Map 57212 enUS", "Spell 57171 enUS", "Spell 57212 enUS",
+            "Map 57171 frFR", "Map 57212 frFR", "Spell 57171 frFR", "Spell 57212 frFR",
+        ]);
+    }
+
+    #[test]
+    fn test_small_tables_first() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().join("11.0.5.57171").join("enUS");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("Spell.csv"), "x".repeat(1000)).unwrap();
+        fs::write(dir.join("Map.csv.zst"), "x".repeat(10)).unwrap();
+        fs::write(dir.join("Spell.filtered.csv"), "x").unwrap();
+
+        let tables = HashSet::from(["Spell".to_string(), "Map".to_string(), "Achievement".to_string()]);
+        let builds = vec![Build::new("11.0.5", 57212)];
+        let tasks = schedule_tasks(&tables, &builds, &["enUS".to_string()], TableOrder::SmallFirst, None, temp_dir.path());
+        assert_eq!(names(&tasks), vec!["Map 57212 enUS", "Spell 57212 enUS", "Achievement 57212 enUS"]);
+    }
+}
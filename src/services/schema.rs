@@ -0,0 +1,572 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use crate::entities::Build;
+use crate::services::definitions::{Definitions, TableLayout};
+use crate::utils::{list_builds, list_tables, open_table, table_path};
+
+const SCHEMA_FILE: &str = "schema.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnType {
+    Empty,
+    Int,
+    Float,
+    String,
+}
+
+impl ColumnType {
+    pub fn of_value(value: &str) -> Self {
+        if value.is_empty() {
+            ColumnType::Empty
+        } else if value.parse::<i64>().is_ok() || value.parse::<u64>().is_ok() {
+            ColumnType::Int
+        } else if value.parse::<f64>().is_ok() && value.bytes().any(|b| b.is_ascii_digit()) {
+            ColumnType::Float
+        } else {
+            ColumnType::String
+        }
+    }
+
+    /// Narrowest type able to hold values of both types.
+    pub fn merge(self, other: Self) -> Self {
+        use ColumnType::*;
+        match (self, other) {
+            (Empty, t) | (t, Empty) => t,
+            (String, _) | (_, String) => String,
+            (Float, _) | (_, Float) => Float,
+            (Int, Int) => Int,
+        }
+    }
+
+    /// Whether every value of type `self` can be stored in a column declared as `declared`.
+    pub fn fits(self, declared: Self) -> bool {
+        self == ColumnType::Empty || self.merge(declared) == declared
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ColumnType::Empty => "empty",
+            ColumnType::Int => "int",
+            ColumnType::Float => "float",
+            ColumnType::String => "string",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColumnSchema {
+    pub name: String,
+    /// Type inferred from the values
+    #[serde(rename = "type")]
+    pub kind: ColumnType,
+    /// Type declared by the definition layout, if any
+    #[serde(default, rename = "declared_type", skip_serializing_if = "Option::is_none")]
+    pub declared_kind: Option<ColumnType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub foreign_key: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub localized: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TableSchema {
+    pub columns: Vec<ColumnSchema>,
+}
+
+impl TableSchema {
+    pub fn column(&self, name: &str) -> Option<&ColumnSchema> {
+        self.columns.iter().find(|c| c.name == name)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.columns.iter().map(|c| c.name.as_str()).collect()
+    }
+
+    /// Widens the types of the columns to hold the values of `other` too,
+    /// e.g. the same table in another locale. Columns only in `other` are added.
+    pub fn merge(&mut self, other: &TableSchema) {
+        for column in &other.columns {
+            match self.columns.iter_mut().find(|c| c.name == column.name) {
+                Some(existing) => existing.kind = existing.kind.merge(column.kind),
+                None => self.columns.push(column.clone()),
+            }
+        }
+    }
+
+    /// Records the declared types, foreign keys and localized columns of a definition layout,
+    /// returning the columns whose values contradict the declared type.
+    pub fn annotate(&mut self, layout: &TableLayout) -> Vec<SchemaChange> {
+        let mut mismatches = Vec::new();
+        for field in &layout.fields {
+            for name in field.csv_columns() {
+                let Some(column) = self.columns.iter_mut().find(|c| c.name == name) else {
+                    continue;
+                };
+                let declared = field.kind.column_type();
+                if !column.kind.fits(declared) {
+                    mismatches.push(SchemaChange::DefinitionMismatch {
+                        table: layout.table.clone(),
+                        column: name,
+                        declared,
+                        found: column.kind,
+                    });
+                }
+                column.declared_kind = Some(declared);
+                column.foreign_key = field.foreign_key.as_ref()
+                    .map(|fk| format!("{}::{}", fk.table, fk.column));
+                column.localized = field.is_localized();
+            }
+        }
+        mismatches
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BuildSchema {
+    pub build: String,
+    pub tables: BTreeMap<String, TableSchema>,
+}
+
+impl BuildSchema {
+    pub fn path(root: &Path, build: &str) -> PathBuf {
+        root.join(build).join(SCHEMA_FILE)
+    }
+
+    pub fn load(root: &Path, build: &str) -> Result<Option<Self>> {
+        let path = Self::path(root, build);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(&path)?;
+        let schema = serde_json::from_str(&content)
+            .with_context(|| format!("Invalid schema file {}", path.display()))?;
+        Ok(Some(schema))
+    }
+
+    pub fn save(&self, root: &Path) -> Result<()> {
+        let path = Self::path(root, &self.build);
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RowIssue {
+    FieldCount { expected: usize, found: usize },
+    BrokenQuoting,
+    InvalidUtf8,
+}
+
+impl fmt::Display for RowIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RowIssue::FieldCount { expected, found } => {
+                write!(f, "expected {} fields, found {}", expected, found)
+            }
+            RowIssue::BrokenQuoting => write!(f, "broken quoting"),
+            RowIssue::InvalidUtf8 => write!(f, "invalid UTF-8"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MalformedRow {
+    pub table: String,
+    pub locale: String,
+    pub line: u64,
+    pub issue: RowIssue,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaChange {
+    ColumnsAdded { table: String, columns: Vec<String> },
+    ColumnsRemoved { table: String, columns: Vec<String> },
+    ColumnsReordered { table: String },
+    TypeChanged { table: String, column: String, from: ColumnType, to: ColumnType },
+    DefinitionMismatch { table: String, column: String, declared: ColumnType, found: ColumnType },
+}
+
+impl fmt::Display for SchemaChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaChange::ColumnsAdded { table, columns } => {
+                write!(f, "{}: columns added: {}", table, columns.join(", "))
+            }
+            SchemaChange::ColumnsRemoved { table, columns } => {
+                write!(f, "{}: columns removed: {}", table, columns.join(", "))
+            }
+            SchemaChange::ColumnsReordered { table } => write!(f, "{}: columns reordered", table),
+            SchemaChange::TypeChanged { table, column, from, to } => {
+                write!(f, "{}.{}: type changed from {} to {}", table, column, from, to)
+            }
+            SchemaChange::DefinitionMismatch { table, column, declared, found } => {
+                write!(f, "{}.{}: declared {} in definitions, found {} values", table, column, declared, found)
+            }
+        }
+    }
+}
+
+pub struct InferredTable {
+    pub schema: TableSchema,
+    pub malformed: Vec<MalformedRow>,
+}
+
+pub fn infer_table(path: &Path, table: &str, locale: &str) -> Result<InferredTable> {
+    let mut reader = open_table(path)?;
+    let headers: Vec<String> = reader.headers()?.iter().map(String::from).collect();
+    let mut kinds = vec![ColumnType::Empty; headers.len()];
+    let mut malformed = Vec::new();
+
+    let mut record = csv::ByteRecord::new();
+    loop {
+        let line = reader.position().line();
+        let issue = match reader.read_byte_record(&mut record) {
+            Ok(false) => break,
+            Ok(true) => check_record(&record, headers.len()),
+            Err(e) => match e.kind() {
+                csv::ErrorKind::Utf8 { .. } => Some(RowIssue::InvalidUtf8),
+                _ => return Err(e).with_context(|| format!("Unable to read {}", path.display())),
+            },
+        };
+
+        if let Some(issue) = issue {
+            malformed.push(MalformedRow {
+                table: table.to_string(),
+                locale: locale.to_string(),
+                line,
+                issue,
+            });
+            continue;
+        }
+
+        for (kind, field) in kinds.iter_mut().zip(record.iter()) {
+            // `check_record` guarantees valid UTF-8
+            let value = std::str::from_utf8(field).unwrap_or_default();
+            *kind = kind.merge(ColumnType::of_value(value));
+        }
+    }
+
+    let columns = headers.into_iter()
+        .zip(kinds)
+        .map(|(name, kind)| ColumnSchema { name, kind, declared_kind: None, foreign_key: None, localized: false })
+        .collect();
+    Ok(InferredTable { schema: TableSchema { columns }, malformed })
+}
+
+fn check_record(record: &csv::ByteRecord, expected: usize) -> Option<RowIssue> {
+    if record.len() != expected {
+        // An unbalanced quote swallows separators and line breaks into a single field
+        let swallowed = record.iter().any(|f| f.contains(&b'"') || f.contains(&b'\n'));
+        return Some(if swallowed {
+            RowIssue::BrokenQuoting
+        } else {
+            RowIssue::FieldCount { expected, found: record.len() }
+        });
+    }
+    // An unterminated quote can also swallow whole rows while keeping the field count right
+    if expected > 1 && record.iter().any(|f| swallows_row(f, expected)) {
+        return Some(RowIssue::BrokenQuoting);
+    }
+    if record.iter().any(|f| std::str::from_utf8(f).is_err()) {
+        return Some(RowIssue::InvalidUtf8);
+    }
+    None
+}
+
+fn swallows_row(field: &[u8], expected: usize) -> bool {
+    field.split(|&b| b == b'\n')
+        .skip(1)
+        .any(|line| line.iter().filter(|&&b| b == b',').count() >= expected - 1)
+}
+
+pub fn compare_schemas(old: &BuildSchema, new: &BuildSchema) -> Vec<SchemaChange> {
+    let mut changes = Vec::new();
+
+    for (table, new_table) in &new.tables {
+        let Some(old_table) = old.tables.get(table) else {
+            continue;
+        };
+
+        let added: Vec<String> = new_table.names().into_iter()
+            .filter(|c| old_table.column(c).is_none())
+            .map(String::from)
+            .collect();
+        let removed: Vec<String> = old_table.names().into_iter()
+            .filter(|c| new_table.column(c).is_none())
+            .map(String::from)
+            .collect();
+
+        if !added.is_empty() {
+            changes.push(SchemaChange::ColumnsAdded { table: table.clone(), columns: added.clone() });
+        }
+        if !removed.is_empty() {
+            changes.push(SchemaChange::ColumnsRemoved { table: table.clone(), columns: removed.clone() });
+        }
+
+        let old_order: Vec<&str> = old_table.names().into_iter()
+            .filter(|c| !removed.iter().any(|r| r == c))
+            .collect();
+        let new_order: Vec<&str> = new_table.names().into_iter()
+            .filter(|c| !added.iter().any(|a| a == c))
+            .collect();
+        if old_order != new_order {
+            changes.push(SchemaChange::ColumnsReordered { table: table.clone() });
+        }
+
+        for column in &new_table.columns {
+            let Some(old_column) = old_table.column(&column.name) else {
+                continue;
+            };
+            // A column without any value carries no type information
+            if old_column.kind != column.kind
+                && old_column.kind != ColumnType::Empty
+                && column.kind != ColumnType::Empty
+            {
+                changes.push(SchemaChange::TypeChanged {
+                    table: table.clone(),
+                    column: column.name.clone(),
+                    from: old_column.kind,
+                    to: column.kind,
+                });
+            }
+        }
+    }
+
+    changes
+}
+
+pub struct SchemaReport {
+    pub build: String,
+    pub previous_build: Option<String>,
+    pub tables: usize,
+    pub changes: Vec<SchemaChange>,
+    pub malformed: Vec<MalformedRow>,
+}
+
+impl SchemaReport {
+    pub fn is_clean(&self) -> bool {
+        self.changes.is_empty() && self.malformed.is_empty()
+    }
+}
+
+/// Infers the schema of every table downloaded for `build` in `locales`,
+/// merges it into `<build>/schema.json` and compares it with the closest older
+/// build that has one. Tables with a matching definition layout are annotated
+/// and checked against it.
+pub fn check_build(
+    root: &Path,
+    build: &Build,
+    locales: &[String],
+    definitions: Option<&Definitions>,
+) -> Result<SchemaReport> {
+    let build_name = build.format_full_version();
+    let mut schema = BuildSchema::load(root, &build_name)?
+        .unwrap_or_else(|| BuildSchema { build: build_name.clone(), tables: BTreeMap::new() });
+    let mut checked = BuildSchema { build: build_name.clone(), tables: BTreeMap::new() };
+    let mut malformed = Vec::new();
+
+    for locale in locales {
+        let locale_dir = root.join(&build_name).join(locale);
+        for table in list_tables(&locale_dir)? {
+            let inferred = infer_table(&table_path(root, &build_name, locale, &table), &table, locale)?;
+            malformed.extend(inferred.malformed);
+            match checked.tables.get_mut(&table) {
+                Some(existing) => existing.merge(&inferred.schema),
+                None => {
+                    checked.tables.insert(table, inferred.schema);
+                }
+            }
+        }
+    }
+    // Keeps the types seen in the locales of earlier checks
+    for (table, table_schema) in checked.tables.iter_mut() {
+        if let Some(saved) = schema.tables.get(table) {
+            table_schema.merge(saved);
+        }
+    }
+
+    let previous_build = find_previous_build(root, build)?;
+    let mut changes = match &previous_build {
+        Some(previous) => match BuildSchema::load(root, previous)? {
+            Some(previous_schema) => compare_schemas(&previous_schema, &checked),
+            None => vec![],
+        },
+        None => vec![],
+    };
+
+    if let Some(definitions) = definitions {
+        for (table, table_schema) in checked.tables.iter_mut() {
+            if let Some(layout) = definitions.layout(table, build) {
+                changes.extend(table_schema.annotate(&layout));
+            }
+        }
+    }
+
+    let tables = checked.tables.len();
+    if tables > 0 {
+        schema.tables.extend(checked.tables);
+        schema.save(root)?;
+    }
+
+    Ok(SchemaReport {
+        build: build_name,
+        previous_build,
+        tables,
+        changes,
+        malformed,
+    })
+}
+
+fn find_previous_build(root: &Path, build: &Build) -> Result<Option<String>> {
+    let previous = list_builds(root)?
+        .into_iter()
+        .filter(|b| b < build)
+        .filter(|b| BuildSchema::path(root, &b.format_full_version()).exists())
+        .max();
+    Ok(previous.map(|b| b.format_full_version()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn write_table(root: &Path, build: &str, table: &str, content: &str) -> PathBuf {
+        let path = table_path(root, build, "enUS", table);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_column_type_inference() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = write_table(
+            temp_dir.path(),
+            "11.0.5.57171",
+            "Spell",
+            "ID,Name,Scale,Flags,Unused\n1,Fireball,1.5,-1,\n2,\"Frost, Bolt\",2,4294967295,\n",
+        );
+
+        let inferred = infer_table(&path, "Spell", "enUS").unwrap();
+        let kinds: Vec<ColumnType> = inferred.schema.columns.iter().map(|c| c.kind).collect();
+        assert_eq!(kinds, vec![
+            ColumnType::Int,
+            ColumnType::String,
+            ColumnType::Float,
+            ColumnType::Int,
+            ColumnType::Empty,
+        ]);
+        assert!(inferred.malformed.is_empty());
+    }
+
+    #[test]
+    fn test_malformed_rows() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = write_table(
+            temp_dir.path(),
+            "11.0.5.57171",
+            "Spell",
+            "ID,Name\n1,Fireball\n2,Frostbolt,extra\n3,\"Blink\n4,Polymorph\n",
+        );
+
+        let inferred = infer_table(&path, "Spell", "enUS").unwrap();
+        assert_eq!(inferred.malformed.len(), 2);
+        assert_eq!(inferred.malformed[0].line, 3);
+        assert_eq!(inferred.malformed[0].issue, RowIssue::FieldCount { expected: 2, found: 3 });
+        assert_eq!(inferred.malformed[1].issue, RowIssue::BrokenQuoting);
+    }
+
+    #[test]
+    fn test_check_build_against_previous() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let locales = vec!["enUS".to_string()];
+
+        write_table(root, "11.0.5.57171", "Spell", "ID,Name,Flags\n1,Fireball,0\n");
+        let first = check_build(root, &"11.0.5.57171".parse().unwrap(), &locales, None).unwrap();
+        assert!(first.previous_build.is_none());
+        assert!(first.is_clean());
+        assert!(BuildSchema::path(root, "11.0.5.57171").exists());
+
+        write_table(root, "11.0.5.57212", "Spell", "ID,Flags,Name,School\n1,0.5,Fireball,4\n");
+        let second = check_build(root, &"11.0.5.57212".parse().unwrap(), &locales, None).unwrap();
+        assert_eq!(second.previous_build.as_deref(), Some("11.0.5.57171"));
+        assert_eq!(second.changes, vec![
+            SchemaChange::ColumnsAdded { table: "Spell".to_string(), columns: vec!["School".to_string()] },
+            SchemaChange::ColumnsReordered { table: "Spell".to_string() },
+            SchemaChange::TypeChanged {
+                table: "Spell".to_string(),
+                column: "Flags".to_string(),
+                from: ColumnType::Int,
+                to: ColumnType::Float,
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_check_build_merges_locales_and_runs() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let build: Build = "11.0.5.57212".parse().unwrap();
+        write_table(root, "11.0.5.57212", "Spell", "ID,Name,Scale\n1,Fireball,1\n");
+        write_table(root, "11.0.5.57212", "Map", "ID,Directory\n1,azeroth\n");
+        let fr_spell = table_path(root, "11.0.5.57212", "frFR", "Spell");
+        fs::create_dir_all(fr_spell.parent().unwrap()).unwrap();
+        fs::write(&fr_spell, "ID,Name,Scale\n1,Boule de feu,1.5\n").unwrap();
+
+        let both = vec!["enUS".to_string(), "frFR".to_string()];
+        check_build(root, &build, &both, None).unwrap();
+        let schema = BuildSchema::load(root, "11.0.5.57212").unwrap().unwrap();
+        assert_eq!(schema.tables["Spell"].column("Scale").unwrap().kind, ColumnType::Float);
+
+        // A later check of one locale keeps the other tables and types
+        fs::remove_file(table_path(root, "11.0.5.57212", "enUS", "Map")).unwrap();
+        let report = check_build(root, &build, &["enUS".to_string()], None).unwrap();
+        assert_eq!(report.tables, 1);
+        let schema = BuildSchema::load(root, "11.0.5.57212").unwrap().unwrap();
+        assert!(schema.tables.contains_key("Map"));
+        assert_eq!(schema.tables["Spell"].column("Scale").unwrap().kind, ColumnType::Float);
+    }
+
+    #[test]
+    fn test_annotate_with_definitions() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = write_table(
+            temp_dir.path(),
+            "11.0.5.57212",
+            "SpellName",
+            "ID,Name_lang,SpellID,Scale\n1,Fireball,10,1.5\n2,Frostbolt,11.5,\n",
+        );
+        let definition = crate::services::definitions::TableDefinition::parse(
+            "SpellName",
+            "COLUMNS\nint ID\nlocstring Name_lang\nint<Spell::ID> SpellID\nfloat Scale\n\n\
+             BUILD 11.0.5.57212\n$id$ID<32>\nName_lang\nSpellID<32>\nScale\n",
+        ).unwrap();
+        let layout = definition.layout_for(&"11.0.5.57212".parse().unwrap()).unwrap();
+
+        let mut schema = infer_table(&path, "SpellName", "enUS").unwrap().schema;
+        let mismatches = schema.annotate(&layout);
+
+        assert_eq!(mismatches, vec![SchemaChange::DefinitionMismatch {
+            table: "SpellName".to_string(),
+            column: "SpellID".to_string(),
+            declared: ColumnType::Int,
+            found: ColumnType::Float,
+        }]);
+        assert!(schema.column("Name_lang").unwrap().localized);
+        assert_eq!(schema.column("SpellID").unwrap().foreign_key.as_deref(), Some("Spell::ID"));
+        assert_eq!(schema.column("Scale").unwrap().kind, ColumnType::Float);
+        // The inferred type is kept next to the declared one
+        let spell_id = schema.column("SpellID").unwrap();
+        assert_eq!((spell_id.kind, spell_id.declared_kind), (ColumnType::Float, Some(ColumnType::Int)));
+    }
+}
@@ -0,0 +1,344 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use crate::services::schema::ColumnType;
+use crate::utils::{id_column, list_dirs, list_tables, open_table, table_path};
+
+const INDEX_DIR: &str = ".search";
+
+/// Lowercased words of `text`. Han and kana characters are indexed one by one
+/// since those scripts do not separate words with spaces.
+pub fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    for c in text.chars() {
+        if is_ideographic(c) {
+            if !current.is_empty() {
+                tokens.push(std::mem::take(&mut current));
+            }
+            tokens.push(c.to_string());
+        } else if c.is_alphanumeric() {
+            current.extend(c.to_lowercase());
+        } else if !current.is_empty() {
+            tokens.push(std::mem::take(&mut current));
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+fn is_ideographic(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF    // Hiragana, Katakana
+        | 0x3400..=0x4DBF  // CJK Extension A
+        | 0x4E00..=0x9FFF  // CJK Unified Ideographs
+        | 0xF900..=0xFAFF  // CJK Compatibility Ideographs
+    )
+}
+
+/// Inverted index of the string cells of one `<build>/<locale>/<table>.csv` file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Segment {
+    source_size: u64,
+    source_modified: u64,
+    ids: Vec<String>,
+    columns: Vec<String>,
+    /// Token → (row, column) cells containing it
+    postings: BTreeMap<String, Vec<(u32, u16)>>,
+}
+
+impl Segment {
+    fn build(source: &Path) -> Result<Self> {
+        let (source_size, source_modified) = source_stamp(source)?;
+        let mut reader = open_table(source)?;
+        let columns: Vec<String> = reader.headers()?.iter().map(String::from).collect();
+        let id_index = id_column(&columns);
+
+        let mut ids = Vec::new();
+        let mut postings: BTreeMap<String, Vec<(u32, u16)>> = BTreeMap::new();
+        let mut record = csv::StringRecord::new();
+        while reader.read_record(&mut record)? {
+            let row = ids.len() as u32;
+            ids.push(record.get(id_index).unwrap_or_default().to_string());
+            for (column, value) in record.iter().enumerate() {
+                if ColumnType::of_value(value) != ColumnType::String {
+                    continue;
+                }
+                let tokens: HashSet<String> = tokenize(value).into_iter().collect();
+                for token in tokens {
+                    postings.entry(token).or_default().push((row, column as u16));
+                }
+            }
+        }
+
+        Ok(Self { source_size, source_modified, ids, columns, postings })
+    }
+
+    /// Cells containing every token of the query.
+    fn candidates(&self, tokens: &[String]) -> Vec<(u32, u16)> {
+        let mut cells: Option<HashSet<(u32, u16)>> = None;
+        for token in tokens {
+            let found: HashSet<(u32, u16)> = self.postings.get(token)
+                .map(|p| p.iter().copied().collect())
+                .unwrap_or_default();
+            cells = Some(match cells {
+                Some(cells) => cells.intersection(&found).copied().collect(),
+                None => found,
+            });
+        }
+        let mut cells: Vec<(u32, u16)> = cells.unwrap_or_default().into_iter().collect();
+        cells.sort();
+        cells
+    }
+}
+
+fn source_stamp(path: &Path) -> Result<(u64, u64)> {
+    let metadata = path.metadata()?;
+    let modified = metadata.modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    Ok((metadata.len(), modified))
+}
+
+fn index_dir(root: &Path, build: &str) -> PathBuf {
+    root.join(build).join(INDEX_DIR)
+}
+
+fn segment_path(root: &Path, build: &str, locale: &str, table: &str) -> PathBuf {
+    index_dir(root, build).join(locale).join(format!("{}.json", table))
+}
+
+pub fn has_index(root: &Path, build: &str) -> bool {
+    index_dir(root, build).is_dir()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IndexStats {
+    pub indexed: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+}
+
+/// Brings the index of `build` up to date: files that changed since they were
+/// indexed are indexed again and segments of deleted files are dropped.
+pub fn update_index(root: &Path, build: &str) -> Result<IndexStats> {
+    let mut stats = IndexStats::default();
+
+    for locale in list_dirs(&root.join(build))? {
+        let tables = list_tables(&root.join(build).join(&locale))?;
+        for table in &tables {
+            let source = table_path(root, build, &locale, table);
+            let path = segment_path(root, build, &locale, table);
+            if let Some(segment) = read_segment(&path)? {
+                if (segment.source_size, segment.source_modified) == source_stamp(&source)? {
+                    stats.unchanged += 1;
+                    continue;
+                }
+            }
+            let segment = Segment::build(&source)?;
+            std::fs::create_dir_all(path.parent().unwrap_or(root))?;
+            std::fs::write(&path, serde_json::to_vec(&segment)?)?;
+            stats.indexed += 1;
+        }
+
+        for segment in list_segments(&index_dir(root, build).join(&locale))? {
+            if !tables.contains(&segment) {
+                std::fs::remove_file(segment_path(root, build, &locale, &segment))?;
+                stats.removed += 1;
+            }
+        }
+    }
+
+    Ok(stats)
+}
+
+fn read_segment(path: &Path) -> Result<Option<Segment>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = std::fs::read(path)?;
+    // A corrupted segment is simply rebuilt
+    Ok(serde_json::from_slice(&content).ok())
+}
+
+fn list_segments(dir: &Path) -> Result<Vec<String>> {
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if let Some(table) = name.strip_suffix(".json") {
+            names.push(table.to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SearchHit {
+    pub locale: String,
+    pub table: String,
+    pub id: String,
+    pub column: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+    pub locales: Vec<String>,
+    pub tables: Vec<String>,
+    pub limit: usize,
+}
+
+/// Rows of `build` whose string columns contain `phrase`, case-insensitive.
+pub fn search(root: &Path, build: &str, phrase: &str, options: &SearchOptions) -> Result<Vec<SearchHit>> {
+    let tokens = tokenize(phrase);
+    if tokens.is_empty() {
+        return Ok(vec![]);
+    }
+    let needle = phrase.to_lowercase();
+    let mut hits = Vec::new();
+
+    for locale in list_dirs(&index_dir(root, build))? {
+        if !options.locales.is_empty() && !options.locales.contains(&locale) {
+            continue;
+        }
+        for table in list_segments(&index_dir(root, build).join(&locale))? {
+            if !options.tables.is_empty() && !options.tables.contains(&table) {
+                continue;
+            }
+            let Some(segment) = read_segment(&segment_path(root, build, &locale, &table))? else {
+                continue;
+            };
+            let candidates = segment.candidates(&tokens);
+            if candidates.is_empty() {
+                continue;
+            }
+
+            // The index only knows words, the phrase itself is checked against the file
+            let texts = read_cells(&table_path(root, build, &locale, &table), &candidates)?;
+            for (row, column) in candidates {
+                let text = &texts[&(row, column)];
+                if text.to_lowercase().contains(&needle) {
+                    hits.push(SearchHit {
+                        locale: locale.clone(),
+                        table: table.clone(),
+                        id: segment.ids[row as usize].clone(),
+                        column: segment.columns[column as usize].clone(),
+                        text: text.clone(),
+                    });
+                    if options.limit > 0 && hits.len() >= options.limit {
+                        return Ok(hits);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(hits)
+}
+
+fn read_cells(path: &Path, cells: &[(u32, u16)]) -> Result<HashMap<(u32, u16), String>> {
+    let wanted: HashSet<u32> = cells.iter().map(|&(row, _)| row).collect();
+    let mut texts = HashMap::new();
+    let mut reader = open_table(path)?;
+    let mut record = csv::StringRecord::new();
+    let mut row = 0u32;
+    while reader.read_record(&mut record)
+        .with_context(|| format!("Unable to read {}", path.display()))?
+    {
+        if wanted.contains(&row) {
+            for &(r, column) in cells.iter().filter(|&&(r, _)| r == row) {
+                texts.insert((r, column), record.get(column as usize).unwrap_or_default().to_string());
+            }
+        }
+        row += 1;
+    }
+    // Cells missing from a file changed since indexing simply do not match
+    for cell in cells {
+        texts.entry(*cell).or_default();
+    }
+    Ok(texts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    const BUILD: &str = "11.0.5.57212";
+
+    fn write_table(root: &Path, locale: &str, table: &str, content: &str) {
+        let path = table_path(root, BUILD, locale, table);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn create_test_tree() -> TempDir {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        write_table(root, "enUS", "SpellName", "ID,Name_lang\n133,Fireball\n116,Frostbolt\n2136,Fire Blast\n");
+        write_table(root, "frFR", "SpellName", "ID,Name_lang\n133,Boule de feu\n116,Éclair de givre\n2136,Trait de feu\n");
+        write_table(root, "zhCN", "SpellName", "ID,Name_lang\n133,火球术\n116,寒冰箭\n");
+        write_table(root, "enUS", "Map", "ID,Directory,MapName_lang\n0,Azeroth,Eastern Kingdoms\n");
+        temp_dir
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(tokenize("Boule de Feu!"), vec!["boule", "de", "feu"]);
+        assert_eq!(tokenize("Éclair"), vec!["éclair"]);
+        assert_eq!(tokenize("火球术 x2"), vec!["火", "球", "术", "x2"]);
+    }
+
+    #[test]
+    fn test_search_phrase_across_locales() {
+        let tree = create_test_tree();
+        let root = tree.path();
+        let stats = update_index(root, BUILD).unwrap();
+        assert_eq!(stats.indexed, 4);
+
+        let hits = search(root, BUILD, "de feu", &SearchOptions::default()).unwrap();
+        let ids: Vec<&str> = hits.iter().map(|h| h.id.as_str()).collect();
+        assert_eq!(ids, vec!["133", "2136"]);
+        assert!(hits.iter().all(|h| h.locale == "frFR" && h.column == "Name_lang"));
+
+        // Both words are present but not as a phrase
+        assert!(search(root, BUILD, "feu de", &SearchOptions::default()).unwrap().is_empty());
+
+        let hits = search(root, BUILD, "火球", &SearchOptions::default()).unwrap();
+        assert_eq!(hits[0].locale, "zhCN");
+        assert_eq!(hits[0].text, "火球术");
+
+        let filtered = SearchOptions { tables: vec!["Map".into()], ..Default::default() };
+        let hits = search(root, BUILD, "eastern", &filtered).unwrap();
+        assert_eq!(hits[0].column, "MapName_lang");
+    }
+
+    #[test]
+    fn test_incremental_update() {
+        let tree = create_test_tree();
+        let root = tree.path();
+        update_index(root, BUILD).unwrap();
+
+        let again = update_index(root, BUILD).unwrap();
+        assert_eq!(again, IndexStats { indexed: 0, unchanged: 4, removed: 0 });
+
+        write_table(root, "enUS", "SpellName", "ID,Name_lang\n133,Fireball\n116,Frostbolt\n2136,Fire Blast\n999,Pyroblast\n");
+        fs::remove_file(table_path(root, BUILD, "enUS", "Map")).unwrap();
+        let stats = update_index(root, BUILD).unwrap();
+        assert_eq!(stats, IndexStats { indexed: 1, unchanged: 2, removed: 1 });
+
+        let hits = search(root, BUILD, "pyroblast", &SearchOptions::default()).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(search(root, BUILD, "eastern", &SearchOptions::default()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,323 @@
+use std::collections::{BTreeSet, HashMap};
+use std::io::Read;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use anyhow::Result;
+use axum::body::Body;
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{middleware, Json, Router};
+use bytes::Bytes;
+use tokio_util::io::ReaderStream;
+use tokio_util::sync::CancellationToken;
+use crate::entities::Build;
+use crate::services::metrics::{metrics_response, track_requests, HttpMetrics};
+use crate::utils::{list_builds, list_dirs, list_tables, table_path, Compression};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// State shared by the request handlers.
+pub struct ServerState {
+    pub root: PathBuf,
+    pub metrics: Arc<HttpMetrics>,
+}
+
+impl ServerState {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root, metrics: Arc::default() }
+    }
+}
+
+pub type SharedState = Arc<ServerState>;
+
+/// Routes of the local mirror, using the wago.tools URL scheme.
+pub fn router(state: SharedState) -> Router {
+    Router::new()
+        .route("/db2/{table}/csv", get(table_csv))
+        .route("/builds", get(builds))
+        .route("/tables", get(tables))
+        .route("/metrics", get(metrics))
+        .layer(middleware::from_fn_with_state(Arc::clone(&state.metrics), track_requests))
+        .with_state(state)
+}
+
+/// Serves `router` until `shutdown` is cancelled, letting requests in
+/// progress complete.
+pub async fn serve(router: Router, listen: SocketAddr, shutdown: CancellationToken) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(listen).await?;
+    axum::serve(listener, router)
+        .with_graceful_shutdown(shutdown.cancelled_owned())
+        .await?;
+    Ok(())
+}
+
+/// Error answered with a status code and a plain-text message.
+pub struct ApiError(StatusCode, String);
+
+impl ApiError {
+    pub fn not_found(message: String) -> Self {
+        ApiError(StatusCode::NOT_FOUND, message)
+    }
+
+    pub fn bad_request(message: String) -> Self {
+        ApiError(StatusCode::BAD_REQUEST, message)
+    }
+
+    pub fn bad_gateway(message: String) -> Self {
+        ApiError(StatusCode::BAD_GATEWAY, message)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, self.1).into_response()
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    }
+}
+
+impl From<std::io::Error> for ApiError {
+    fn from(e: std::io::Error) -> Self {
+        ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    }
+}
+
+/// `build` and `locale` query parameters, defaulting like wago.tools does.
+pub fn resolve_request(
+    root: &std::path::Path,
+    table: &str,
+    params: &HashMap<String, String>,
+) -> Result<(Build, String), ApiError> {
+    if table.is_empty() || !table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(ApiError::bad_request(format!("Invalid table name '{}'", table)));
+    }
+    let locale = params.get("locale").cloned().unwrap_or_else(|| "enUS".to_string());
+    if !locale.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(ApiError::bad_request(format!("Invalid locale '{}'", locale)));
+    }
+    Ok((resolve_build(root, params)?, locale))
+}
+
+/// `build` query parameter, the newest downloaded build by default.
+pub fn resolve_build(root: &std::path::Path, params: &HashMap<String, String>) -> Result<Build, ApiError> {
+    match params.get("build") {
+        Some(build) => build.parse()
+            .map_err(|_| ApiError::bad_request(format!("Invalid build '{}'", build))),
+        None => list_builds(root)?
+            .pop()
+            .ok_or_else(|| ApiError::not_found("No build downloaded yet".to_string())),
+    }
+}
+
+fn accepts(headers: &HeaderMap, encoding: &str) -> bool {
+    headers.get_all(header::ACCEPT_ENCODING).iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|token| {
+            let mut parts = token.split(';').map(str::trim);
+            let name = parts.next().unwrap_or_default();
+            let refused = parts.any(|p| p.replace(' ', "") == "q=0");
+            (name.eq_ignore_ascii_case(encoding) || name == "*") && !refused
+        })
+}
+
+/// Streams a table file as CSV. Compressed files go out as they are when the
+/// client accepts their encoding, and are decoded on the fly otherwise.
+pub async fn send_table(path: &std::path::Path, headers: &HeaderMap) -> Result<Response, ApiError> {
+    let compression = Compression::of_path(path);
+    let encoding = match compression {
+        Compression::None => None,
+        Compression::Zstd => Some("zstd"),
+        Compression::Gzip => Some("gzip"),
+    };
+
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/csv; charset=utf-8"));
+
+    let body = match encoding {
+        Some(encoding) if !accepts(headers, encoding) => {
+            let (sender, receiver) = tokio::sync::mpsc::channel::<std::io::Result<Bytes>>(4);
+            let path = path.to_path_buf();
+            tokio::task::spawn_blocking(move || {
+                let mut reader = match open_decoded(&path, compression) {
+                    Ok(reader) => reader,
+                    Err(e) => {
+                        let _ = sender.blocking_send(Err(e));
+                        return;
+                    }
+                };
+                let mut buffer = vec![0; CHUNK_SIZE];
+                loop {
+                    match reader.read(&mut buffer) {
+                        Ok(0) => break,
+                        Ok(n) => {
+                            if sender.blocking_send(Ok(Bytes::copy_from_slice(&buffer[..n]))).is_err() {
+                                break;
+                            }
+                        }
+                        Err(e) => {
+                            let _ = sender.blocking_send(Err(e));
+                            break;
+                        }
+                    }
+                }
+            });
+            Body::from_stream(tokio_stream_from(receiver))
+        }
+        _ => {
+            if let Some(encoding) = encoding {
+                response_headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding));
+            }
+            let file = tokio::fs::File::open(path).await?;
+            response_headers.insert(header::CONTENT_LENGTH, file.metadata().await?.len().into());
+            Body::from_stream(ReaderStream::new(file))
+        }
+    };
+    Ok((response_headers, body).into_response())
+}
+
+fn open_decoded(path: &std::path::Path, compression: Compression) -> std::io::Result<Box<dyn Read + Send>> {
+    let file = std::fs::File::open(path)?;
+    Ok(match compression {
+        Compression::None => Box::new(file),
+        Compression::Zstd => Box::new(zstd::Decoder::new(file)?),
+        Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(std::io::BufReader::new(file))),
+    })
+}
+
+fn tokio_stream_from<T: Send + 'static>(
+    mut receiver: tokio::sync::mpsc::Receiver<T>,
+) -> impl futures::Stream<Item = T> + Send {
+    futures::stream::poll_fn(move |cx| receiver.poll_recv(cx))
+}
+
+async fn table_csv(
+    State(state): State<SharedState>,
+    Path(table): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let (build, locale) = resolve_request(&state.root, &table, &params)?;
+    let path = table_path(&state.root, &build.format_full_version(), &locale, &table);
+    if !path.exists() {
+        return Err(ApiError::not_found(format!("{} is not available for {} {}", table, build, locale)));
+    }
+    send_table(&path, &headers).await
+}
+
+async fn metrics(State(state): State<SharedState>) -> Response {
+    let mut body = String::new();
+    state.metrics.render(&mut body);
+    metrics_response(body)
+}
+
+/// Downloaded builds, newest first.
+async fn builds(State(state): State<SharedState>) -> Result<Json<Vec<String>>, ApiError> {
+    let builds = list_builds(&state.root)?;
+    Ok(Json(builds.iter().rev().map(|b| b.to_string()).collect()))
+}
+
+/// Tables downloaded for `build` (the newest by default) in any locale.
+async fn tables(
+    State(state): State<SharedState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Vec<String>>, ApiError> {
+    let build = resolve_build(&state.root, &params)?;
+    let build_dir = state.root.join(build.format_full_version());
+    let mut tables = BTreeSet::new();
+    for locale in list_dirs(&build_dir)? {
+        tables.extend(list_tables(&build_dir.join(locale))?);
+    }
+    Ok(Json(tables.into_iter().collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::fs;
+    use tempfile::TempDir;
+    use crate::services::downloader::DownloadService;
+
+    async fn start(root: &std::path::Path) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = router(Arc::new(ServerState::new(root.to_path_buf())));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    fn create_test_tree() -> TempDir {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().join("11.0.5.57212");
+        fs::create_dir_all(dir.join("enUS")).unwrap();
+        fs::create_dir_all(dir.join("frFR")).unwrap();
+        fs::create_dir_all(temp_dir.path().join("11.0.5.57171")).unwrap();
+        fs::write(dir.join("enUS").join("Spell.csv"), "ID,Name_lang\n1,Fireball\n").unwrap();
+        fs::write(dir.join("frFR").join("Map.csv.zst"), zstd::encode_all(&b"ID\n0\n"[..], 0).unwrap()).unwrap();
+        temp_dir
+    }
+
+    #[tokio::test]
+    async fn test_endpoints() {
+        let tree = create_test_tree();
+        let url = start(tree.path()).await;
+        let client = reqwest::Client::new();
+
+        let builds: Vec<String> = client.get(format!("{}/builds", url)).send().await.unwrap().json().await.unwrap();
+        assert_eq!(builds, vec!["11.0.5.57212", "11.0.5.57171"]);
+        let tables: Vec<String> = client.get(format!("{}/tables?build=11.0.5.57212", url)).send().await.unwrap().json().await.unwrap();
+        assert_eq!(tables, vec!["Map", "Spell"]);
+
+        // Newest build and enUS by default
+        let spell = client.get(format!("{}/db2/Spell/csv", url)).send().await.unwrap();
+        assert_eq!(spell.text().await.unwrap(), "ID,Name_lang\n1,Fireball\n");
+
+        let map = client.get(format!("{}/db2/Map/csv?build=11.0.5.57212&locale=frFR", url)).send().await.unwrap();
+        assert!(map.headers().get("content-encoding").is_none());
+        assert_eq!(map.text().await.unwrap(), "ID\n0\n");
+
+        let missing = client.get(format!("{}/db2/Map/csv?build=11.0.5.57171", url)).send().await.unwrap();
+        assert_eq!(missing.status(), 404);
+        let invalid = client.get(format!("{}/db2/Map/csv?locale=../..", url)).send().await.unwrap();
+        assert_eq!(invalid.status(), 400);
+
+        let metrics = client.get(format!("{}/metrics", url)).send().await.unwrap().text().await.unwrap();
+        assert!(metrics.contains("wago_db2_http_requests_total{route=\"/db2/{table}/csv\",status=\"200\"} 2\n"));
+        assert!(metrics.contains("wago_db2_http_requests_total{route=\"/db2/{table}/csv\",status=\"404\"} 1\n"));
+    }
+
+    #[tokio::test]
+    async fn test_download_from_mirror() {
+        let tree = create_test_tree();
+        let url = start(tree.path()).await;
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut service = DownloadService::new(format!("{}/db2", url)).unwrap();
+        service.set_output_dir(temp_dir.path());
+        let tables = HashSet::from(["Map".to_string()]);
+        let report = service.download_all(&tables, &["11.0.5.57212".parse().unwrap()], &["frFR".to_string()]).await.unwrap().total;
+
+        // Sent compressed as the client accepts zstd
+        assert_eq!(report.downloaded, 1);
+        assert!(report.bytes_on_wire < report.bytes_decoded + 10);
+        let path = temp_dir.path().join("11.0.5.57212").join("frFR").join("Map.csv");
+        assert_eq!(fs::read_to_string(path).unwrap(), "ID\n0\n");
+    }
+
+    #[test]
+    fn test_accept_encoding() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT_ENCODING, HeaderValue::from_static("gzip;q=0.5, zstd;q=0, br"));
+        assert!(accepts(&headers, "gzip"));
+        assert!(!accepts(&headers, "zstd"));
+        assert!(!accepts(&HeaderMap::new(), "gzip"));
+    }
+}
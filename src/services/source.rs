@@ -0,0 +1,417 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use reqwest::Client;
+use serde::Deserialize;
+use tokio_util::io::ReaderStream;
+use crate::config::AppConfig;
+use crate::data::tables::get_available_tables;
+use crate::entities::Build;
+use crate::services::http::build_client;
+use crate::utils::{list_dirs, table_path, Compression};
+
+pub const WAGO_BUILDS_URL: &str = "https://wago.tools/api/builds";
+
+/// `[source]` section: where builds, tables and table files come from.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+pub enum SourceConfig {
+    /// wago.tools, tables fetched from `base_url`
+    Wago {
+        #[serde(default = "default_wago_builds_url")]
+        builds_url: String,
+    },
+    /// A `<build>/<locale>/<table>.csv` tree, e.g. an offline snapshot
+    Local { path: PathBuf },
+    /// Any server, `url` holding `{table}`, `{build}`, `{locale}`, `{version}` and `{build_number}`
+    Template {
+        url: String,
+        /// Returns a JSON array of build strings
+        builds_url: Option<String>,
+        /// Returns a JSON array of table names, `{build}` style placeholders allowed
+        tables_url: Option<String>,
+    },
+}
+
+fn default_wago_builds_url() -> String {
+    WAGO_BUILDS_URL.to_string()
+}
+
+impl Default for SourceConfig {
+    fn default() -> Self {
+        SourceConfig::Wago { builds_url: default_wago_builds_url() }
+    }
+}
+
+/// Body of a fetched table, still in its transfer encoding.
+pub struct TableStream {
+    /// Response status, none when the table was not fetched over HTTP
+    pub status: Option<reqwest::StatusCode>,
+    pub content_encoding: Option<String>,
+    pub body: BoxStream<'static, Result<Bytes>>,
+}
+
+#[async_trait]
+pub trait DataSource: Send + Sync {
+    fn describe(&self) -> String;
+
+    /// Known builds, newest first.
+    async fn list_builds(&self) -> Result<Vec<Build>>;
+
+    async fn list_tables(&self, build: &Build) -> Result<Vec<String>>;
+
+    /// Newest `count` builds of each of `products` (all of them when empty),
+    /// newest first. Sources without products return their newest builds.
+    async fn latest_builds(&self, _products: &[String], count: usize) -> Result<Vec<Build>> {
+        Ok(self.list_builds().await?.into_iter().take(count).collect())
+    }
+
+    async fn fetch_table(&self, table: &str, build: &Build, locale: &str) -> Result<TableStream>;
+}
+
+fn fill_template(template: &str, table: &str, build: &Build, locale: &str) -> String {
+    template
+        .replace("{table}", table)
+        .replace("{build}", &build.format_full_version())
+        .replace("{locale}", locale)
+        .replace("{version}", build.version())
+        .replace("{build_number}", &build.build_number().to_string())
+}
+
+fn newest_first(builds: impl IntoIterator<Item = Build>) -> Vec<Build> {
+    let builds: BTreeSet<Build> = builds.into_iter().collect();
+    builds.into_iter().rev().collect()
+}
+
+/// A table request answered with an error status.
+#[derive(Debug)]
+pub struct StatusError {
+    pub table: String,
+    pub status: reqwest::StatusCode,
+}
+
+impl std::fmt::Display for StatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Download error: {}: {}", self.table, self.status)
+    }
+}
+
+impl std::error::Error for StatusError {}
+
+/// Errors worth retrying, also telling the adaptive concurrency to back off:
+/// 408, 429, 5xx, timeouts and dropped connections.
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    let transient = |status: reqwest::StatusCode| {
+        status == reqwest::StatusCode::REQUEST_TIMEOUT
+            || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            || status.is_server_error()
+    };
+    if let Some(e) = error.downcast_ref::<StatusError>() {
+        return transient(e.status);
+    }
+    if let Some(e) = error.downcast_ref::<reqwest::Error>() {
+        return e.is_timeout() || e.is_connect() || e.is_body() || e.status().is_some_and(transient);
+    }
+    false
+}
+
+async fn fetch_url(client: &Client, url: &str, table: &str) -> Result<TableStream> {
+    let response = client.get(url).send().await?;
+    if !response.status().is_success() {
+        return Err(StatusError { table: table.to_string(), status: response.status() }.into());
+    }
+    let content_encoding = response.headers()
+        .get(reqwest::header::CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    Ok(TableStream {
+        status: Some(response.status()),
+        content_encoding,
+        body: response.bytes_stream().map_err(anyhow::Error::from).boxed(),
+    })
+}
+
+async fn fetch_json<T: serde::de::DeserializeOwned>(client: &Client, url: &str) -> Result<T> {
+    let response = client.get(url).send().await?.error_for_status()?;
+    response.json().await.with_context(|| format!("Unexpected response from {}", url))
+}
+
+pub struct WagoSource {
+    client: Client,
+    base_url: String,
+    builds_url: String,
+}
+
+#[derive(Deserialize)]
+struct WagoBuild {
+    version: String,
+}
+
+impl WagoSource {
+    pub fn new(client: Client, base_url: &str, builds_url: &str) -> Self {
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            builds_url: builds_url.to_string(),
+        }
+    }
+
+    /// Builds grouped by product: {"wow": [{"version": "11.0.5.57212", ...}], "wowt": [...]}
+    async fn products(&self) -> Result<HashMap<String, Vec<Build>>> {
+        let products: HashMap<String, Vec<WagoBuild>> = fetch_json(&self.client, &self.builds_url).await?;
+        Ok(products.into_iter()
+            .map(|(product, builds)| (product, builds.into_iter().filter_map(|b| b.version.parse().ok()).collect()))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl DataSource for WagoSource {
+    fn describe(&self) -> String {
+        self.base_url.clone()
+    }
+
+    async fn list_builds(&self) -> Result<Vec<Build>> {
+        Ok(newest_first(self.products().await?.into_values().flatten()))
+    }
+
+    async fn latest_builds(&self, products: &[String], count: usize) -> Result<Vec<Build>> {
+        let mut listed = self.products().await?;
+        let selected = if products.is_empty() {
+            listed.keys().cloned().collect()
+        } else {
+            products.to_vec()
+        };
+        let mut builds = Vec::new();
+        for product in &selected {
+            let product_builds = listed.remove(product).ok_or_else(|| {
+                let mut known: Vec<&String> = listed.keys().collect();
+                known.sort();
+                anyhow::anyhow!("Unknown product '{}', expected one of {:?}", product, known)
+            })?;
+            builds.extend(newest_first(product_builds).into_iter().take(count));
+        }
+        Ok(newest_first(builds))
+    }
+
+    async fn list_tables(&self, _build: &Build) -> Result<Vec<String>> {
+        // wago.tools has no table listing, use the bundled one
+        let mut tables: Vec<String> = get_available_tables().into_iter().collect();
+        tables.sort();
+        Ok(tables)
+    }
+
+    async fn fetch_table(&self, table: &str, build: &Build, locale: &str) -> Result<TableStream> {
+        let url = format!(
+            "{}/{}/csv?build={}&locale={}",
+            self.base_url, table, build.format_full_version(), locale
+        );
+        fetch_url(&self.client, &url, table).await
+    }
+}
+
+pub struct LocalSource {
+    root: PathBuf,
+}
+
+impl LocalSource {
+    pub fn new(root: &Path) -> Self {
+        Self { root: root.to_path_buf() }
+    }
+}
+
+#[async_trait]
+impl DataSource for LocalSource {
+    fn describe(&self) -> String {
+        self.root.display().to_string()
+    }
+
+    async fn list_builds(&self) -> Result<Vec<Build>> {
+        Ok(newest_first(crate::utils::list_builds(&self.root)?))
+    }
+
+    async fn list_tables(&self, build: &Build) -> Result<Vec<String>> {
+        let build_dir = self.root.join(build.format_full_version());
+        let mut tables = BTreeSet::new();
+        for locale in list_dirs(&build_dir)? {
+            tables.extend(crate::utils::list_tables(&build_dir.join(locale))?);
+        }
+        Ok(tables.into_iter().collect())
+    }
+
+    async fn fetch_table(&self, table: &str, build: &Build, locale: &str) -> Result<TableStream> {
+        let path = table_path(&self.root, &build.format_full_version(), locale, table);
+        if !path.exists() {
+            return Err(anyhow::anyhow!("Download error: {}: not found in {}", table, self.root.display()));
+        }
+        // Compressed files are handed over as is and decoded like a compressed response
+        let content_encoding = match Compression::of_path(&path) {
+            Compression::None => None,
+            Compression::Zstd => Some("zstd".to_string()),
+            Compression::Gzip => Some("gzip".to_string()),
+        };
+        let file = tokio::fs::File::open(&path).await
+            .with_context(|| format!("Unable to open {}", path.display()))?;
+        Ok(TableStream {
+            status: None,
+            content_encoding,
+            body: ReaderStream::new(file).map_err(anyhow::Error::from).boxed(),
+        })
+    }
+}
+
+pub struct TemplateSource {
+    client: Client,
+    url: String,
+    builds_url: Option<String>,
+    tables_url: Option<String>,
+}
+
+impl TemplateSource {
+    pub fn new(client: Client, url: &str, builds_url: Option<&str>, tables_url: Option<&str>) -> Self {
+        Self {
+            client,
+            url: url.to_string(),
+            builds_url: builds_url.map(str::to_string),
+            tables_url: tables_url.map(str::to_string),
+        }
+    }
+}
+
+#[async_trait]
+impl DataSource for TemplateSource {
+    fn describe(&self) -> String {
+        self.url.clone()
+    }
+
+    async fn list_builds(&self) -> Result<Vec<Build>> {
+        let url = self.builds_url.as_deref()
+            .ok_or_else(|| anyhow::anyhow!("No builds_url configured for {}", self.url))?;
+        let builds: Vec<String> = fetch_json(&self.client, url).await?;
+        builds.iter().map(|b| b.parse()).collect::<Result<Vec<Build>>>().map(newest_first)
+    }
+
+    async fn list_tables(&self, build: &Build) -> Result<Vec<String>> {
+        let template = self.tables_url.as_deref()
+            .ok_or_else(|| anyhow::anyhow!("No tables_url configured for {}", self.url))?;
+        fetch_json(&self.client, &fill_template(template, "", build, "")).await
+    }
+
+    async fn fetch_table(&self, table: &str, build: &Build, locale: &str) -> Result<TableStream> {
+        fetch_url(&self.client, &fill_template(&self.url, table, build, locale), table).await
+    }
+}
+
+pub fn from_config(config: &AppConfig) -> Result<Arc<dyn DataSource>> {
+    Ok(match &config.source {
+        SourceConfig::Wago { builds_url } => {
+            Arc::new(WagoSource::new(build_client(&config.http)?, &config.base_url, builds_url))
+        }
+        SourceConfig::Local { path } => Arc::new(LocalSource::new(path)),
+        SourceConfig::Template { url, builds_url, tables_url } => Arc::new(TemplateSource::new(
+            build_client(&config.http)?,
+            url,
+            builds_url.as_deref(),
+            tables_url.as_deref(),
+        )),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    async fn read_all(stream: TableStream) -> Vec<u8> {
+        let chunks: Vec<Bytes> = stream.body.try_collect().await.unwrap();
+        chunks.concat()
+    }
+
+    fn build() -> Build {
+        "11.0.5.57212".parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_wago_source() {
+        let mut server = mockito::Server::new_async().await;
+        let _builds = server.mock("GET", "/api/builds")
+            .with_body(r#"{"wow": [{"version": "11.0.5.57212", "product": "wow"}, {"version": "11.0.5.57171"}],
+                "wowt": [{"version": "11.1.0.58000"}, {"version": "11.0.5.57212"}]}"#)
+            .create_async()
+            .await;
+        let _table = server.mock("GET", "/db2/Spell/csv?build=11.0.5.57212&locale=frFR")
+            .with_body("ID\n1\n")
+            .create_async()
+            .await;
+
+        let source = WagoSource::new(Client::new(), &format!("{}/db2/", server.url()), &format!("{}/api/builds", server.url()));
+        let builds: Vec<String> = source.list_builds().await.unwrap().iter().map(|b| b.to_string()).collect();
+        assert_eq!(builds, vec!["11.1.0.58000", "11.0.5.57212", "11.0.5.57171"]);
+        let latest: Vec<String> = source.latest_builds(&["wow".to_string()], 1).await.unwrap().iter().map(|b| b.to_string()).collect();
+        assert_eq!(latest, vec!["11.0.5.57212"]);
+        assert_eq!(source.latest_builds(&[], 1).await.unwrap().len(), 2);
+        assert!(source.latest_builds(&["wow_beta".to_string()], 1).await.is_err());
+        assert!(source.list_tables(&build()).await.unwrap().contains(&"Achievement".to_string()));
+        assert_eq!(read_all(source.fetch_table("Spell", &build(), "frFR").await.unwrap()).await, b"ID\n1\n");
+        assert!(source.fetch_table("Map", &build(), "frFR").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_template_source() {
+        let mut server = mockito::Server::new_async().await;
+        let _table = server.mock("GET", "/dumps/11.0.5/57212/deDE/Map.csv")
+            .with_body("ID\n0\n")
+            .create_async()
+            .await;
+        let _tables = server.mock("GET", "/dumps/11.0.5.57212/tables.json")
+            .with_body(r#"["Map", "Spell"]"#)
+            .create_async()
+            .await;
+
+        let source = TemplateSource::new(
+            Client::new(),
+            &format!("{}/dumps/{{version}}/{{build_number}}/{{locale}}/{{table}}.csv", server.url()),
+            None,
+            Some(&format!("{}/dumps/{{build}}/tables.json", server.url())),
+        );
+        assert_eq!(read_all(source.fetch_table("Map", &build(), "deDE").await.unwrap()).await, b"ID\n0\n");
+        assert_eq!(source.list_tables(&build()).await.unwrap(), vec!["Map", "Spell"]);
+        assert!(source.list_builds().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_local_source() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().join("11.0.5.57212");
+        fs::create_dir_all(dir.join("enUS")).unwrap();
+        fs::create_dir_all(dir.join("frFR")).unwrap();
+        fs::create_dir_all(temp_dir.path().join("11.0.5.57171")).unwrap();
+        fs::write(dir.join("enUS").join("Spell.csv"), "ID\n1\n").unwrap();
+        fs::write(dir.join("frFR").join("Map.csv.zst"), zstd::encode_all(&b"ID\n0\n"[..], 0).unwrap()).unwrap();
+
+        let source = LocalSource::new(temp_dir.path());
+        let builds: Vec<String> = source.list_builds().await.unwrap().iter().map(|b| b.to_string()).collect();
+        assert_eq!(builds, vec!["11.0.5.57212", "11.0.5.57171"]);
+        assert_eq!(source.list_tables(&build()).await.unwrap(), vec!["Map", "Spell"]);
+
+        let map = source.fetch_table("Map", &build(), "frFR").await.unwrap();
+        assert_eq!(map.content_encoding.as_deref(), Some("zstd"));
+        assert_eq!(read_all(source.fetch_table("Spell", &build(), "enUS").await.unwrap()).await, b"ID\n1\n");
+        assert!(source.fetch_table("Spell", &build(), "frFR").await.is_err());
+    }
+
+    #[test]
+    fn test_source_config() {
+        let config: AppConfig = toml::from_str("[source]\nkind = \"local\"\npath = \"/mnt/db2\"\n").unwrap();
+        assert_eq!(config.source, SourceConfig::Local { path: "/mnt/db2".into() });
+        let config: AppConfig = toml::from_str("").unwrap();
+        assert_eq!(config.source, SourceConfig::default());
+        assert!(toml::from_str::<AppConfig>("[source]\nkind = \"ftp\"\n").is_err());
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::utils::{ensure_dir_exists, list_dirs};
+
+const OBJECTS_DIR: &str = ".objects";
+const MANIFEST_FILE: &str = "store.json";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreMode {
+    /// A full copy of every file per build
+    #[default]
+    Plain,
+    /// Files stored once by hash in `.objects`, builds hold hardlinks to them
+    Cas,
+}
+
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path)
+        .with_context(|| format!("Unable to read {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Files of a build stored in the object store, by path relative to the build directory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub files: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    pub fn path(root: &Path, build: &str) -> PathBuf {
+        root.join(build).join(MANIFEST_FILE)
+    }
+
+    pub fn load(root: &Path, build: &str) -> Result<Self> {
+        let path = Self::path(root, build);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Unable to read {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Invalid store manifest {}", path.display()))
+    }
+
+    pub fn save(&self, root: &Path, build: &str) -> Result<()> {
+        let path = Self::path(root, build);
+        if self.files.is_empty() {
+            if path.exists() {
+                fs::remove_file(&path)?;
+            }
+            return Ok(());
+        }
+        fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Unable to write {}", path.display()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StoreStats {
+    pub stored: usize,
+    pub deduplicated: usize,
+    pub bytes_saved: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GcStats {
+    pub removed: usize,
+    pub bytes_freed: u64,
+    pub kept: usize,
+}
+
+/// Object store shared by every build under `root`.
+#[derive(Debug, Clone)]
+pub struct ObjectStore {
+    root: PathBuf,
+}
+
+impl ObjectStore {
+    pub fn new(root: &Path) -> Self {
+        Self { root: root.to_path_buf() }
+    }
+
+    fn objects_dir(&self) -> PathBuf {
+        self.root.join(OBJECTS_DIR)
+    }
+
+    pub fn object_path(&self, hash: &str) -> PathBuf {
+        self.objects_dir().join(&hash[..2]).join(hash)
+    }
+
+    /// Moves the given files of a build into the store and links them back in place.
+    /// `files` are relative to the build directory, e.g. `enUS/Spell.csv`.
+    pub fn store_build(&self, build: &str, files: &[String]) -> Result<StoreStats> {
+        let mut manifest = Manifest::load(&self.root, build)?;
+        let mut stats = StoreStats::default();
+
+        for file in files {
+            let path = self.root.join(build).join(file);
+            if !path.is_file() {
+                continue;
+            }
+            // Already linked to its object by a previous run
+            if let Some(hash) = manifest.files.get(file) {
+                let object = self.object_path(hash);
+                if object.exists() && same_size(&object, &path) {
+                    continue;
+                }
+            }
+
+            let hash = sha256_file(&path)?;
+            let object = self.object_path(&hash);
+            if object.exists() {
+                stats.bytes_saved += path.metadata()?.len();
+                stats.deduplicated += 1;
+                fs::remove_file(&path)?;
+            } else {
+                ensure_dir_exists(object.parent().unwrap_or(&self.root))?;
+                fs::rename(&path, &object)
+                    .with_context(|| format!("Unable to move {} to the object store", path.display()))?;
+                set_readonly(&object)?;
+                stats.stored += 1;
+            }
+            link(&object, &path)?;
+            manifest.files.insert(file.clone(), hash);
+        }
+
+        manifest.save(&self.root, build)?;
+        Ok(stats)
+    }
+
+    /// Removes the objects no build references anymore. Manifest entries whose file
+    /// was deleted are dropped first, so removing `<build>/<locale>/<table>.csv` or a
+    /// whole build is enough to release its objects.
+    pub fn gc(&self, dry_run: bool) -> Result<GcStats> {
+        let mut referenced = HashSet::new();
+        for build in list_dirs(&self.root)? {
+            let mut manifest = Manifest::load(&self.root, &build)?;
+            let before = manifest.files.len();
+            manifest.files.retain(|file, _| self.root.join(&build).join(file).is_file());
+            if !dry_run && manifest.files.len() != before {
+                manifest.save(&self.root, &build)?;
+            }
+            referenced.extend(manifest.files.into_values());
+        }
+
+        let mut stats = GcStats::default();
+        for prefix in list_dirs(&self.objects_dir())? {
+            let dir = self.objects_dir().join(prefix);
+            for entry in fs::read_dir(&dir)? {
+                let entry = entry?;
+                let hash = entry.file_name().to_string_lossy().to_string();
+                if referenced.contains(&hash) {
+                    stats.kept += 1;
+                    continue;
+                }
+                stats.removed += 1;
+                stats.bytes_freed += entry.metadata()?.len();
+                if !dry_run {
+                    fs::remove_file(entry.path())?;
+                }
+            }
+            if !dry_run && fs::read_dir(&dir)?.next().is_none() {
+                fs::remove_dir(&dir)?;
+            }
+        }
+        Ok(stats)
+    }
+}
+
+fn same_size(a: &Path, b: &Path) -> bool {
+    match (a.metadata(), b.metadata()) {
+        (Ok(a), Ok(b)) => a.len() == b.len(),
+        _ => false,
+    }
+}
+
+/// Every build linked to an object shares its content, so it must only be
+/// replaced by a new file, never written in place.
+fn set_readonly(object: &Path) -> Result<()> {
+    let mut permissions = object.metadata()?.permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(object, permissions)
+        .with_context(|| format!("Unable to make {} read-only", object.display()))
+}
+
+/// Hardlinks `path` to `object`, copying when the filesystem has no hardlinks.
+fn link(object: &Path, path: &Path) -> Result<()> {
+    if fs::hard_link(object, path).is_err() {
+        fs::copy(object, path)
+            .with_context(|| format!("Unable to link {} to {}", path.display(), object.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(root: &Path, path: &str, content: &str) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn files() -> Vec<String> {
+        vec!["enUS/Spell.csv".to_string(), "enUS/Map.csv".to_string()]
+    }
+
+    #[test]
+    fn test_identical_files_are_stored_once() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        write(root, "11.0.5.57171/enUS/Spell.csv", "ID\n1\n");
+        write(root, "11.0.5.57171/enUS/Map.csv", "ID\n0\n");
+        write(root, "11.0.5.57212/enUS/Spell.csv", "ID\n1\n");
+        write(root, "11.0.5.57212/enUS/Map.csv", "ID\n0\n1\n");
+
+        let store = ObjectStore::new(root);
+        let first = store.store_build("11.0.5.57171", &files()).unwrap();
+        assert_eq!(first, StoreStats { stored: 2, deduplicated: 0, bytes_saved: 0 });
+        let second = store.store_build("11.0.5.57212", &files()).unwrap();
+        assert_eq!(second, StoreStats { stored: 1, deduplicated: 1, bytes_saved: 5 });
+
+        // Paths still read as before and a second run has nothing to do
+        assert_eq!(fs::read_to_string(root.join("11.0.5.57212/enUS/Spell.csv")).unwrap(), "ID\n1\n");
+        assert_eq!(store.store_build("11.0.5.57212", &files()).unwrap(), StoreStats::default());
+
+        let manifest = Manifest::load(root, "11.0.5.57212").unwrap();
+        let hash = &manifest.files["enUS/Spell.csv"];
+        assert_eq!(hash, &sha256_file(&root.join("11.0.5.57171/enUS/Spell.csv")).unwrap());
+        assert!(store.object_path(hash).exists());
+        assert_eq!(fs::read_dir(root.join(OBJECTS_DIR)).unwrap().flatten()
+            .map(|d| fs::read_dir(d.path()).unwrap().count())
+            .sum::<usize>(), 3);
+    }
+
+    #[test]
+    fn test_changing_a_build_leaves_the_others_intact() {
+        use crate::services::filter::{apply_filter, FilterMode, RowFilter};
+
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        write(root, "11.0.5.57171/enUS/Spell.csv", "ID\n1\n2\n");
+        write(root, "11.0.5.57212/enUS/Spell.csv", "ID\n1\n2\n");
+        let store = ObjectStore::new(root);
+        store.store_build("11.0.5.57171", &files()).unwrap();
+        store.store_build("11.0.5.57212", &files()).unwrap();
+        let hash = Manifest::load(root, "11.0.5.57171").unwrap().files["enUS/Spell.csv"].clone();
+        let object = store.object_path(&hash);
+        assert!(fs::metadata(root.join("11.0.5.57171/enUS/Spell.csv")).unwrap().permissions().readonly());
+
+        let filter: RowFilter = "ID = 1".parse().unwrap();
+        apply_filter(&filter, &root.join("11.0.5.57171/enUS/Spell.csv"), FilterMode::Replace).unwrap();
+
+        assert_eq!(fs::read_to_string(root.join("11.0.5.57171/enUS/Spell.csv")).unwrap(), "ID\n1\n");
+        assert_eq!(fs::read_to_string(root.join("11.0.5.57212/enUS/Spell.csv")).unwrap(), "ID\n1\n2\n");
+        assert_eq!(fs::read_to_string(&object).unwrap(), "ID\n1\n2\n");
+        assert_eq!(sha256_file(&object).unwrap(), hash);
+    }
+
+    #[test]
+    fn test_gc_removes_unreferenced_objects() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        write(root, "11.0.5.57171/enUS/Spell.csv", "ID\n1\n");
+        write(root, "11.0.5.57171/enUS/Map.csv", "ID\n0\n");
+        write(root, "11.0.5.57212/enUS/Spell.csv", "ID\n1\n");
+        let store = ObjectStore::new(root);
+        store.store_build("11.0.5.57171", &files()).unwrap();
+        store.store_build("11.0.5.57212", &files()).unwrap();
+
+        assert_eq!(store.gc(false).unwrap(), GcStats { removed: 0, bytes_freed: 0, kept: 2 });
+
+        fs::remove_dir_all(root.join("11.0.5.57171")).unwrap();
+        assert_eq!(store.gc(true).unwrap(), GcStats { removed: 1, bytes_freed: 5, kept: 1 });
+        assert_eq!(store.gc(false).unwrap(), GcStats { removed: 1, bytes_freed: 5, kept: 1 });
+        assert_eq!(store.gc(false).unwrap(), GcStats { removed: 0, bytes_freed: 0, kept: 1 });
+
+        // Deleting a single file releases its object too
+        fs::remove_file(root.join("11.0.5.57212/enUS/Spell.csv")).unwrap();
+        assert_eq!(store.gc(false).unwrap().removed, 1);
+        assert!(!Manifest::path(root, "11.0.5.57212").exists());
+    }
+}
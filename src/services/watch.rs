@@ -0,0 +1,147 @@
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use anyhow::Result;
+use serde::Deserialize;
+use crate::config::AppConfig;
+use crate::entities::Build;
+use crate::services::source::DataSource;
+use crate::utils::{BandwidthLimiter, ByteRate};
+
+/// How often the configuration file is checked for a new `limit_rate`.
+const CONFIG_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// `[watch]` section, the defaults of the `watch` command.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchConfig {
+    pub interval_secs: u64,
+    /// wago.tools products such as `wow`, `wowt` or `wow_beta`, all of them when empty
+    pub products: Vec<String>,
+    /// How many of the newest builds of each product are kept downloaded
+    pub latest: usize,
+    pub locales: Vec<String>,
+    /// Tables to download, all tables when empty
+    pub tables: Vec<String>,
+    /// Shell command run after each downloaded build
+    pub hook: Option<String>,
+    /// Address of the Prometheus `/metrics` endpoint, none by default
+    pub metrics_listen: Option<SocketAddr>,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 600,
+            products: Vec::new(),
+            latest: 1,
+            locales: vec!["enUS".to_string()],
+            tables: Vec::new(),
+            hook: None,
+            metrics_listen: None,
+        }
+    }
+}
+
+/// Applies the `limit_rate` of the configuration file at `path` to
+/// `bandwidth` whenever it changes in the file. `file_rate` is the value the
+/// file had when watching started, so that edits to other keys leave a rate
+/// given with `--limit-rate` in place.
+pub async fn follow_config(path: PathBuf, mut file_rate: Option<ByteRate>, bandwidth: BandwidthLimiter) {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut last_modified = modified(&path);
+    loop {
+        tokio::time::sleep(CONFIG_CHECK_INTERVAL).await;
+        let current = modified(&path);
+        if current == last_modified {
+            continue;
+        }
+        last_modified = current;
+        reload_rate(&path, &mut file_rate, &bandwidth);
+    }
+}
+
+fn reload_rate(path: &Path, file_rate: &mut Option<ByteRate>, bandwidth: &BandwidthLimiter) {
+    match AppConfig::load(Some(path)) {
+        Ok(config) if config.limit_rate != *file_rate => {
+            *file_rate = config.limit_rate;
+            bandwidth.set_rate(config.limit_rate);
+            match config.limit_rate {
+                Some(rate) => tracing::info!("Bandwidth limited to {}", rate),
+                None => tracing::info!("Bandwidth no longer limited"),
+            }
+        }
+        Ok(_) => {}
+        Err(e) => tracing::error!("{:#}", e),
+    }
+}
+
+/// Newest builds of the watched products that are not in `output_dir` yet,
+/// oldest first, plus the ones whose last download failed.
+pub async fn pending_builds(
+    source: &dyn DataSource,
+    products: &[String],
+    latest: usize,
+    output_dir: &Path,
+    failed: &HashSet<Build>,
+) -> Result<Vec<Build>> {
+    let mut builds: Vec<Build> = source.latest_builds(products, latest).await?
+        .into_iter()
+        .filter(|build| failed.contains(build) || !output_dir.join(build.format_full_version()).exists())
+        .collect();
+    builds.reverse();
+    Ok(builds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+    use crate::services::source::WagoSource;
+
+    #[tokio::test]
+    async fn test_pending_builds() {
+        let mut server = mockito::Server::new_async().await;
+        let _builds = server.mock("GET", "/api/builds")
+            .with_body(r#"{"wow": [{"version": "11.0.5.57212"}, {"version": "11.0.5.57171"}],
+                "wowt": [{"version": "11.1.0.58000"}], "wow_classic": [{"version": "4.4.1.57294"}]}"#)
+            .create_async()
+            .await;
+        let source = WagoSource::new(reqwest::Client::new(), &server.url(), &format!("{}/api/builds", server.url()));
+        let output_dir = TempDir::new().unwrap();
+        fs::create_dir(output_dir.path().join("11.0.5.57212")).unwrap();
+        let products = vec!["wow".to_string(), "wowt".to_string()];
+
+        let pending = pending_builds(&source, &products, 1, output_dir.path(), &HashSet::new()).await.unwrap();
+        assert_eq!(pending, vec!["11.1.0.58000".parse().unwrap()]);
+
+        let failed = HashSet::from(["11.0.5.57212".parse().unwrap()]);
+        let pending = pending_builds(&source, &products, 2, output_dir.path(), &failed).await.unwrap();
+        let pending: Vec<String> = pending.iter().map(|b| b.to_string()).collect();
+        assert_eq!(pending, vec!["11.0.5.57171", "11.0.5.57212", "11.1.0.58000"]);
+    }
+
+    #[test]
+    fn test_reload_rate_keeps_cli_rate() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("wago-db2.toml");
+        fs::write(&path, "requests_per_minute = 100\n").unwrap();
+        // --limit-rate 1M, no limit_rate in the file
+        let bandwidth = BandwidthLimiter::new(Some(ByteRate(1024 * 1024)));
+        let mut file_rate = None;
+
+        fs::write(&path, "requests_per_minute = 200\n").unwrap();
+        reload_rate(&path, &mut file_rate, &bandwidth);
+        assert_eq!(bandwidth.rate(), Some(ByteRate(1024 * 1024)));
+
+        fs::write(&path, "limit_rate = \"2M\"\n").unwrap();
+        reload_rate(&path, &mut file_rate, &bandwidth);
+        assert_eq!(bandwidth.rate(), Some(ByteRate(2 * 1024 * 1024)));
+
+        fs::write(&path, "requests_per_minute = 200\n").unwrap();
+        reload_rate(&path, &mut file_rate, &bandwidth);
+        assert_eq!(bandwidth.rate(), None);
+    }
+}
@@ -0,0 +1,179 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use serde::Deserialize;
+use tokio::time::{sleep, Duration, Instant};
+
+/// Bytes per second, written `500K`, `2M` or `2MB/s` (1024 based) or as a number of bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "RawByteRate")]
+pub struct ByteRate(pub u64);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawByteRate {
+    Bytes(u64),
+    Text(String),
+}
+
+impl TryFrom<RawByteRate> for ByteRate {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: RawByteRate) -> Result<Self, Self::Error> {
+        match raw {
+            RawByteRate::Bytes(bytes) => Ok(ByteRate(bytes)),
+            RawByteRate::Text(text) => text.parse(),
+        }
+    }
+}
+
+impl std::str::FromStr for ByteRate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || anyhow::anyhow!("Invalid rate '{}', expected e.g. 500K or 2MB/s", s);
+        let text = s.trim().to_ascii_uppercase();
+        let text = text.strip_suffix("/S").unwrap_or(&text);
+        let text = text.strip_suffix('B').unwrap_or(text);
+        let (number, multiplier) = match text.chars().last() {
+            Some('K') => (&text[..text.len() - 1], 1024.0),
+            Some('M') => (&text[..text.len() - 1], 1024.0 * 1024.0),
+            Some('G') => (&text[..text.len() - 1], 1024.0 * 1024.0 * 1024.0),
+            _ => (text, 1.0),
+        };
+        let value: f64 = number.trim().parse().map_err(|_| invalid())?;
+        if value <= 0.0 || !value.is_finite() {
+            return Err(invalid());
+        }
+        Ok(ByteRate((value * multiplier) as u64))
+    }
+}
+
+impl std::fmt::Display for ByteRate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.0 >= 1024 * 1024 {
+            write!(f, "{:.1} MB/s", self.0 as f64 / (1024.0 * 1024.0))
+        } else {
+            write!(f, "{:.1} KB/s", self.0 as f64 / 1024.0)
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// Token bucket shared by every clone, holding at most one second of
+/// traffic. The rate can be changed while downloads are running.
+#[derive(Debug, Clone)]
+pub struct BandwidthLimiter {
+    /// Bytes per second, 0 when unlimited
+    rate: Arc<AtomicU64>,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+impl BandwidthLimiter {
+    pub fn new(rate: Option<ByteRate>) -> Self {
+        let limiter = Self {
+            rate: Arc::new(AtomicU64::new(0)),
+            bucket: Arc::new(Mutex::new(Bucket { tokens: 0.0, refilled_at: Instant::now() })),
+        };
+        limiter.set_rate(rate);
+        limiter
+    }
+
+    pub fn rate(&self) -> Option<ByteRate> {
+        match self.rate.load(Ordering::Relaxed) {
+            0 => None,
+            rate => Some(ByteRate(rate)),
+        }
+    }
+
+    pub fn set_rate(&self, rate: Option<ByteRate>) {
+        let rate = rate.map_or(0, |r| r.0);
+        self.rate.store(rate, Ordering::Relaxed);
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.tokens = rate as f64;
+        bucket.refilled_at = Instant::now();
+    }
+
+    /// Takes `bytes` from the bucket, waiting for it to refill when it runs dry.
+    pub async fn consume(&self, bytes: usize) {
+        let rate = self.rate.load(Ordering::Relaxed) as f64;
+        if rate == 0.0 {
+            return;
+        }
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = Instant::now();
+            let refill = now.duration_since(bucket.refilled_at).as_secs_f64() * rate;
+            bucket.tokens = (bucket.tokens + refill).min(rate) - bytes as f64;
+            bucket.refilled_at = now;
+            // Later callers wait for the debt of the previous ones too
+            if bucket.tokens < 0.0 { -bucket.tokens / rate } else { 0.0 }
+        };
+        if wait > 0.0 {
+            sleep(Duration::from_secs_f64(wait)).await;
+        }
+    }
+
+    /// Paces `body` so that it is read no faster than the rate.
+    pub fn throttle(&self, body: BoxStream<'static, anyhow::Result<Bytes>>) -> BoxStream<'static, anyhow::Result<Bytes>> {
+        let limiter = self.clone();
+        body.then(move |chunk| {
+            let limiter = limiter.clone();
+            async move {
+                if let Ok(bytes) = &chunk {
+                    limiter.consume(bytes.len()).await;
+                }
+                chunk
+            }
+        }).boxed()
+    }
+}
+
+impl Default for BandwidthLimiter {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!("2M".parse::<ByteRate>().unwrap(), ByteRate(2 * 1024 * 1024));
+        assert_eq!("500kb/s".parse::<ByteRate>().unwrap(), ByteRate(500 * 1024));
+        assert_eq!("1.5K".parse::<ByteRate>().unwrap(), ByteRate(1536));
+        assert_eq!("4096".parse::<ByteRate>().unwrap(), ByteRate(4096));
+        assert!("fast".parse::<ByteRate>().is_err());
+        assert!("0".parse::<ByteRate>().is_err());
+        assert_eq!(ByteRate(2 * 1024 * 1024).to_string(), "2.0 MB/s");
+    }
+
+    #[tokio::test]
+    async fn test_token_bucket() {
+        let limiter = BandwidthLimiter::new(Some(ByteRate(10_000)));
+        let started = Instant::now();
+        // One second of traffic is available at once
+        limiter.consume(10_000).await;
+        assert!(started.elapsed() < Duration::from_millis(100));
+
+        let clone = limiter.clone();
+        limiter.consume(2_500).await;
+        clone.consume(2_500).await;
+        assert!(started.elapsed() >= Duration::from_millis(500));
+
+        limiter.set_rate(None);
+        let unlimited = Instant::now();
+        clone.consume(1_000_000).await;
+        assert!(unlimited.elapsed() < Duration::from_millis(100));
+        assert_eq!(clone.rate(), None);
+    }
+}
@@ -0,0 +1,100 @@
+use std::io::{self, Write};
+use anyhow::Result;
+use flate2::write::{GzDecoder, ZlibDecoder};
+
+/// `Accept-Encoding` sent with every download, best ratio first.
+pub const ACCEPT_ENCODING: &str = "zstd, br, gzip, deflate";
+
+/// Counts the bytes going through a writer.
+pub struct CountingWriter<W: Write> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> CountingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, count: 0 }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Decodes a response body according to its `Content-Encoding` as it is written.
+pub enum ContentDecoder<W: Write> {
+    Identity(W),
+    Gzip(GzDecoder<W>),
+    Deflate(ZlibDecoder<W>),
+    Brotli(Box<brotli::DecompressorWriter<W>>),
+    Zstd(zstd::stream::write::Decoder<'static, W>),
+}
+
+impl<W: Write> ContentDecoder<W> {
+    pub fn new(content_encoding: Option<&str>, inner: W) -> Result<Self> {
+        let encoding = content_encoding.unwrap_or_default().trim().to_ascii_lowercase();
+        Ok(match encoding.as_str() {
+            "" | "identity" => ContentDecoder::Identity(inner),
+            "gzip" | "x-gzip" => ContentDecoder::Gzip(GzDecoder::new(inner)),
+            "deflate" => ContentDecoder::Deflate(ZlibDecoder::new(inner)),
+            "br" => ContentDecoder::Brotli(Box::new(brotli::DecompressorWriter::new(inner, 64 * 1024))),
+            "zstd" => ContentDecoder::Zstd(zstd::stream::write::Decoder::new(inner)?),
+            other => return Err(anyhow::anyhow!("Unsupported content encoding '{}'", other)),
+        })
+    }
+
+    /// Flushes what is left of the encoded stream and returns the inner writer.
+    pub fn finish(self) -> Result<W> {
+        Ok(match self {
+            ContentDecoder::Identity(w) => w,
+            ContentDecoder::Gzip(d) => d.finish()?,
+            ContentDecoder::Deflate(d) => d.finish()?,
+            ContentDecoder::Brotli(mut d) => {
+                d.close()?;
+                d.into_inner().map_err(|_| anyhow::anyhow!("Truncated brotli stream"))?
+            }
+            ContentDecoder::Zstd(mut d) => {
+                d.flush()?;
+                d.into_inner()
+            }
+        })
+    }
+}
+
+impl<W: Write> Write for ContentDecoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            ContentDecoder::Identity(w) => w.write(buf),
+            ContentDecoder::Gzip(w) => w.write(buf),
+            ContentDecoder::Deflate(w) => w.write(buf),
+            ContentDecoder::Brotli(w) => w.write(buf),
+            ContentDecoder::Zstd(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            ContentDecoder::Identity(w) => w.flush(),
+            ContentDecoder::Gzip(w) => w.flush(),
+            ContentDecoder::Deflate(w) => w.flush(),
+            ContentDecoder::Brotli(w) => w.flush(),
+            ContentDecoder::Zstd(w) => w.flush(),
+        }
+    }
+}
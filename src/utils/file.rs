@@ -1,4 +1,4 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use anyhow::Result;
 
 pub fn file_exists_with_size(path: &Path) -> bool {
@@ -9,6 +9,13 @@ pub fn file_exists_with_size(path: &Path) -> bool {
     }
 }
 
+/// Where a file is written before being renamed to `path` once complete.
+pub fn part_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
 pub fn ensure_dir_exists(path: &Path) -> Result<()> {
     if !path.exists() {
         std::fs::create_dir_all(path)?;
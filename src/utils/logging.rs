@@ -0,0 +1,164 @@
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use anyhow::{Context, Result};
+use indicatif::ProgressBar;
+use serde::Deserialize;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, EnvFilter, Layer};
+
+/// Progress bar being drawn, hidden while a log line is written over it.
+static PROGRESS: Mutex<Option<ProgressBar>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Hourly,
+    #[default]
+    Daily,
+    Never,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON object per line, with the fields of the event and its spans
+    Json,
+}
+
+/// `[log]` section.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// Written next to the console output, `<file>.<date>` once rotated
+    pub file: Option<PathBuf>,
+    pub rotation: LogRotation,
+    /// Rotated files kept, all of them when 0
+    pub max_files: usize,
+    /// Filter of the file, e.g. "debug" or "wago_db2_csv_downloader=trace,warn"
+    pub level: String,
+    pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            rotation: LogRotation::Daily,
+            max_files: 7,
+            level: "debug".to_string(),
+            format: LogFormat::Text,
+        }
+    }
+}
+
+/// Writes log lines to stderr without tearing the progress bar.
+struct ConsoleWriter;
+
+impl Write for ConsoleWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match PROGRESS.lock().unwrap().as_ref() {
+            Some(bar) if !bar.is_finished() => bar.suspend(|| io::stderr().write_all(buf))?,
+            _ => io::stderr().write_all(buf)?,
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+/// Shows `bar` below the log lines until it is replaced or `None` is set.
+pub fn set_progress_bar(bar: Option<ProgressBar>) {
+    *PROGRESS.lock().unwrap() = bar;
+}
+
+/// Console level for `-v`/`-q`: 0 is info, each `-v` adds a level and each `-q` removes one.
+fn console_level(verbosity: i8) -> &'static str {
+    match verbosity {
+        i8::MIN..=-2 => "error",
+        -1 => "warn",
+        0 => "info",
+        1 => "debug",
+        _ => "trace",
+    }
+}
+
+/// Logs to the console at `verbosity`, or as `RUST_LOG` says when it is set,
+/// and to the file of `config`. The returned guard flushes the file when dropped.
+pub fn init_logging(verbosity: i8, config: &LogConfig) -> Result<Option<WorkerGuard>> {
+    let console_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        EnvFilter::new(format!("warn,{}={}", env!("CARGO_CRATE_NAME"), console_level(verbosity)))
+    });
+    let console = fmt::layer()
+        .with_writer(|| ConsoleWriter)
+        .with_ansi(io::stderr().is_terminal())
+        .without_time()
+        .with_target(false)
+        .compact()
+        .with_filter(console_filter);
+
+    let (file, guard) = match &config.file {
+        Some(path) => {
+            let directory = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(std::path::Path::new("."));
+            let name = path.file_name()
+                .with_context(|| format!("Invalid log file {}", path.display()))?;
+            std::fs::create_dir_all(directory)
+                .with_context(|| format!("Unable to create {}", directory.display()))?;
+            let rotation = match config.rotation {
+                LogRotation::Hourly => Rotation::HOURLY,
+                LogRotation::Daily => Rotation::DAILY,
+                LogRotation::Never => Rotation::NEVER,
+            };
+            let mut appender = RollingFileAppender::builder()
+                .rotation(rotation)
+                .filename_prefix(name.to_string_lossy());
+            if config.max_files > 0 {
+                appender = appender.max_log_files(config.max_files);
+            }
+            let appender = appender.build(directory)
+                .with_context(|| format!("Unable to open the log file {}", path.display()))?;
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            let filter = EnvFilter::try_new(&config.level)
+                .with_context(|| format!("Invalid log level '{}'", config.level))?;
+            let layer = match config.format {
+                LogFormat::Text => fmt::layer().with_writer(writer).with_ansi(false).boxed(),
+                LogFormat::Json => fmt::layer().json().with_writer(writer).boxed(),
+            };
+            (Some(layer.with_filter(filter)), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(console)
+        .with(file)
+        .try_init()?;
+    Ok(guard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_console_level() {
+        assert_eq!(console_level(0), "info");
+        assert_eq!(console_level(2), "trace");
+        assert_eq!(console_level(-1), "warn");
+        assert_eq!(console_level(-5), "error");
+    }
+
+    #[test]
+    fn test_log_config() {
+        let config: LogConfig = toml::from_str("file = \"logs/db2.log\"\nrotation = \"hourly\"\nformat = \"json\"").unwrap();
+        assert_eq!(config.rotation, LogRotation::Hourly);
+        assert_eq!(config.format, LogFormat::Json);
+        assert_eq!(config.max_files, 7);
+    }
+}
@@ -1,5 +1,17 @@
 mod rate_limiter;
 mod file;
+mod table_file;
+mod content_encoding;
+mod bandwidth;
+mod signal;
+mod logging;
+mod time_window;
 
 pub use rate_limiter::RateLimiter;
-pub use file::{file_exists_with_size, ensure_dir_exists};
\ No newline at end of file
+pub use bandwidth::{BandwidthLimiter, ByteRate};
+pub use content_encoding::{ContentDecoder, CountingWriter, ACCEPT_ENCODING};
+pub use file::{file_exists_with_size, ensure_dir_exists, part_path};
+pub use signal::shutdown_on_signal;
+pub use logging::{init_logging, set_progress_bar, LogConfig};
+pub use time_window::{next_opening, TimeWindow};
+pub use table_file::{table_path, open_table, id_column, list_dirs, list_builds, list_tables, split_table_file, Compression, TableData, TableWriter};
\ No newline at end of file
@@ -1,41 +1,57 @@
+use std::sync::Arc;
 use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
 use tokio::time::sleep;
 
+#[derive(Debug)]
+struct LimiterState {
+    last_request: Instant,
+    requests_made: u32,
+}
+
+/// Requests-per-minute limit shared by every clone, so that concurrent tasks
+/// draw from the same budget.
 #[derive(Debug, Clone)]
 pub struct RateLimiter {
     requests_per_minute: u32,
-    last_request: Instant,
-    requests_made: u32,
+    state: Arc<Mutex<LimiterState>>,
 }
 
 impl RateLimiter {
     pub fn new(requests_per_minute: u32) -> Self {
         Self {
             requests_per_minute,
-            last_request: Instant::now(),
-            requests_made: 0,
+            state: Arc::new(Mutex::new(LimiterState {
+                last_request: Instant::now(),
+                requests_made: 0,
+            })),
         }
     }
 
-    pub async fn wait(&mut self) {
+    /// Waits for the next request slot and returns the time spent waiting.
+    pub async fn wait(&self) -> Duration {
+        let started = Instant::now();
+        let mut state = self.state.lock().await;
         let minute = Duration::from_secs(60);
         let now = Instant::now();
         
-        if now.duration_since(self.last_request) >= minute {
-            self.requests_made = 0;
-            self.last_request = now;
-        } else if self.requests_made >= self.requests_per_minute {
-            let wait_time = minute - now.duration_since(self.last_request);
-            println!(
-                "Wait {} seconds before continuing the download to bypass the blocking...", 
+        if now.duration_since(state.last_request) >= minute {
+            state.requests_made = 0;
+            state.last_request = now;
+        } else if state.requests_made >= self.requests_per_minute {
+            let wait_time = minute - now.duration_since(state.last_request);
+            tracing::info!(
+                "{} requests per minute reached, waiting {} seconds",
+                self.requests_per_minute,
                 wait_time.as_secs()
             );
             sleep(wait_time).await;
-            self.requests_made = 0;
-            self.last_request = Instant::now();
+            state.requests_made = 0;
+            state.last_request = Instant::now();
         }
 
-        self.requests_made += 1;
+        state.requests_made += 1;
+        started.elapsed()
     }
 }
 
@@ -47,13 +63,23 @@ mod tests {
     async fn test_rate_limiter_initial_state() {
         let limiter = RateLimiter::new(60);
         assert_eq!(limiter.requests_per_minute, 60);
-        assert_eq!(limiter.requests_made, 0);
+        assert_eq!(limiter.state.lock().await.requests_made, 0);
     }
 
     #[tokio::test]
     async fn test_rate_limiter_counting() {
-        let mut limiter = RateLimiter::new(60);
+        let limiter = RateLimiter::new(60);
+        limiter.wait().await;
+        assert_eq!(limiter.state.lock().await.requests_made, 1);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter_shared_by_clones() {
+        let limiter = RateLimiter::new(1);
+        let clone = limiter.clone();
         limiter.wait().await;
-        assert_eq!(limiter.requests_made, 1);
+        // The budget of the minute is spent for the clone too
+        assert!(tokio::time::timeout(Duration::from_millis(100), clone.wait()).await.is_err());
+        assert_eq!(limiter.state.lock().await.requests_made, 1);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use crate::entities::Build;
+
+const ZSTD_LEVEL: i32 = 3;
+
+/// How table files are stored on disk, told apart by their extension.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// `<table>.csv`
+    #[default]
+    None,
+    /// `<table>.csv.zst`
+    Zstd,
+    /// `<table>.csv.gz`
+    Gzip,
+}
+
+impl Compression {
+    pub const ALL: [Compression; 3] = [Compression::None, Compression::Zstd, Compression::Gzip];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Compression::None => "csv",
+            Compression::Zstd => "csv.zst",
+            Compression::Gzip => "csv.gz",
+        }
+    }
+
+    pub fn of_path(path: &Path) -> Self {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        split_table_file(&name).map(|(_, c)| c).unwrap_or_default()
+    }
+}
+
+/// Splits a table file name into its stem and compression: `Spell.csv.zst` → (`Spell`, Zstd).
+pub fn split_table_file(name: &str) -> Option<(&str, Compression)> {
+    // Longest extensions first, `.csv` is a suffix of none of them
+    [Compression::Zstd, Compression::Gzip, Compression::None].into_iter()
+        .find_map(|c| name.strip_suffix(c.extension())?.strip_suffix('.').map(|stem| (stem, c)))
+}
+
+/// Path of a downloaded table, whichever compression it was stored with.
+/// Falls back to the plain `.csv` path when the table is not on disk.
+pub fn table_path(root: &Path, build: &str, locale: &str, table: &str) -> PathBuf {
+    let dir = root.join(build).join(locale);
+    Compression::ALL.iter()
+        .map(|c| dir.join(format!("{}.{}", table, c.extension())))
+        .find(|p| p.exists())
+        .unwrap_or_else(|| dir.join(format!("{}.csv", table)))
+}
+
+pub fn open_table(path: &Path) -> Result<csv::Reader<Box<dyn Read>>> {
+    let file = File::open(path)
+        .with_context(|| format!("Unable to open {}", path.display()))?;
+    let reader: Box<dyn Read> = match Compression::of_path(path) {
+        Compression::None => Box::new(file),
+        Compression::Zstd => Box::new(zstd::Decoder::new(file)?),
+        Compression::Gzip => Box::new(MultiGzDecoder::new(BufReader::new(file))),
+    };
+    Ok(csv::ReaderBuilder::new()
+        .has_headers(true)
+        .flexible(true)
+        .from_reader(reader))
+}
+
+/// Writes a table file, compressing it on the fly. `finish` must be called
+/// once done so compressed files are complete.
+pub enum TableWriter {
+    Plain(BufWriter<File>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl TableWriter {
+    pub fn create(path: &Path, compression: Compression) -> Result<Self> {
+        let file = BufWriter::new(File::create(path)
+            .with_context(|| format!("Unable to write {}", path.display()))?);
+        Ok(match compression {
+            Compression::None => TableWriter::Plain(file),
+            Compression::Zstd => TableWriter::Zstd(zstd::Encoder::new(file, ZSTD_LEVEL)?),
+            Compression::Gzip => TableWriter::Gzip(GzEncoder::new(file, flate2::Compression::default())),
+        })
+    }
+
+    pub fn finish(self) -> Result<()> {
+        let mut file = match self {
+            TableWriter::Plain(file) => file,
+            TableWriter::Zstd(encoder) => encoder.finish()?,
+            TableWriter::Gzip(encoder) => encoder.finish()?,
+        };
+        file.flush()?;
+        Ok(())
+    }
+}
+
+impl Write for TableWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            TableWriter::Plain(w) => w.write(buf),
+            TableWriter::Zstd(w) => w.write(buf),
+            TableWriter::Gzip(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            TableWriter::Plain(w) => w.flush(),
+            TableWriter::Zstd(w) => w.flush(),
+            TableWriter::Gzip(w) => w.flush(),
+        }
+    }
+}
+
+/// Index of the column used as row key: `ID` when present, the first column otherwise.
+pub fn id_column(headers: &[String]) -> usize {
+    headers.iter()
+        .position(|h| h.eq_ignore_ascii_case("ID"))
+        .unwrap_or(0)
+}
+
+#[derive(Debug, Clone)]
+pub struct TableData {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    pub ids: HashMap<String, usize>,
+}
+
+impl TableData {
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut reader = open_table(path)?;
+        let headers: Vec<String> = reader.headers()?.iter().map(String::from).collect();
+        let id_index = id_column(&headers);
+
+        let mut rows = Vec::new();
+        let mut ids = HashMap::new();
+        for record in reader.records() {
+            let record = record
+                .with_context(|| format!("Malformed row in {}", path.display()))?;
+            let row: Vec<String> = record.iter().map(String::from).collect();
+            if let Some(id) = row.get(id_index) {
+                ids.insert(id.clone(), rows.len());
+            }
+            rows.push(row);
+        }
+
+        Ok(Self { headers, rows, ids })
+    }
+
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.headers.iter().position(|h| h == name)
+    }
+
+    pub fn row(&self, id: &str) -> Option<&Vec<String>> {
+        self.ids.get(id).map(|&i| &self.rows[i])
+    }
+
+    /// Row indexes grouped by the values of `column`.
+    pub fn index(&self, column: usize) -> HashMap<&str, Vec<usize>> {
+        let mut index: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (i, row) in self.rows.iter().enumerate() {
+            if let Some(value) = row.get(column) {
+                index.entry(value.as_str()).or_default().push(i);
+            }
+        }
+        index
+    }
+}
+
+/// Visible sub-directories of `path`, sorted by name. A missing directory yields an empty list.
+pub fn list_dirs(path: &Path) -> Result<Vec<String>> {
+    if !path.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // Hidden directories hold the tool's own data, e.g. search indexes
+        if entry.file_type()?.is_dir() && !name.starts_with('.') {
+            names.push(name);
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Builds downloaded under `root`, oldest first.
+pub fn list_builds(root: &Path) -> Result<Vec<Build>> {
+    let mut builds: Vec<Build> = list_dirs(root)?
+        .into_iter()
+        .filter_map(|name| name.parse().ok())
+        .collect();
+    builds.sort();
+    Ok(builds)
+}
+
+/// Names of the tables downloaded in a `<build>/<locale>` directory, sorted.
+pub fn list_tables(locale_dir: &Path) -> Result<Vec<String>> {
+    if !locale_dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(locale_dir)? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        // Derived files such as `Spell.filtered.csv` are not tables
+        if let Some((table, _)) = split_table_file(&name).filter(|(t, _)| !t.contains('.')) {
+            names.push(table.to_string());
+        }
+    }
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
@@ -0,0 +1,104 @@
+use std::time::Duration;
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+
+/// Daily range of local time, written `22:00-06:00`. The start is included,
+/// the end is not, and a range ending before it starts spans midnight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl TimeWindow {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start < self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+impl std::str::FromStr for TimeWindow {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || anyhow::anyhow!("Invalid time window '{}', expected e.g. 22:00-06:00", s);
+        let (start, end) = s.split_once('-').ok_or_else(invalid)?;
+        let parse = |time: &str| NaiveTime::parse_from_str(time.trim(), "%H:%M").map_err(|_| invalid());
+        let window = TimeWindow { start: parse(start)?, end: parse(end)? };
+        if window.start == window.end {
+            return Err(invalid());
+        }
+        Ok(window)
+    }
+}
+
+impl std::fmt::Display for TimeWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.start.format("%H:%M"), self.end.format("%H:%M"))
+    }
+}
+
+impl TryFrom<String> for TimeWindow {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<TimeWindow> for String {
+    fn from(window: TimeWindow) -> Self {
+        window.to_string()
+    }
+}
+
+/// When none of `windows` contains `now`, the start of the next one and the
+/// time until then. Empty `windows` are always open.
+pub fn next_opening(windows: &[TimeWindow], now: NaiveTime) -> Option<(NaiveTime, Duration)> {
+    if windows.is_empty() || windows.iter().any(|w| w.contains(now)) {
+        return None;
+    }
+    windows.iter()
+        .map(|w| {
+            let wait = (w.start - now).num_seconds().rem_euclid(24 * 3600);
+            (w.start, Duration::from_secs(wait as u64))
+        })
+        .min_by_key(|(_, wait)| *wait)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(s: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(s, "%H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_parse_window() {
+        let window: TimeWindow = "22:00-06:00".parse().unwrap();
+        assert_eq!(window.to_string(), "22:00-06:00");
+        assert!(window.contains(time("23:30")));
+        assert!(window.contains(time("00:00")));
+        assert!(!window.contains(time("06:00")));
+        assert!(!window.contains(time("12:00")));
+        assert!("13:00-14:00".parse::<TimeWindow>().unwrap().contains(time("13:59")));
+        assert!("22:00".parse::<TimeWindow>().is_err());
+        assert!("25:00-06:00".parse::<TimeWindow>().is_err());
+        assert!("06:00-06:00".parse::<TimeWindow>().is_err());
+    }
+
+    #[test]
+    fn test_next_opening() {
+        let windows = vec!["22:00-06:00".parse().unwrap(), "12:00-13:00".parse().unwrap()];
+        assert_eq!(next_opening(&windows, time("23:00")), None);
+        assert_eq!(next_opening(&windows, time("12:30")), None);
+        assert_eq!(next_opening(&windows, time("07:00")), Some((time("12:00"), Duration::from_secs(5 * 3600))));
+        assert_eq!(next_opening(&windows, time("21:30")), Some((time("22:00"), Duration::from_secs(1800))));
+        assert_eq!(next_opening(&[], time("07:00")), None);
+    }
+}